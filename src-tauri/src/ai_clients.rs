@@ -1,26 +1,241 @@
+use crate::breaker::{BreakerStatus, CircuitBreaker};
+use crate::claude::ClaudeClient;
+use crate::config_file::{config_file_path, read_json, stage_json, StagedFile};
+use crate::http_client::build_client;
+use crate::rate_limit::RateLimiter;
+use crate::redact::{redact_secrets, redact_with};
+use crate::text_util::{document_block, truncate_chars, UNTRUSTED_CONTENT_GUARD};
+use crate::vault::{self, NewPassphrase};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
 use tokio::sync::RwLock;
 
 const OPENAI_API_URL: &str = "https://api.openai.com/v1/chat/completions";
-const GEMINI_API_URL: &str = "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent";
+const OPENAI_MODELS_URL: &str = "https://api.openai.com/v1/models";
+const GEMINI_MODELS_URL: &str = "https://generativelanguage.googleapis.com/v1beta/models";
+const DEFAULT_OPENAI_MODEL: &str = "gpt-4o-mini";
+const DEFAULT_GEMINI_MODEL: &str = "gemini-2.0-flash";
+/// Models a single request may switch to; all accept the chat parameters sent here
+const OPENAI_MODELS: [ModelSpec; 5] = [
+    ModelSpec::new("gpt-4o-mini", 16384),
+    ModelSpec::new("gpt-4o", 16384),
+    ModelSpec::new("gpt-4.1", 32768),
+    ModelSpec::new("gpt-4.1-mini", 32768),
+    ModelSpec::new("gpt-4.1-nano", 32768),
+];
+const GEMINI_MODELS: [ModelSpec; 4] = [
+    ModelSpec::new("gemini-2.0-flash", 8192),
+    ModelSpec::new("gemini-2.0-flash-lite", 8192),
+    ModelSpec::new("gemini-2.5-flash", 65536),
+    ModelSpec::new("gemini-2.5-pro", 65536),
+];
 const AI_CONFIG_FILE_NAME: &str = "ainotework_ai_config.json";
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 15;
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_OPENAI_MAX_TOKENS: u32 = 4096;
+/// Highest output ceiling among each provider's models; a request is capped at its own model's
+const OPENAI_MAX_OUTPUT_CEILING: u32 = 32768;
+const GEMINI_MAX_OUTPUT_CEILING: u32 = 65536;
+/// Fixed OpenAI and Gemini `seed` while deterministic mode is on
+const DETERMINISTIC_SEED: i64 = 42;
 
 #[derive(Serialize, Deserialize, Default, Clone)]
 pub struct AiConfig {
     pub openai_api_key: Option<String>,
     pub gemini_api_key: Option<String>,
+    pub connect_timeout_secs: Option<u64>,
+    pub request_timeout_secs: Option<u64>,
+    pub openai_max_output_tokens: Option<u32>,
+    pub gemini_max_output_tokens: Option<u32>,
+    /// Providers to try in order when one fails with a retryable error; empty or unset disables fallback
+    pub provider_fallback: Option<Vec<String>>,
+    /// Requests per minute; 0 or unset means no limit
+    pub openai_rate_limit_rpm: Option<u32>,
+    pub gemini_rate_limit_rpm: Option<u32>,
+    /// Model `distill_content` uses, by provider, where it isn't `default_distill_model`
+    pub distill_models: Option<HashMap<String, String>>,
+}
+
+/// Per-request overrides shared by all providers; `None` keeps the provider default
+#[derive(Clone, Debug, Default)]
+pub struct RequestOptions {
+    pub temperature: Option<f32>,
+    /// Claude only: extended thinking budget in tokens; other providers ignore it
+    pub thinking_budget: Option<u32>,
+    /// Model for this request only; must be in the provider's allow-list
+    pub model: Option<String>,
+    /// The document came from an arbitrary URL or page; Q&A prompts add `UNTRUSTED_CONTENT_GUARD`
+    pub untrusted_content: bool,
+}
+
+/// A failed provider request. `retryable` marks failures another attempt, or another provider,
+/// may get past: rate limits, overload, server errors, timeouts, network problems and an open
+/// circuit breaker. Converts into the plain `String` errors the commands return.
+#[derive(Debug)]
+pub struct ProviderError {
+    pub message: String,
+    pub retryable: bool,
+    /// Text received before the reply was cut off at the output token limit, so it isn't
+    /// mistaken for a complete answer but isn't thrown away either
+    pub partial: Option<String>,
+}
+
+impl ProviderError {
+    pub fn retryable(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            retryable: true,
+            partial: None,
+        }
+    }
+
+    /// An error response, retryable for 429 and 5xx (Anthropic's 529 "overloaded" included)
+    pub fn from_status(status: reqwest::StatusCode, message: String) -> Self {
+        Self {
+            message,
+            retryable: status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+            partial: None,
+        }
+    }
+
+    /// `provider`'s reply stopped at the output token limit after `partial`
+    pub fn truncated(provider: &str, partial: String) -> Self {
+        Self {
+            message: format!(
+                "{}'s reply was cut off at the output token limit. Raise the output token limit or split the content and try again.",
+                provider
+            ),
+            retryable: false,
+            partial: Some(partial),
+        }
+    }
+}
+
+impl std::fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<String> for ProviderError {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            retryable: false,
+            partial: None,
+        }
+    }
+}
+
+impl From<&str> for ProviderError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+/// A cut-off reply keeps its partial text after the error message
+impl From<ProviderError> for String {
+    fn from(error: ProviderError) -> Self {
+        match error.partial.filter(|p| !p.trim().is_empty()) {
+            Some(partial) => format!("{}\n\nPartial output:\n{}", error.message, partial),
+            None => error.message,
+        }
+    }
+}
+
+/// A model a request may switch to, with the limits that differ between models
+pub(crate) struct ModelSpec {
+    pub id: &'static str,
+    /// Most tokens one response may have, thinking included
+    pub max_output: u32,
+    /// Accepts extended thinking (Claude only)
+    pub thinking: bool,
+}
+
+impl ModelSpec {
+    pub(crate) const fn new(id: &'static str, max_output: u32) -> Self {
+        Self {
+            id,
+            max_output,
+            thinking: false,
+        }
+    }
+
+    pub(crate) const fn with_thinking(self) -> Self {
+        Self { thinking: true, ..self }
+    }
+}
+
+/// The requested model if the provider allows it, otherwise the provider default
+pub(crate) fn resolve_model<'a>(
+    provider: &str,
+    requested: Option<&str>,
+    allowed: &'a [ModelSpec],
+    default: &str,
+) -> Result<&'a ModelSpec, String> {
+    let id = requested.unwrap_or(default);
+    allowed.iter().find(|spec| spec.id == id).ok_or_else(|| {
+        let ids: Vec<&str> = allowed.iter().map(|spec| spec.id).collect();
+        format!("Model '{}' is not available for {} (allowed: {})", id, provider, ids.join(", "))
+    })
 }
 
 // OpenAI Types
 #[derive(Serialize)]
 struct OpenAiRequest {
     model: String,
-    messages: Vec<OpenAiMessage>,
+    messages: Vec<OpenAiRequestMessage>,
     max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<OpenAiToolSpec>>,
+}
+
+/// A function the model may ask to call; `parameters` is a JSON Schema object
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OpenAiFunction {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub parameters: serde_json::Value,
+}
+
+/// Wire form of a tool: only function tools exist in chat completions
+#[derive(Serialize)]
+struct OpenAiToolSpec {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: OpenAiFunction,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OpenAiToolCall {
+    pub id: String,
+    pub function: OpenAiFunctionCall,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OpenAiFunctionCall {
+    pub name: String,
+    /// JSON-encoded arguments as produced by the model; not guaranteed to be valid
+    pub arguments: String,
+}
+
+/// Answer to a request with tools: either text or the calls the model wants made
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum OpenAiToolReply {
+    Text(String),
+    ToolCalls(Vec<OpenAiToolCall>),
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -29,14 +244,64 @@ pub struct OpenAiMessage {
     pub content: String,
 }
 
+/// Wire form of a message; content is plain text or parts (needed for images)
+#[derive(Serialize)]
+struct OpenAiRequestMessage {
+    role: String,
+    content: OpenAiContent,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum OpenAiContent {
+    Text(String),
+    Parts(Vec<OpenAiContentPart>),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OpenAiContentPart {
+    Text { text: String },
+    ImageUrl { image_url: OpenAiImageUrl },
+}
+
+#[derive(Serialize)]
+struct OpenAiImageUrl {
+    url: String,
+}
+
+impl From<OpenAiMessage> for OpenAiRequestMessage {
+    fn from(message: OpenAiMessage) -> Self {
+        Self {
+            role: message.role,
+            content: OpenAiContent::Text(message.content),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct OpenAiResponse {
     choices: Vec<OpenAiChoice>,
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiUsage {
+    completion_tokens: u64,
 }
 
 #[derive(Deserialize)]
 struct OpenAiChoice {
-    message: OpenAiMessage,
+    message: OpenAiResponseMessage,
+    finish_reason: Option<String>,
+}
+
+/// Content is null when the model answers with tool calls instead
+#[derive(Deserialize)]
+struct OpenAiResponseMessage {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<OpenAiToolCall>,
 }
 
 #[derive(Deserialize)]
@@ -49,10 +314,61 @@ struct OpenAiErrorDetail {
     message: String,
 }
 
+/// One `data:` event of a streamed chat completion
+#[derive(Deserialize)]
+struct OpenAiStreamChunk {
+    #[serde(default)]
+    choices: Vec<OpenAiStreamChoice>,
+    error: Option<OpenAiErrorDetail>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiStreamChoice {
+    #[serde(default)]
+    delta: OpenAiDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct OpenAiDelta {
+    content: Option<String>,
+}
+
 // Gemini Types
 #[derive(Serialize)]
 struct GeminiRequest {
     contents: Vec<GeminiContent>,
+    #[serde(rename = "generationConfig", skip_serializing_if = "Option::is_none")]
+    generation_config: Option<GeminiGenerationConfig>,
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct GeminiGenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+}
+
+impl GeminiGenerationConfig {
+    /// None when nothing is overridden, so the request shape stays unchanged
+    fn from_options(
+        options: &RequestOptions,
+        max_output_tokens: Option<u32>,
+        seed: Option<i64>,
+    ) -> Option<Self> {
+        if options.temperature.is_none() && max_output_tokens.is_none() && seed.is_none() {
+            return None;
+        }
+        Some(Self {
+            temperature: options.temperature,
+            max_output_tokens,
+            seed,
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -65,19 +381,48 @@ struct GeminiContent {
 
 #[derive(Serialize, Deserialize, Clone, Default)]
 struct GeminiPart {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     text: String,
+    #[serde(rename = "inlineData", skip_serializing_if = "Option::is_none")]
+    inline_data: Option<GeminiInlineData>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct GeminiInlineData {
+    #[serde(rename = "mimeType")]
+    mime_type: String,
+    data: String,
 }
 
 #[derive(Deserialize)]
 struct GeminiResponse {
     candidates: Option<Vec<GeminiCandidate>>,
     error: Option<GeminiError>,
+    #[serde(rename = "promptFeedback")]
+    prompt_feedback: Option<GeminiPromptFeedback>,
+    #[serde(rename = "usageMetadata")]
+    usage_metadata: Option<GeminiUsage>,
+}
+
+#[derive(Deserialize)]
+struct GeminiUsage {
+    #[serde(rename = "candidatesTokenCount", default)]
+    candidates_token_count: u64,
 }
 
+/// Blocked or truncated candidates come back without content
 #[derive(Deserialize, Clone)]
 struct GeminiCandidate {
-    content: GeminiContent,
+    #[serde(default)]
+    content: Option<GeminiContent>,
+    #[serde(rename = "finishReason")]
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GeminiPromptFeedback {
+    #[serde(rename = "blockReason")]
+    block_reason: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -85,55 +430,564 @@ struct GeminiError {
     message: String,
 }
 
+/// Both OpenAI and Gemini reject inline images above roughly 20 MB
+const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+/// Gemini's 20 MB inline limit counts the whole request: the base64 data plus the prompt
+const GEMINI_MAX_INLINE_REQUEST_BYTES: usize = 20 * 1024 * 1024;
+const SUPPORTED_IMAGE_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/webp", "image/gif"];
+
+/// A base64 image validated against the providers' type and size limits
+pub struct ImageInput {
+    mime_type: String,
+    data: String,
+    /// Decoded size in bytes
+    size: usize,
+}
+
+impl ImageInput {
+    /// Accepts a data URL (`data:image/png;base64,...`) or bare base64 with `mime_type`
+    pub fn from_data(image_data: &str, mime_type: Option<&str>) -> Result<Self, String> {
+        use base64::Engine;
+
+        let (url_mime, data) = match image_data.trim().strip_prefix("data:") {
+            Some(rest) => {
+                let (header, data) = rest.split_once(',').ok_or("Invalid image data URL")?;
+                (header.split(';').next().map(|m| m.to_string()), data)
+            }
+            None => (None, image_data.trim()),
+        };
+
+        let mime_type = url_mime
+            .or_else(|| mime_type.map(|m| m.to_string()))
+            .ok_or("Image type is required (e.g. image/png)")?
+            .to_lowercase();
+        let mime_type = if mime_type == "image/jpg" { "image/jpeg".to_string() } else { mime_type };
+        if !SUPPORTED_IMAGE_TYPES.contains(&mime_type.as_str()) {
+            return Err(format!(
+                "Unsupported image type: {} (supported: {})",
+                mime_type,
+                SUPPORTED_IMAGE_TYPES.join(", ")
+            ));
+        }
+
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .map_err(|e| format!("Failed to decode image: {}", e))?;
+        if bytes.len() > MAX_IMAGE_BYTES {
+            return Err(format!(
+                "Image too large ({:.1} MB), limit is {} MB",
+                bytes.len() as f64 / (1024.0 * 1024.0),
+                MAX_IMAGE_BYTES / (1024 * 1024)
+            ));
+        }
+
+        Ok(Self {
+            mime_type,
+            data: data.to_string(),
+            size: bytes.len(),
+        })
+    }
+
+    /// Size of the image once base64 encoded in a request
+    fn encoded_len(&self) -> usize {
+        4 * self.size.div_ceil(3)
+    }
+}
+
+/// An answer with the output tokens the provider reported for it, when it reported any
+pub struct MeteredAnswer {
+    pub text: String,
+    pub output_tokens: Option<u64>,
+}
+
+/// Error for a failed OpenAI request: the API's message when the body has one, else the body,
+/// with `api_key` and anything else that looks like a key masked
+fn openai_error_message(status: reqwest::StatusCode, body: &str, api_key: &str) -> String {
+    match serde_json::from_str::<OpenAiError>(body) {
+        Ok(error) => redact_with(&format!("OpenAI error: {}", error.error.message), api_key),
+        Err(_) => redact_with(&format!("OpenAI error ({}): {}", status, body), api_key),
+    }
+}
+
+/// Error for a failed Gemini request, with keys masked as in `openai_error_message`
+fn gemini_error_message(status: reqwest::StatusCode, body: &str, api_key: &str) -> String {
+    redact_with(&format!("Gemini error ({}): {}", status, body), api_key)
+}
+
+/// Reply message and completion tokens from a successful OpenAI chat completion body
+fn parse_openai_response(body: &str) -> Result<(OpenAiResponseMessage, Option<u64>), ProviderError> {
+    let response: OpenAiResponse =
+        serde_json::from_str(body).map_err(|e| format!("Failed to parse response: {}", e))?;
+
+    let output_tokens = response.usage.map(|u| u.completion_tokens);
+    let choice = response
+        .choices
+        .into_iter()
+        .next()
+        .ok_or("Empty response from OpenAI")?;
+    if choice.finish_reason.as_deref() == Some("length") {
+        return Err(ProviderError::truncated("OpenAI", choice.message.content.unwrap_or_default()));
+    }
+    Ok((choice.message, output_tokens))
+}
+
+/// Tool calls when the model asked for any, otherwise its text
+fn tool_reply(message: OpenAiResponseMessage) -> Result<OpenAiToolReply, String> {
+    if !message.tool_calls.is_empty() {
+        return Ok(OpenAiToolReply::ToolCalls(message.tool_calls));
+    }
+    message
+        .content
+        .filter(|c| !c.is_empty())
+        .map(OpenAiToolReply::Text)
+        .ok_or_else(|| "Empty response from OpenAI".to_string())
+}
+
+/// Answer text and usage from a successful Gemini response body; `api_key` is redacted from errors
+fn parse_gemini_response(body: &str, api_key: &str) -> Result<MeteredAnswer, ProviderError> {
+    let response: GeminiResponse =
+        serde_json::from_str(body).map_err(|e| redact_with(&format!("Failed to parse response: {} - Body: {}", e, truncate_chars(body, 500)), api_key))?;
+
+    if let Some(error) = response.error {
+        return Err(redact_secrets(&format!("Gemini error: {}", error.message)).into());
+    }
+
+    if let Some(reason) = response.prompt_feedback.and_then(|f| f.block_reason) {
+        return Err(format!("Gemini blocked the prompt ({})", reason).into());
+    }
+
+    let output_tokens = response.usage_metadata.map(|u| u.candidates_token_count);
+    if let Some(candidate) = response.candidates.and_then(|c| c.into_iter().next()) {
+        let text: String = candidate
+            .content
+            .map(|c| c.parts.into_iter().map(|p| p.text).collect())
+            .unwrap_or_default();
+        if candidate.finish_reason.as_deref() == Some("MAX_TOKENS") {
+            return Err(ProviderError::truncated("Gemini", text));
+        }
+        if !text.is_empty() {
+            return Ok(MeteredAnswer { text, output_tokens });
+        }
+        if let Some(reason) = candidate.finish_reason.filter(|r| r != "STOP") {
+            return Err(format!("Gemini returned no text (finish reason: {})", reason).into());
+        }
+    }
+
+    Err(redact_with(&format!("Empty response from Gemini. Raw: {}", truncate_chars(body, 500)), api_key).into())
+}
+
+/// Guard line placed ahead of an untrusted document, empty otherwise
+pub(crate) fn content_guard(untrusted: bool) -> String {
+    if untrusted {
+        format!("{}\n\n", UNTRUSTED_CONTENT_GUARD)
+    } else {
+        String::new()
+    }
+}
+
+/// Document Q&A prompt shared by the OpenAI and Gemini paths
+fn question_prompt(content: &str, question: &str, untrusted: bool) -> String {
+    format!(
+        "당신은 해당 분야의 전문가입니다. <document> 태그 안의 문서를 바탕으로 질문에 전문적이고 상세하게 답변해주세요.\n\n\
+        ## 답변 가이드라인:\n\
+        - 전문 용어가 있다면 쉽게 설명해주세요\n\
+        - 관련 배경 지식도 함께 제공해주세요\n\
+        - 실용적인 예시나 활용 방안이 있다면 포함해주세요\n\
+        - 논리적인 구조로 답변을 구성해주세요\n\n\
+        ## 질문:\n{}\n\n\
+        {}## 참고 문서:\n{}\n\n\
+        반드시 한글로 상세하게 답변해주세요.",
+        question,
+        content_guard(untrusted),
+        document_block(content)
+    )
+}
+
+fn image_question_prompt(question: &str) -> String {
+    format!(
+        "첨부된 이미지를 바탕으로 다음 질문에 상세하게 답변해주세요. 반드시 한글로 답변해주세요.\n\n## 질문:\n{}",
+        question
+    )
+}
+
+/// Incremental server-sent events parser.
+/// Network chunks can split lines and multi-byte characters anywhere, so bytes are buffered until a full line arrives.
+#[derive(Default)]
+struct SseDecoder {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    /// Feed received bytes; returns the data of every event they completed
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            if let Some(event) = self.line(&String::from_utf8_lossy(&line)) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Event still open when the body ended without a trailing blank line
+    fn finish(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.buffer);
+        if let Some(event) = self.line(&String::from_utf8_lossy(&rest)) {
+            return Some(event);
+        }
+        self.line("")
+    }
+
+    fn line(&mut self, line: &str) -> Option<String> {
+        let line = line.trim_end_matches(['\n', '\r']);
+        if line.is_empty() {
+            // Blank line ends the event; multiple data lines are joined with newlines
+            if self.data.is_empty() {
+                return None;
+            }
+            let event = self.data.join("\n");
+            self.data.clear();
+            return Some(event);
+        }
+        // event:, id:, retry: and comments aren't used by either provider
+        if let Some(value) = line.strip_prefix("data:") {
+            self.data.push(value.strip_prefix(' ').unwrap_or(value).to_string());
+        }
+        None
+    }
+}
+
 pub struct AiClients {
-    client: Client,
+    client: Arc<RwLock<Client>>,
     openai_api_key: Arc<RwLock<Option<String>>>,
     gemini_api_key: Arc<RwLock<Option<String>>>,
+    capture_raw: AtomicBool,
+    last_raw_responses: Arc<RwLock<HashMap<String, String>>>,
+    openai_max_output_tokens: AtomicU32,
+    /// 0 leaves Gemini's own default in place
+    gemini_max_output_tokens: AtomicU32,
+    deterministic: AtomicBool,
+    openai_breaker: CircuitBreaker,
+    gemini_breaker: CircuitBreaker,
+    openai_limiter: RateLimiter,
+    gemini_limiter: RateLimiter,
 }
 
 impl AiClients {
     pub fn new() -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(60))  // 60 second timeout
-            .connect_timeout(Duration::from_secs(15))
-            .build()
-            .unwrap_or_else(|_| Client::new());
+        let config = Self::load_config();
+        let client = build_client(
+            config.connect_timeout_secs.unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS),
+            config.request_timeout_secs.unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS),
+        );
 
         Self {
-            client,
+            client: Arc::new(RwLock::new(client)),
             openai_api_key: Arc::new(RwLock::new(None)),
             gemini_api_key: Arc::new(RwLock::new(None)),
+            capture_raw: AtomicBool::new(false),
+            last_raw_responses: Arc::new(RwLock::new(HashMap::new())),
+            openai_max_output_tokens: AtomicU32::new(
+                config
+                    .openai_max_output_tokens
+                    .unwrap_or(DEFAULT_OPENAI_MAX_TOKENS)
+                    .clamp(1, OPENAI_MAX_OUTPUT_CEILING),
+            ),
+            gemini_max_output_tokens: AtomicU32::new(
+                config
+                    .gemini_max_output_tokens
+                    .unwrap_or(0)
+                    .min(GEMINI_MAX_OUTPUT_CEILING),
+            ),
+            deterministic: AtomicBool::new(ClaudeClient::load_deterministic_mode()),
+            openai_breaker: CircuitBreaker::new("OpenAI"),
+            gemini_breaker: CircuitBreaker::new("Gemini"),
+            openai_limiter: RateLimiter::new(config.openai_rate_limit_rpm.unwrap_or(0)),
+            gemini_limiter: RateLimiter::new(config.gemini_rate_limit_rpm.unwrap_or(0)),
         }
     }
 
-    fn get_config_path() -> Option<PathBuf> {
-        dirs::config_dir().map(|p| p.join(AI_CONFIG_FILE_NAME))
+    pub(crate) fn get_config_path() -> Option<PathBuf> {
+        config_file_path(AI_CONFIG_FILE_NAME)
+    }
+
+    fn try_load_config() -> Result<AiConfig, String> {
+        let path = Self::get_config_path().ok_or("Could not determine config directory")?;
+        read_json(&path)
     }
 
+    /// Defaults when the file can't be read; updates go through `try_load_config` so a bad file is never overwritten
     fn load_config() -> AiConfig {
-        if let Some(path) = Self::get_config_path() {
-            if let Ok(content) = std::fs::read_to_string(&path) {
-                if let Ok(config) = serde_json::from_str(&content) {
-                    return config;
-                }
+        Self::try_load_config().unwrap_or_default()
+    }
+
+    /// Secrets are encrypted on the way out when a master passphrase is set
+    pub(crate) fn save_config(config: &AiConfig) -> Result<(), String> {
+        Self::stage_config(config)?.commit()
+    }
+
+    /// `save_config` without moving the file into place, for writes that go together
+    fn stage_config(config: &AiConfig) -> Result<StagedFile, String> {
+        let path = Self::get_config_path().ok_or("Could not determine config directory")?;
+        let mut config = config.clone();
+        config.openai_api_key = vault::seal(config.openai_api_key)?;
+        config.gemini_api_key = vault::seal(config.gemini_api_key)?;
+        stage_json(&path, &config)
+    }
+
+    /// `save_config` for a passphrase change: secrets are sealed under the new key and the file
+    /// is only staged, to be committed together with the vault and the other configs
+    pub(crate) fn stage_config_resealed(
+        config: &AiConfig,
+        passphrase: &NewPassphrase,
+    ) -> Result<StagedFile, String> {
+        let path = Self::get_config_path().ok_or("Could not determine config directory")?;
+        let mut config = config.clone();
+        config.openai_api_key = passphrase.seal(config.openai_api_key)?;
+        config.gemini_api_key = passphrase.seal(config.gemini_api_key)?;
+        stage_json(&path, &config)
+    }
+
+    /// Saved config with secrets decrypted, for re-encrypting under a new passphrase
+    pub(crate) fn load_config_revealed() -> Result<AiConfig, String> {
+        let mut config = Self::try_load_config()?;
+        config.openai_api_key = vault::reveal(config.openai_api_key)?;
+        config.gemini_api_key = vault::reveal(config.gemini_api_key)?;
+        Ok(config)
+    }
+
+    /// Saved settings for export; API keys are left out unless `include_secrets`, and stay
+    /// encrypted when a master passphrase is set
+    pub(crate) fn export_settings(include_secrets: bool) -> Result<AiConfig, String> {
+        let mut config = Self::try_load_config()?;
+        if !include_secrets {
+            config.openai_api_key = None;
+            config.gemini_api_key = None;
+        }
+        Ok(config)
+    }
+
+    /// Exported settings merged into the saved ones, staged for `commit_all`; values missing
+    /// from the import are kept. See `vault::merge_imported` for `replacing_vault`.
+    pub(crate) fn stage_import(incoming: AiConfig, replacing_vault: bool) -> Result<StagedFile, String> {
+        let mut config = Self::try_load_config()?;
+        config.openai_api_key =
+            vault::merge_imported(incoming.openai_api_key, config.openai_api_key, replacing_vault)?;
+        config.gemini_api_key =
+            vault::merge_imported(incoming.gemini_api_key, config.gemini_api_key, replacing_vault)?;
+        config.connect_timeout_secs = incoming.connect_timeout_secs.or(config.connect_timeout_secs);
+        config.request_timeout_secs = incoming.request_timeout_secs.or(config.request_timeout_secs);
+        config.openai_max_output_tokens = incoming.openai_max_output_tokens.or(config.openai_max_output_tokens);
+        config.gemini_max_output_tokens = incoming.gemini_max_output_tokens.or(config.gemini_max_output_tokens);
+        config.provider_fallback = incoming.provider_fallback.or(config.provider_fallback);
+        config.openai_rate_limit_rpm = incoming.openai_rate_limit_rpm.or(config.openai_rate_limit_rpm);
+        config.gemini_rate_limit_rpm = incoming.gemini_rate_limit_rpm.or(config.gemini_rate_limit_rpm);
+        config.distill_models = incoming.distill_models.or(config.distill_models);
+        Self::stage_config(&config)
+    }
+
+    /// Temperature 0 and a fixed seed for both providers. Only applied in memory: the setting
+    /// is saved once, with Claude's, and read back from there on startup.
+    pub fn apply_deterministic_mode(&self, enabled: bool) {
+        self.deterministic.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Fallback order of providers; empty when fallback is off
+    pub fn load_provider_fallback() -> Vec<String> {
+        Self::load_config().provider_fallback.unwrap_or_default()
+    }
+
+    pub fn set_provider_fallback(chain: Vec<String>) -> Result<(), String> {
+        let mut cleaned: Vec<String> = Vec::new();
+        for provider in chain {
+            let provider = provider.trim().to_lowercase();
+            if !matches!(provider.as_str(), "claude" | "openai" | "gemini") {
+                return Err(format!("Unknown model: {}", provider));
+            }
+            if !cleaned.contains(&provider) {
+                cleaned.push(provider);
             }
         }
-        AiConfig::default()
+
+        let mut config = Self::try_load_config()?;
+        config.provider_fallback = Some(cleaned);
+        Self::save_config(&config)
     }
 
-    fn save_config(config: &AiConfig) -> Result<(), String> {
-        let path = Self::get_config_path().ok_or("Could not determine config directory")?;
-        let content = serde_json::to_string_pretty(config)
-            .map_err(|e| format!("Failed to serialize config: {}", e))?;
-        std::fs::write(&path, content)
-            .map_err(|e| format!("Failed to write config file: {}", e))?;
-        Ok(())
+    /// Cheapest model of each provider, used for preprocessing that doesn't need a strong model
+    fn default_distill_model(provider: &str) -> Result<&'static str, String> {
+        match provider {
+            "claude" => Ok("claude-3-5-haiku-20241022"),
+            "openai" => Ok("gpt-4.1-nano"),
+            "gemini" => Ok("gemini-2.0-flash-lite"),
+            _ => Err(format!("Unknown model: {}", provider)),
+        }
+    }
+
+    /// Model `distill_content` uses for `provider`: the configured one, else the cheapest
+    pub fn distill_model(provider: &str) -> Result<String, String> {
+        let default = Self::default_distill_model(provider)?;
+        Ok(Self::load_config()
+            .distill_models
+            .and_then(|mut models| models.remove(provider))
+            .unwrap_or_else(|| default.to_string()))
+    }
+
+    /// Model `distill_content` uses for `provider`; `None` or an empty name restores the default
+    pub fn set_distill_model(provider: &str, model: Option<String>) -> Result<(), String> {
+        Self::default_distill_model(provider)?;
+        let model = model.map(|m| m.trim().to_string()).filter(|m| !m.is_empty());
+        if let Some(model) = &model {
+            let allowed = match provider {
+                "claude" => ClaudeClient::available_models(),
+                "openai" => OPENAI_MODELS.iter().map(|m| m.id.to_string()).collect(),
+                _ => GEMINI_MODELS.iter().map(|m| m.id.to_string()).collect(),
+            };
+            if !allowed.contains(model) {
+                return Err(format!(
+                    "Model '{}' is not available for {} (allowed: {})",
+                    model,
+                    provider,
+                    allowed.join(", ")
+                ));
+            }
+        }
+
+        let mut config = Self::try_load_config()?;
+        let models = config.distill_models.get_or_insert_with(HashMap::new);
+        match model {
+            Some(model) => models.insert(provider.to_string(), model),
+            None => models.remove(provider),
+        };
+        Self::save_config(&config)
+    }
+
+    /// Requests per minute for `provider`; 0 means no limit
+    pub fn get_rate_limit(&self, provider: &str) -> Result<u32, String> {
+        match provider {
+            "openai" => Ok(self.openai_limiter.rpm()),
+            "gemini" => Ok(self.gemini_limiter.rpm()),
+            _ => Err(format!("Unknown provider: {}", provider)),
+        }
+    }
+
+    pub fn set_rate_limit(&self, provider: &str, rpm: u32) -> Result<(), String> {
+        let mut config = Self::try_load_config()?;
+        match provider {
+            "openai" => {
+                self.openai_limiter.set_rpm(rpm);
+                config.openai_rate_limit_rpm = Some(rpm);
+            }
+            "gemini" => {
+                self.gemini_limiter.set_rpm(rpm);
+                config.gemini_rate_limit_rpm = Some(rpm);
+            }
+            _ => return Err(format!("Unknown provider: {}", provider)),
+        }
+        Self::save_config(&config)
+    }
+
+    pub fn breaker_statuses(&self) -> Vec<BreakerStatus> {
+        vec![self.openai_breaker.status(), self.gemini_breaker.status()]
+    }
+
+    /// `options` with the temperature pinned to 0 while deterministic mode is on
+    fn effective_options(&self, options: &RequestOptions) -> RequestOptions {
+        let mut options = options.clone();
+        if self.deterministic.load(Ordering::SeqCst) {
+            options.temperature = Some(0.0);
+        }
+        options
+    }
+
+    fn seed(&self) -> Option<i64> {
+        self.deterministic
+            .load(Ordering::SeqCst)
+            .then_some(DETERMINISTIC_SEED)
+    }
+
+    /// Snapshot of the current client so no lock is held while a request is in flight
+    async fn http(&self) -> Client {
+        self.client.read().await.clone()
+    }
+
+    /// (connect, request) timeouts in seconds
+    pub fn get_timeouts() -> (u64, u64) {
+        let config = Self::load_config();
+        (
+            config.connect_timeout_secs.unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS),
+            config.request_timeout_secs.unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS),
+        )
+    }
+
+    pub async fn set_timeouts(&self, connect_secs: u64, request_secs: u64) -> Result<(), String> {
+        *self.client.write().await = build_client(connect_secs, request_secs);
+
+        let mut config = Self::try_load_config()?;
+        config.connect_timeout_secs = Some(connect_secs);
+        config.request_timeout_secs = Some(request_secs);
+        Self::save_config(&config)
+    }
+
+    /// Output token limit per provider; `None` for Gemini means its default
+    pub fn get_max_output_tokens(&self, provider: &str) -> Result<Option<u32>, String> {
+        match provider {
+            "openai" => Ok(Some(self.openai_max_output_tokens.load(Ordering::SeqCst))),
+            "gemini" => Ok(match self.gemini_max_output_tokens.load(Ordering::SeqCst) {
+                0 => None,
+                tokens => Some(tokens),
+            }),
+            _ => Err(format!("Unknown provider: {}", provider)),
+        }
+    }
+
+    pub fn set_max_output_tokens(&self, provider: &str, tokens: u32) -> Result<(), String> {
+        let ceiling = match provider {
+            "openai" => OPENAI_MAX_OUTPUT_CEILING,
+            "gemini" => GEMINI_MAX_OUTPUT_CEILING,
+            _ => return Err(format!("Unknown provider: {}", provider)),
+        };
+        if !(1..=ceiling).contains(&tokens) {
+            return Err(format!("max_output_tokens for {} must be between 1 and {}", provider, ceiling));
+        }
+
+        let mut config = Self::try_load_config()?;
+        if provider == "openai" {
+            self.openai_max_output_tokens.store(tokens, Ordering::SeqCst);
+            config.openai_max_output_tokens = Some(tokens);
+        } else {
+            self.gemini_max_output_tokens.store(tokens, Ordering::SeqCst);
+            config.gemini_max_output_tokens = Some(tokens);
+        }
+        Self::save_config(&config)
+    }
+
+    /// Keep raw response bodies for bug reports; off unless debugging
+    pub fn set_capture_raw(&self, enabled: bool) {
+        self.capture_raw.store(enabled, Ordering::SeqCst);
+    }
+
+    async fn record_raw_response(&self, provider: &str, body: &str, api_key: &str) {
+        if self.capture_raw.load(Ordering::SeqCst) {
+            self.last_raw_responses
+                .write()
+                .await
+                .insert(provider.to_string(), redact_with(body, api_key));
+        }
+    }
+
+    pub async fn last_raw_response(&self, provider: &str) -> Option<String> {
+        self.last_raw_responses.read().await.get(provider).cloned()
     }
 
     // OpenAI Methods
     pub async fn load_openai_key(&self) -> Option<String> {
         let config = Self::load_config();
-        if let Some(key) = config.openai_api_key.clone() {
+        if let Some(key) = vault::reveal(config.openai_api_key).ok().flatten() {
             let mut api_key = self.openai_api_key.write().await;
             *api_key = Some(key.clone());
             return Some(key);
@@ -146,7 +1000,7 @@ impl AiClients {
             let mut api_key = self.openai_api_key.write().await;
             *api_key = Some(key.clone());
         }
-        let mut config = Self::load_config();
+        let mut config = Self::try_load_config()?;
         config.openai_api_key = Some(key);
         Self::save_config(&config)?;
         Ok(())
@@ -162,7 +1016,7 @@ impl AiClients {
             let mut api_key = self.openai_api_key.write().await;
             *api_key = None;
         }
-        let mut config = Self::load_config();
+        let mut config = Self::try_load_config()?;
         config.openai_api_key = None;
         Self::save_config(&config)?;
         Ok(())
@@ -171,7 +1025,7 @@ impl AiClients {
     // Gemini Methods
     pub async fn load_gemini_key(&self) -> Option<String> {
         let config = Self::load_config();
-        if let Some(key) = config.gemini_api_key.clone() {
+        if let Some(key) = vault::reveal(config.gemini_api_key).ok().flatten() {
             let mut api_key = self.gemini_api_key.write().await;
             *api_key = Some(key.clone());
             return Some(key);
@@ -184,7 +1038,7 @@ impl AiClients {
             let mut api_key = self.gemini_api_key.write().await;
             *api_key = Some(key.clone());
         }
-        let mut config = Self::load_config();
+        let mut config = Self::try_load_config()?;
         config.gemini_api_key = Some(key);
         Self::save_config(&config)?;
         Ok(())
@@ -200,216 +1054,683 @@ impl AiClients {
             let mut api_key = self.gemini_api_key.write().await;
             *api_key = None;
         }
-        let mut config = Self::load_config();
+        let mut config = Self::try_load_config()?;
         config.gemini_api_key = None;
         Self::save_config(&config)?;
         Ok(())
     }
 
-    // OpenAI Ask
-    pub async fn ask_openai(&self, content: &str, question: &str) -> Result<String, String> {
+    /// Chat-capable OpenAI model ids available to the stored key
+    pub async fn list_openai_models(&self) -> Result<Vec<String>, String> {
         let api_key = self.openai_api_key.read().await;
         let api_key = api_key.as_ref().ok_or("OpenAI API key not set")?;
 
-        let prompt = format!(
-            "당신은 해당 분야의 전문가입니다. 다음 문서를 바탕으로 질문에 전문적이고 상세하게 답변해주세요.\n\n\
-            ## 답변 가이드라인:\n\
-            - 전문 용어가 있다면 쉽게 설명해주세요\n\
-            - 관련 배경 지식도 함께 제공해주세요\n\
-            - 실용적인 예시나 활용 방안이 있다면 포함해주세요\n\
-            - 논리적인 구조로 답변을 구성해주세요\n\n\
-            ## 질문:\n{}\n\n\
-            ## 참고 문서:\n{}\n\n\
-            반드시 한글로 상세하게 답변해주세요.",
-            question, content
-        );
+        let response = self
+            .http()
+            .await
+            .get(OPENAI_MODELS_URL)
+            .header("Authorization", format!("Bearer {}", api_key))
+            .send()
+            .await
+            .map_err(|e| redact_with(&format!("OpenAI request failed: {}", e), api_key))?;
 
-        let request = OpenAiRequest {
-            model: "gpt-4o-mini".to_string(),
-            messages: vec![OpenAiMessage {
+        let status = response.status();
+        let body = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
+
+        if !status.is_success() {
+            return Err(openai_error_message(status, &body, api_key));
+        }
+
+        let value: serde_json::Value =
+            serde_json::from_str(&body).map_err(|e| format!("Failed to parse response: {}", e))?;
+
+        // The endpoint lists every model type; keep the chat completion families
+        let non_chat = ["instruct", "audio", "realtime", "tts", "transcribe", "search", "image", "embedding"];
+        let mut models: Vec<String> = value
+            .get("data")
+            .and_then(|d| d.as_array())
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| m.get("id").and_then(|id| id.as_str()))
+                    .filter(|id| {
+                        (id.starts_with("gpt-")
+                            || id.starts_with("chatgpt-")
+                            || id.strip_prefix('o').is_some_and(|r| r.starts_with(|c: char| c.is_ascii_digit())))
+                            && !non_chat.iter().any(|n| id.contains(n))
+                    })
+                    .map(|id| id.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        models.sort();
+
+        Ok(models)
+    }
+
+    /// Gemini models that support `generateContent`
+    pub async fn list_gemini_models(&self) -> Result<Vec<String>, String> {
+        let api_key = self.gemini_api_key.read().await;
+        let api_key = api_key.as_ref().ok_or("Gemini API key not set")?;
+
+        let mut models = Vec::new();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut request = self
+                .http()
+                .await
+                .get(GEMINI_MODELS_URL)
+                .header("x-goog-api-key", api_key.as_str())
+                .query(&[("pageSize", "100")]);
+            if let Some(token) = &page_token {
+                request = request.query(&[("pageToken", token.as_str())]);
+            }
+
+            let response = request
+                .send()
+                .await
+                .map_err(|e| redact_with(&format!("Gemini request failed: {}", e), api_key))?;
+
+            let status = response.status();
+            let body = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
+
+            if !status.is_success() {
+                return Err(gemini_error_message(status, &body, api_key));
+            }
+
+            let value: serde_json::Value =
+                serde_json::from_str(&body).map_err(|e| format!("Failed to parse response: {}", e))?;
+
+            if let Some(list) = value.get("models").and_then(|m| m.as_array()) {
+                for model in list {
+                    let supports_chat = model
+                        .get("supportedGenerationMethods")
+                        .and_then(|m| m.as_array())
+                        .map(|methods| methods.iter().any(|m| m.as_str() == Some("generateContent")))
+                        .unwrap_or(false);
+                    if let Some(name) = model.get("name").and_then(|n| n.as_str()) {
+                        if supports_chat {
+                            models.push(name.trim_start_matches("models/").to_string());
+                        }
+                    }
+                }
+            }
+
+            page_token = value
+                .get("nextPageToken")
+                .and_then(|t| t.as_str())
+                .filter(|t| !t.is_empty())
+                .map(|t| t.to_string());
+            if page_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(models)
+    }
+
+    // OpenAI Ask
+    pub async fn ask_openai_with(&self, content: &str, question: &str, options: &RequestOptions) -> Result<String, ProviderError> {
+        self.send_openai_prompt_with(&question_prompt(content, question, options.untrusted_content), options)
+            .await
+    }
+
+    /// `ask_openai_with`, also giving the output tokens OpenAI reported
+    pub async fn ask_openai_metered(&self, content: &str, question: &str, options: &RequestOptions) -> Result<MeteredAnswer, ProviderError> {
+        self.openai_metered(
+            vec![OpenAiRequestMessage {
                 role: "user".to_string(),
-                content: prompt,
+                content: OpenAiContent::Text(question_prompt(content, question, options.untrusted_content)),
             }],
-            max_tokens: 4096,
+            options,
+        )
+        .await
+    }
+
+    pub async fn ask_openai_with_history(
+        &self,
+        messages: Vec<OpenAiMessage>,
+        options: &RequestOptions,
+    ) -> Result<String, String> {
+        self.openai_request(
+            messages.into_iter().map(OpenAiRequestMessage::from).collect(),
+            options,
+        )
+        .await
+        .map_err(String::from)
+    }
+
+    pub async fn ask_openai_with_image(&self, image: &ImageInput, question: &str) -> Result<String, String> {
+        self.openai_request(
+            vec![OpenAiRequestMessage {
+                role: "user".to_string(),
+                content: OpenAiContent::Parts(vec![
+                    OpenAiContentPart::Text {
+                        text: image_question_prompt(question),
+                    },
+                    OpenAiContentPart::ImageUrl {
+                        image_url: OpenAiImageUrl {
+                            url: format!("data:{};base64,{}", image.mime_type, image.data),
+                        },
+                    },
+                ]),
+            }],
+            &RequestOptions::default(),
+        )
+        .await
+        .map_err(String::from)
+    }
+
+    /// Single completion with `tools` offered to the model; tool calls take precedence over text
+    pub async fn ask_openai_with_tools(
+        &self,
+        messages: Vec<OpenAiMessage>,
+        tools: Vec<OpenAiFunction>,
+    ) -> Result<OpenAiToolReply, String> {
+        let tools = tools
+            .into_iter()
+            .map(|function| OpenAiToolSpec {
+                kind: "function",
+                function,
+            })
+            .collect::<Vec<_>>();
+        let (message, _) = self
+            .openai_completion(
+                messages.into_iter().map(OpenAiRequestMessage::from).collect(),
+                &RequestOptions::default(),
+                (!tools.is_empty()).then_some(tools),
+            )
+            .await?;
+        tool_reply(message)
+    }
+
+    async fn openai_request(
+        &self,
+        messages: Vec<OpenAiRequestMessage>,
+        options: &RequestOptions,
+    ) -> Result<String, ProviderError> {
+        self.openai_metered(messages, options).await.map(|answer| answer.text)
+    }
+
+    async fn openai_metered(
+        &self,
+        messages: Vec<OpenAiRequestMessage>,
+        options: &RequestOptions,
+    ) -> Result<MeteredAnswer, ProviderError> {
+        let (message, output_tokens) = self.openai_completion(messages, options, None).await?;
+        Ok(MeteredAnswer {
+            text: message.content.ok_or("Empty response from OpenAI")?,
+            output_tokens,
+        })
+    }
+
+    /// The reply message and the completion tokens OpenAI reported for it
+    async fn openai_completion(
+        &self,
+        messages: Vec<OpenAiRequestMessage>,
+        options: &RequestOptions,
+        tools: Option<Vec<OpenAiToolSpec>>,
+    ) -> Result<(OpenAiResponseMessage, Option<u64>), ProviderError> {
+        let api_key = self.openai_api_key.read().await;
+        let api_key = api_key.as_ref().ok_or("OpenAI API key not set")?;
+
+        let options = &self.effective_options(options);
+        let model = resolve_model("OpenAI", options.model.as_deref(), &OPENAI_MODELS, DEFAULT_OPENAI_MODEL)?;
+        let request = OpenAiRequest {
+            model: model.id.to_string(),
+            messages,
+            max_tokens: self.openai_max_output_tokens.load(Ordering::SeqCst).min(model.max_output),
+            temperature: options.temperature,
+            seed: self.seed(),
+            stream: false,
+            tools,
         };
 
+        self.openai_breaker.check().map_err(ProviderError::retryable)?;
+        self.openai_limiter.acquire().await;
         let response = self
-            .client
+            .http()
+            .await
             .post(OPENAI_API_URL)
             .header("Authorization", format!("Bearer {}", api_key))
             .header("Content-Type", "application/json")
             .json(&request)
             .send()
-            .await
-            .map_err(|e| format!("OpenAI request failed: {}", e))?;
+            .await;
+        self.openai_breaker.record_response(&response);
+        let response = response
+            .map_err(|e| ProviderError::retryable(redact_with(&format!("OpenAI request failed: {}", e), api_key)))?;
 
         let status = response.status();
         let body = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
+        self.record_raw_response("openai", &body, api_key).await;
 
         if !status.is_success() {
-            if let Ok(error) = serde_json::from_str::<OpenAiError>(&body) {
-                return Err(format!("OpenAI error: {}", error.error.message));
-            }
-            return Err(format!("OpenAI error ({}): {}", status, body));
+            return Err(ProviderError::from_status(status, openai_error_message(status, &body, api_key)));
         }
 
-        let response: OpenAiResponse =
-            serde_json::from_str(&body).map_err(|e| format!("Failed to parse response: {}", e))?;
+        parse_openai_response(&body)
+    }
+
+    pub async fn send_openai_prompt_with(
+        &self,
+        prompt: &str,
+        options: &RequestOptions,
+    ) -> Result<String, ProviderError> {
+        self.openai_request(
+            vec![OpenAiRequestMessage {
+                role: "user".to_string(),
+                content: OpenAiContent::Text(prompt.to_string()),
+            }],
+            options,
+        )
+        .await
+    }
+
+    /// Streamed variant of `ask_openai`; `on_delta` gets each piece of text as it arrives
+    pub async fn ask_openai_stream<F>(
+        &self,
+        content: &str,
+        question: &str,
+        options: &RequestOptions,
+        on_delta: F,
+    ) -> Result<String, String>
+    where
+        F: FnMut(&str) + Send,
+    {
+        let message = OpenAiRequestMessage {
+            role: "user".to_string(),
+            content: OpenAiContent::Text(question_prompt(content, question, options.untrusted_content)),
+        };
+        self.openai_stream(vec![message], options, on_delta).await
+    }
 
-        response
-            .choices
-            .first()
-            .map(|c| c.message.content.clone())
-            .ok_or_else(|| "Empty response from OpenAI".to_string())
+    pub async fn ask_openai_with_history_stream<F>(
+        &self,
+        messages: Vec<OpenAiMessage>,
+        options: &RequestOptions,
+        on_delta: F,
+    ) -> Result<String, String>
+    where
+        F: FnMut(&str) + Send,
+    {
+        self.openai_stream(
+            messages.into_iter().map(OpenAiRequestMessage::from).collect(),
+            options,
+            on_delta,
+        )
+        .await
     }
 
-    pub async fn ask_openai_with_history(&self, messages: Vec<OpenAiMessage>) -> Result<String, String> {
+    /// Chat completion with `stream: true`: SSE `data:` events ending with `[DONE]`
+    async fn openai_stream<F>(
+        &self,
+        messages: Vec<OpenAiRequestMessage>,
+        options: &RequestOptions,
+        mut on_delta: F,
+    ) -> Result<String, String>
+    where
+        F: FnMut(&str) + Send,
+    {
         let api_key = self.openai_api_key.read().await;
         let api_key = api_key.as_ref().ok_or("OpenAI API key not set")?;
 
+        let options = &self.effective_options(options);
+        let model = resolve_model("OpenAI", options.model.as_deref(), &OPENAI_MODELS, DEFAULT_OPENAI_MODEL)?;
         let request = OpenAiRequest {
-            model: "gpt-4o-mini".to_string(),
+            model: model.id.to_string(),
             messages,
-            max_tokens: 4096,
+            max_tokens: self.openai_max_output_tokens.load(Ordering::SeqCst).min(model.max_output),
+            temperature: options.temperature,
+            seed: self.seed(),
+            stream: true,
+            tools: None,
         };
 
+        self.openai_breaker.check()?;
+        self.openai_limiter.acquire().await;
         let response = self
-            .client
+            .http()
+            .await
             .post(OPENAI_API_URL)
             .header("Authorization", format!("Bearer {}", api_key))
             .header("Content-Type", "application/json")
             .json(&request)
             .send()
-            .await
-            .map_err(|e| format!("OpenAI request failed: {}", e))?;
+            .await;
+        self.openai_breaker.record_response(&response);
+        let mut response =
+            response.map_err(|e| redact_with(&format!("OpenAI request failed: {}", e), api_key))?;
 
         let status = response.status();
-        let body = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
-
         if !status.is_success() {
-            if let Ok(error) = serde_json::from_str::<OpenAiError>(&body) {
-                return Err(format!("OpenAI error: {}", error.error.message));
+            let body = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
+            self.record_raw_response("openai", &body, api_key).await;
+            return Err(openai_error_message(status, &body, api_key));
+        }
+
+        let mut decoder = SseDecoder::default();
+        let mut raw = String::new();
+        let mut answer = String::new();
+        let mut finish_reason = None;
+
+        'read: loop {
+            let chunk = response
+                .chunk()
+                .await
+                .map_err(|e| redact_with(&format!("OpenAI stream interrupted: {}", e), api_key))?;
+            let finished = chunk.is_none();
+            let events = match chunk {
+                Some(bytes) => decoder.push(&bytes),
+                None => decoder.finish().into_iter().collect(),
+            };
+
+            for data in events {
+                raw.push_str(&data);
+                raw.push('\n');
+                if data.trim() == "[DONE]" {
+                    break 'read;
+                }
+
+                let chunk: OpenAiStreamChunk = serde_json::from_str(&data).map_err(|e| {
+                    redact_with(&format!("Failed to parse stream event: {} - {}", e, truncate_chars(&data, 500)), api_key)
+                })?;
+                if let Some(error) = chunk.error {
+                    return Err(redact_with(&format!("OpenAI error: {}", error.message), api_key));
+                }
+                if let Some(choice) = chunk.choices.into_iter().next() {
+                    if let Some(text) = choice.delta.content.filter(|t| !t.is_empty()) {
+                        on_delta(&text);
+                        answer.push_str(&text);
+                    }
+                    if choice.finish_reason.is_some() {
+                        finish_reason = choice.finish_reason;
+                    }
+                }
+            }
+
+            if finished {
+                break;
             }
-            return Err(format!("OpenAI error ({}): {}", status, body));
         }
 
-        let response: OpenAiResponse =
-            serde_json::from_str(&body).map_err(|e| format!("Failed to parse response: {}", e))?;
+        self.record_raw_response("openai", &raw, api_key).await;
 
-        response
-            .choices
-            .first()
-            .map(|c| c.message.content.clone())
-            .ok_or_else(|| "Empty response from OpenAI".to_string())
+        if finish_reason.as_deref() == Some("length") {
+            return Err(ProviderError::truncated("OpenAI", answer).into());
+        }
+        if answer.is_empty() {
+            return Err("Empty response from OpenAI".to_string());
+        }
+        Ok(answer)
     }
 
     // Gemini Ask
-    pub async fn ask_gemini(&self, content: &str, question: &str) -> Result<String, String> {
-        let api_key = self.gemini_api_key.read().await;
-        let api_key = api_key.as_ref().ok_or("Gemini API key not set")?;
+    pub async fn ask_gemini_with(&self, content: &str, question: &str, options: &RequestOptions) -> Result<String, ProviderError> {
+        self.send_gemini_prompt_with(&question_prompt(content, question, options.untrusted_content), options)
+            .await
+    }
 
-        let prompt = format!(
-            "당신은 해당 분야의 전문가입니다. 다음 문서를 바탕으로 질문에 전문적이고 상세하게 답변해주세요.\n\n\
-            ## 답변 가이드라인:\n\
-            - 전문 용어가 있다면 쉽게 설명해주세요\n\
-            - 관련 배경 지식도 함께 제공해주세요\n\
-            - 실용적인 예시나 활용 방안이 있다면 포함해주세요\n\
-            - 논리적인 구조로 답변을 구성해주세요\n\n\
-            ## 질문:\n{}\n\n\
-            ## 참고 문서:\n{}\n\n\
-            반드시 한글로 상세하게 답변해주세요.",
-            question, content
-        );
+    /// `ask_gemini_with`, also giving the output tokens Gemini reported
+    pub async fn ask_gemini_metered(&self, content: &str, question: &str, options: &RequestOptions) -> Result<MeteredAnswer, ProviderError> {
+        self.gemini_metered(
+            vec![GeminiContent {
+                parts: vec![GeminiPart {
+                    text: question_prompt(content, question, options.untrusted_content),
+                    ..Default::default()
+                }],
+                role: Some("user".to_string()),
+            }],
+            options,
+        )
+        .await
+    }
 
-        let request = GeminiRequest {
-            contents: vec![GeminiContent {
-                parts: vec![GeminiPart { text: prompt }],
+    pub async fn ask_gemini_with_image(&self, image: &ImageInput, question: &str) -> Result<String, String> {
+        let prompt = image_question_prompt(question);
+        let request_bytes = image.encoded_len() + prompt.len();
+        if request_bytes > GEMINI_MAX_INLINE_REQUEST_BYTES {
+            return Err(format!(
+                "Image and question too large for Gemini ({:.1} MB encoded), limit is {} MB",
+                request_bytes as f64 / (1024.0 * 1024.0),
+                GEMINI_MAX_INLINE_REQUEST_BYTES / (1024 * 1024)
+            ));
+        }
+
+        self.gemini_request(
+            vec![GeminiContent {
+                parts: vec![
+                    GeminiPart {
+                        text: prompt,
+                        ..Default::default()
+                    },
+                    GeminiPart {
+                        inline_data: Some(GeminiInlineData {
+                            mime_type: image.mime_type.clone(),
+                            data: image.data.clone(),
+                        }),
+                        ..Default::default()
+                    },
+                ],
                 role: Some("user".to_string()),
             }],
-        };
+            &RequestOptions::default(),
+        )
+        .await
+        .map_err(String::from)
+    }
 
-        let url = format!("{}?key={}", GEMINI_API_URL, api_key);
+    async fn gemini_request(
+        &self,
+        messages: Vec<GeminiContent>,
+        options: &RequestOptions,
+    ) -> Result<String, ProviderError> {
+        self.gemini_metered(messages, options).await.map(|answer| answer.text)
+    }
+
+    async fn gemini_metered(
+        &self,
+        messages: Vec<GeminiContent>,
+        options: &RequestOptions,
+    ) -> Result<MeteredAnswer, ProviderError> {
+        let api_key = self.gemini_api_key.read().await;
+        let api_key = api_key.as_ref().ok_or("Gemini API key not set")?;
+        let spec = resolve_model("Gemini", options.model.as_deref(), &GEMINI_MODELS, DEFAULT_GEMINI_MODEL)?;
+        let model = spec.id;
+
+        let options = &self.effective_options(options);
+        let request = GeminiRequest {
+            contents: messages,
+            generation_config: GeminiGenerationConfig::from_options(
+                options,
+                self.get_max_output_tokens("gemini")
+                    .ok()
+                    .flatten()
+                    .map(|tokens| tokens.min(spec.max_output)),
+                self.seed(),
+            ),
+        };
 
+        self.gemini_breaker.check().map_err(ProviderError::retryable)?;
+        self.gemini_limiter.acquire().await;
+        // Send the key as a header so it never ends up in a URL that may be logged
         let response = self
-            .client
-            .post(&url)
+            .http()
+            .await
+            .post(format!("{}/{}:generateContent", GEMINI_MODELS_URL, model))
+            .header("x-goog-api-key", api_key.as_str())
             .header("Content-Type", "application/json")
             .json(&request)
             .send()
-            .await
-            .map_err(|e| format!("Gemini request failed: {}", e))?;
+            .await;
+        self.gemini_breaker.record_response(&response);
+        let response = response
+            .map_err(|e| ProviderError::retryable(redact_with(&format!("Gemini request failed: {}", e), api_key)))?;
 
         let status = response.status();
         let body = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
+        self.record_raw_response("gemini", &body, api_key).await;
 
         if !status.is_success() {
-            return Err(format!("Gemini error ({}): {}", status, body));
+            return Err(ProviderError::from_status(status, gemini_error_message(status, &body, api_key)));
         }
 
-        let response: GeminiResponse =
-            serde_json::from_str(&body).map_err(|e| format!("Failed to parse response: {} - Body: {}", e, &body[..body.len().min(500)]))?;
-
-        if let Some(error) = response.error {
-            return Err(format!("Gemini error: {}", error.message));
-        }
+        parse_gemini_response(&body, api_key)
+    }
 
-        if let Some(candidates) = response.candidates {
-            if let Some(candidate) = candidates.first() {
-                if let Some(part) = candidate.content.parts.first() {
-                    if !part.text.is_empty() {
-                        return Ok(part.text.clone());
-                    }
-                }
-            }
-        }
+    pub async fn send_gemini_prompt_with(
+        &self,
+        prompt: &str,
+        options: &RequestOptions,
+    ) -> Result<String, ProviderError> {
+        self.gemini_request(
+            vec![GeminiContent {
+                parts: vec![GeminiPart {
+                    text: prompt.to_string(),
+                    ..Default::default()
+                }],
+                role: Some("user".to_string()),
+            }],
+            options,
+        )
+        .await
+    }
 
-        Err(format!("Empty response from Gemini. Raw: {}", &body[..body.len().min(500)]))
+    /// Streamed variant of `ask_gemini`; `on_delta` gets each piece of text as it arrives
+    pub async fn ask_gemini_stream<F>(
+        &self,
+        content: &str,
+        question: &str,
+        options: &RequestOptions,
+        on_delta: F,
+    ) -> Result<String, String>
+    where
+        F: FnMut(&str) + Send,
+    {
+        let message = GeminiContent {
+            parts: vec![GeminiPart {
+                text: question_prompt(content, question, options.untrusted_content),
+                ..Default::default()
+            }],
+            role: Some("user".to_string()),
+        };
+        self.gemini_stream(vec![message], options, on_delta).await
     }
 
-    pub async fn ask_gemini_with_history(&self, messages: Vec<GeminiContent>) -> Result<String, String> {
+    /// `streamGenerateContent` with `alt=sse`: each event is a partial `GenerateContentResponse`
+    async fn gemini_stream<F>(
+        &self,
+        messages: Vec<GeminiContent>,
+        options: &RequestOptions,
+        mut on_delta: F,
+    ) -> Result<String, String>
+    where
+        F: FnMut(&str) + Send,
+    {
         let api_key = self.gemini_api_key.read().await;
         let api_key = api_key.as_ref().ok_or("Gemini API key not set")?;
+        let spec = resolve_model("Gemini", options.model.as_deref(), &GEMINI_MODELS, DEFAULT_GEMINI_MODEL)?;
+        let model = spec.id;
 
+        let options = &self.effective_options(options);
         let request = GeminiRequest {
             contents: messages,
+            generation_config: GeminiGenerationConfig::from_options(
+                options,
+                self.get_max_output_tokens("gemini")
+                    .ok()
+                    .flatten()
+                    .map(|tokens| tokens.min(spec.max_output)),
+                self.seed(),
+            ),
         };
 
-        let url = format!("{}?key={}", GEMINI_API_URL, api_key);
-
+        self.gemini_breaker.check()?;
+        self.gemini_limiter.acquire().await;
         let response = self
-            .client
-            .post(&url)
+            .http()
+            .await
+            .post(format!("{}/{}:streamGenerateContent?alt=sse", GEMINI_MODELS_URL, model))
+            .header("x-goog-api-key", api_key.as_str())
             .header("Content-Type", "application/json")
             .json(&request)
             .send()
-            .await
-            .map_err(|e| format!("Gemini request failed: {}", e))?;
+            .await;
+        self.gemini_breaker.record_response(&response);
+        let mut response =
+            response.map_err(|e| redact_with(&format!("Gemini request failed: {}", e), api_key))?;
 
         let status = response.status();
-        let body = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
-
         if !status.is_success() {
-            return Err(format!("Gemini error ({}): {}", status, body));
-        }
-
-        let response: GeminiResponse =
-            serde_json::from_str(&body).map_err(|e| format!("Failed to parse response: {} - Body: {}", e, &body[..body.len().min(500)]))?;
-
-        if let Some(error) = response.error {
-            return Err(format!("Gemini error: {}", error.message));
+            let body = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
+            self.record_raw_response("gemini", &body, api_key).await;
+            return Err(gemini_error_message(status, &body, api_key));
         }
 
-        if let Some(candidates) = response.candidates {
-            if let Some(candidate) = candidates.first() {
-                if let Some(part) = candidate.content.parts.first() {
-                    if !part.text.is_empty() {
-                        return Ok(part.text.clone());
+        let mut decoder = SseDecoder::default();
+        let mut raw = String::new();
+        let mut answer = String::new();
+        let mut finish_reason = None;
+
+        loop {
+            let chunk = response
+                .chunk()
+                .await
+                .map_err(|e| redact_with(&format!("Gemini stream interrupted: {}", e), api_key))?;
+            let finished = chunk.is_none();
+            let events = match chunk {
+                Some(bytes) => decoder.push(&bytes),
+                None => decoder.finish().into_iter().collect(),
+            };
+
+            for data in events {
+                raw.push_str(&data);
+                raw.push('\n');
+
+                let data = data.trim();
+                let response = serde_json::from_str::<GeminiResponse>(data).map_err(|e| {
+                    redact_with(&format!("Failed to parse stream event: {} - {}", e, truncate_chars(data, 500)), api_key)
+                })?;
+
+                if let Some(error) = response.error {
+                    return Err(redact_secrets(&format!("Gemini error: {}", error.message)));
+                }
+                if let Some(reason) = response.prompt_feedback.and_then(|f| f.block_reason) {
+                    return Err(format!("Gemini blocked the prompt ({})", reason));
+                }
+                if let Some(candidate) = response.candidates.and_then(|c| c.into_iter().next()) {
+                    let text: String = candidate
+                        .content
+                        .map(|c| c.parts.into_iter().map(|p| p.text).collect())
+                        .unwrap_or_default();
+                    if !text.is_empty() {
+                        on_delta(&text);
+                        answer.push_str(&text);
+                    }
+                    if candidate.finish_reason.is_some() {
+                        finish_reason = candidate.finish_reason;
                     }
                 }
             }
+
+            if finished {
+                break;
+            }
         }
 
-        Err(format!("Empty response from Gemini. Raw: {}", &body[..body.len().min(500)]))
+        self.record_raw_response("gemini", &raw, api_key).await;
+
+        if finish_reason.as_deref() == Some("MAX_TOKENS") {
+            return Err(ProviderError::truncated("Gemini", answer).into());
+        }
+        if answer.is_empty() {
+            if let Some(reason) = finish_reason.filter(|r| r != "STOP") {
+                return Err(format!("Gemini returned no text (finish reason: {})", reason));
+            }
+            return Err("Empty response from Gemini".to_string());
+        }
+        Ok(answer)
     }
 }
 
@@ -418,3 +1739,177 @@ impl Default for AiClients {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Chat completion where the model calls a tool instead of answering
+    const OPENAI_TOOL_CALLS: &str = r#"{
+        "id": "chatcmpl-abc123",
+        "object": "chat.completion",
+        "model": "gpt-4o-mini-2024-07-18",
+        "choices": [{
+            "index": 0,
+            "message": {
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [
+                    {
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "search_notion", "arguments": "{\"query\":\"회의록\"}"}
+                    },
+                    {
+                        "id": "call_2",
+                        "type": "function",
+                        "function": {"name": "fetch_page", "arguments": "{\"page_id\":\"abc\"}"}
+                    }
+                ]
+            },
+            "finish_reason": "tool_calls"
+        }],
+        "usage": {"prompt_tokens": 82, "completion_tokens": 41, "total_tokens": 123}
+    }"#;
+
+    /// Chat completion with tools offered where the model answers in text
+    const OPENAI_TEXT_WITH_TOOLS: &str = r#"{
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": "도구 없이 답할 수 있습니다.", "tool_calls": []},
+            "finish_reason": "stop"
+        }],
+        "usage": {"prompt_tokens": 60, "completion_tokens": 9, "total_tokens": 69}
+    }"#;
+
+    /// Gemini reply made only of a function call part, with no text
+    const GEMINI_FUNCTION_CALL: &str = r#"{
+        "candidates": [{
+            "content": {
+                "role": "model",
+                "parts": [{"functionCall": {"name": "search_notion", "args": {"query": "회의록"}}}]
+            },
+            "finishReason": "STOP"
+        }],
+        "usageMetadata": {"promptTokenCount": 50, "candidatesTokenCount": 12, "totalTokenCount": 62}
+    }"#;
+
+    const GEMINI_TEXT: &str = r#"{
+        "candidates": [{
+            "content": {"role": "model", "parts": [{"text": "첫 문단. "}, {"text": "둘째 문단."}]},
+            "finishReason": "STOP"
+        }],
+        "usageMetadata": {"promptTokenCount": 50, "candidatesTokenCount": 7, "totalTokenCount": 57}
+    }"#;
+
+    /// Every event from feeding `chunks` in order, then ending the body
+    fn decode(chunks: &[&[u8]]) -> Vec<String> {
+        let mut decoder = SseDecoder::default();
+        let mut events: Vec<String> = chunks.iter().flat_map(|c| decoder.push(c)).collect();
+        events.extend(decoder.finish());
+        events
+    }
+
+    #[test]
+    fn sse_event_split_across_chunks_is_reassembled() {
+        let events = decode(&[b"da", b"ta: {\"a\":", b"1}\n", b"\n", b"data: {\"a\":2}\n\n"]);
+        assert_eq!(events, vec![r#"{"a":1}"#, r#"{"a":2}"#]);
+    }
+
+    #[test]
+    fn sse_multibyte_character_split_across_chunks_survives() {
+        let bytes = "data: 회의\n\n".as_bytes();
+        // Cut inside the three bytes of '회'
+        let events = decode(&[&bytes[..7], &bytes[7..]]);
+        assert_eq!(events, vec!["회의"]);
+    }
+
+    #[test]
+    fn sse_crlf_line_endings_are_accepted() {
+        let events = decode(&[b"data: one\r\n\r\ndata: two\r", b"\n\r\n"]);
+        assert_eq!(events, vec!["one", "two"]);
+    }
+
+    #[test]
+    fn sse_multi_line_data_is_joined_with_newlines() {
+        let events = decode(&[b"event: delta\ndata: first\n: comment\ndata:second\n\n"]);
+        assert_eq!(events, vec!["first\nsecond"]);
+    }
+
+    #[test]
+    fn sse_done_marker_and_unterminated_last_event_are_delivered() {
+        assert_eq!(decode(&[b"data: {}\n\ndata: [DONE]\n\n"]), vec!["{}", "[DONE]"]);
+        assert_eq!(decode(&[b"data: [DONE]"]), vec!["[DONE]"]);
+    }
+
+    /// A configured key with none of the prefixes `redact_secrets` recognizes
+    const CUSTOM_KEY: &str = "proxy-key-0123456789abcdef";
+
+    #[test]
+    fn openai_error_bodies_never_carry_the_key() {
+        let structured = format!(r#"{{"error": {{"message": "Incorrect API key provided: {}", "type": "invalid_request_error"}}}}"#, CUSTOM_KEY);
+        let plain = format!("<html>bad gateway for key={} and sk-proj-abc123XYZ456def789</html>", CUSTOM_KEY);
+        for body in [structured, plain] {
+            let message = openai_error_message(reqwest::StatusCode::UNAUTHORIZED, &body, CUSTOM_KEY);
+            assert!(!message.contains(CUSTOM_KEY), "key leaked: {}", message);
+            assert!(!message.contains("abc123XYZ456def789"), "key leaked: {}", message);
+            assert!(message.contains("***"), "nothing masked: {}", message);
+        }
+    }
+
+    #[test]
+    fn gemini_errors_never_carry_the_key() {
+        let body = r#"{"error": {"code": 400, "message": "API key not valid: AIzaSyA1b2C3d4E5f6G7h8I9j0"}}"#;
+        let message = gemini_error_message(reqwest::StatusCode::BAD_REQUEST, body, "AIzaSyA1b2C3d4E5f6G7h8I9j0");
+        assert!(!message.contains("AIzaSyA1b2C3d4E5f6G7h8I9j0"), "key leaked: {}", message);
+
+        // Unparseable and empty success bodies are quoted in the error too
+        for body in [format!("not json {}", CUSTOM_KEY), format!(r#"{{"candidates": [], "echo": "{}"}}"#, CUSTOM_KEY)] {
+            let message = parse_gemini_response(&body, CUSTOM_KEY).err().unwrap().message;
+            assert!(!message.contains(CUSTOM_KEY), "key leaked: {}", message);
+        }
+    }
+
+    #[test]
+    fn openai_tool_calls_are_returned_in_order_with_raw_arguments() {
+        let (message, output_tokens) = parse_openai_response(OPENAI_TOOL_CALLS).unwrap();
+        assert_eq!(output_tokens, Some(41));
+
+        let OpenAiToolReply::ToolCalls(calls) = tool_reply(message).unwrap() else {
+            panic!("expected tool calls");
+        };
+        let calls: Vec<(&str, &str, &str)> = calls
+            .iter()
+            .map(|c| (c.id.as_str(), c.function.name.as_str(), c.function.arguments.as_str()))
+            .collect();
+        assert_eq!(
+            calls,
+            vec![
+                ("call_1", "search_notion", r#"{"query":"회의록"}"#),
+                ("call_2", "fetch_page", r#"{"page_id":"abc"}"#),
+            ]
+        );
+    }
+
+    #[test]
+    fn openai_text_answer_with_empty_tool_calls_is_text() {
+        let (message, _) = parse_openai_response(OPENAI_TEXT_WITH_TOOLS).unwrap();
+        match tool_reply(message).unwrap() {
+            OpenAiToolReply::Text(text) => assert_eq!(text, "도구 없이 답할 수 있습니다."),
+            OpenAiToolReply::ToolCalls(_) => panic!("expected text"),
+        }
+    }
+
+    #[test]
+    fn gemini_function_call_without_text_is_an_error() {
+        let error = parse_gemini_response(GEMINI_FUNCTION_CALL, "key").err().unwrap();
+        assert!(error.message.starts_with("Empty response from Gemini"), "{}", error.message);
+    }
+
+    #[test]
+    fn gemini_text_parts_are_joined_with_reported_usage() {
+        let answer = parse_gemini_response(GEMINI_TEXT, "key").unwrap();
+        assert_eq!(answer.text, "첫 문단. 둘째 문단.");
+        assert_eq!(answer.output_tokens, Some(7));
+    }
+}
@@ -0,0 +1,179 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// This many failures in a row, all within `FAILURE_WINDOW`, open the breaker
+const FAILURE_THRESHOLD: u32 = 3;
+const FAILURE_WINDOW: Duration = Duration::from_secs(120);
+/// How long calls fail fast before one is let through to test the provider again
+const COOLDOWN: Duration = Duration::from_secs(60);
+/// A trial call that hasn't reported back in this long (e.g. it was cancelled) no longer
+/// holds back the next one
+const TRIAL_TIMEOUT: Duration = Duration::from_secs(180);
+
+#[derive(Default)]
+struct BreakerState {
+    failures: u32,
+    first_failure: Option<Instant>,
+    open_until: Option<Instant>,
+    /// When the single call let through after the cooldown started, until it reports back
+    trial_started: Option<Instant>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BreakerStatus {
+    pub provider: String,
+    pub open: bool,
+    pub consecutive_failures: u32,
+    /// Seconds until calls are let through again, while open
+    pub retry_in_secs: Option<u64>,
+}
+
+/// Fails calls to a provider immediately while it looks down, instead of letting each
+/// one wait out the request timeout. Only timeouts, connection errors and 5xx responses
+/// count as failures; any other response proves the provider is reachable.
+pub struct CircuitBreaker {
+    provider: &'static str,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(provider: &'static str) -> Self {
+        Self {
+            provider,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, BreakerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Err while the breaker is open; after the cooldown one call goes through as a trial and
+    /// the others are still turned away until it succeeds or fails
+    pub fn check(&self) -> Result<(), String> {
+        let now = Instant::now();
+        let mut state = self.state();
+        let Some(until) = state.open_until else {
+            return Ok(());
+        };
+        if now < until {
+            return Err(format!(
+                "{} is temporarily disabled after repeated failures. Retrying in {} seconds.",
+                self.provider,
+                until.saturating_duration_since(now).as_secs().max(1)
+            ));
+        }
+        if state.trial_started.is_some_and(|started| now.duration_since(started) < TRIAL_TIMEOUT) {
+            return Err(format!(
+                "{} is temporarily disabled after repeated failures. Checking whether it has recovered.",
+                self.provider
+            ));
+        }
+        state.trial_started = Some(now);
+        Ok(())
+    }
+
+    pub fn record_response(&self, response: &reqwest::Result<reqwest::Response>) {
+        match response {
+            Ok(response) if !response.status().is_server_error() => self.record_success(),
+            _ => self.record_failure(),
+        }
+    }
+
+    fn record_success(&self) {
+        *self.state() = BreakerState::default();
+    }
+
+    fn record_failure(&self) {
+        let now = Instant::now();
+        let mut state = self.state();
+
+        // The trial call after a cooldown failed: stay open for another cooldown
+        if state.open_until.is_some() {
+            state.open_until = Some(now + COOLDOWN);
+            state.trial_started = None;
+            return;
+        }
+
+        let in_window = state
+            .first_failure
+            .is_some_and(|first| now.duration_since(first) <= FAILURE_WINDOW);
+        if !in_window {
+            state.failures = 0;
+            state.first_failure = Some(now);
+        }
+        state.failures += 1;
+        if state.failures >= FAILURE_THRESHOLD {
+            state.open_until = Some(now + COOLDOWN);
+        }
+    }
+
+    pub fn status(&self) -> BreakerStatus {
+        let state = self.state();
+        let retry_in = state
+            .open_until
+            .map(|until| until.saturating_duration_since(Instant::now()))
+            .filter(|remaining| !remaining.is_zero());
+        BreakerStatus {
+            provider: self.provider.to_string(),
+            open: retry_in.is_some(),
+            consecutive_failures: state.failures,
+            retry_in_secs: retry_in.map(|r| r.as_secs().max(1)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Breaker that has tripped and whose cooldown has just run out
+    fn cooled_down() -> CircuitBreaker {
+        let breaker = CircuitBreaker::new("Test");
+        for _ in 0..FAILURE_THRESHOLD {
+            breaker.record_failure();
+        }
+        assert!(breaker.check().is_err());
+        breaker.state().open_until = Some(Instant::now() - Duration::from_secs(1));
+        breaker
+    }
+
+    #[test]
+    fn only_one_trial_call_goes_through_after_the_cooldown() {
+        let breaker = cooled_down();
+        assert!(breaker.check().is_ok());
+        assert!(breaker.check().is_err());
+        assert!(breaker.check().is_err());
+    }
+
+    #[test]
+    fn successful_trial_closes_the_breaker() {
+        let breaker = cooled_down();
+        breaker.check().unwrap();
+        breaker.record_success();
+        assert!(breaker.check().is_ok());
+        assert!(breaker.check().is_ok());
+    }
+
+    #[test]
+    fn failed_trial_reopens_for_another_cooldown() {
+        let breaker = cooled_down();
+        breaker.check().unwrap();
+        breaker.record_failure();
+        assert!(breaker.check().is_err());
+        assert!(breaker.status().open);
+
+        breaker.state().open_until = Some(Instant::now() - Duration::from_secs(1));
+        assert!(breaker.check().is_ok());
+    }
+
+    #[test]
+    fn trial_that_never_reports_back_stops_blocking_after_a_while() {
+        let breaker = cooled_down();
+        breaker.check().unwrap();
+        breaker.state().trial_started = Some(Instant::now() - TRIAL_TIMEOUT);
+        assert!(breaker.check().is_ok());
+        assert!(breaker.check().is_err());
+    }
+}
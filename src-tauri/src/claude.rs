@@ -1,23 +1,113 @@
+use crate::ai_clients::{content_guard, resolve_model, MeteredAnswer, ModelSpec, ProviderError, RequestOptions};
+use crate::breaker::{BreakerStatus, CircuitBreaker};
+use crate::config_file::{config_file_path, read_json, stage_json, StagedFile};
+use crate::http_client::build_client;
+use crate::rate_limit::RateLimiter;
+use crate::redact::{redact_secrets, redact_with};
+use crate::text_util::{document_block, language_name, translation_runs, truncate_chars};
+use crate::vault::{self, NewPassphrase};
+use futures::stream::{self, StreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
 use tokio::sync::RwLock;
 
 const CLAUDE_API_URL: &str = "https://api.anthropic.com/v1/messages";
+const PROMPT_CACHING_BETA: &str = "prompt-caching-2024-07-31";
+const DEFAULT_MODEL: &str = "claude-sonnet-4-20250514";
+const MODELS: [ModelSpec; 4] = [
+    ModelSpec::new("claude-sonnet-4-20250514", 64000).with_thinking(),
+    ModelSpec::new("claude-opus-4-20250514", 32000).with_thinking(),
+    ModelSpec::new("claude-3-7-sonnet-20250219", 64000).with_thinking(),
+    ModelSpec::new("claude-3-5-haiku-20241022", 8192),
+];
+const DEFAULT_MAX_TOKENS: u32 = 4096;  // Reduced for faster responses
+/// Highest output ceiling among `MODELS`; a request is capped at its own model's
+const MAX_OUTPUT_CEILING: u32 = 64000;
+/// Extended thinking budget; `max_tokens` is raised by this much so the answer keeps its room
+const DEFAULT_THINKING_BUDGET: u32 = 8000;
 const CONFIG_FILE_NAME: &str = "ainotework_config.json";
+const DEFAULT_TRANSLATION_CONCURRENCY: usize = 3;
+const MAX_TRANSLATION_CONCURRENCY: usize = 8;
+/// Max chars per translation chunk (~2500 tokens worth, leaving room for prompt and response)
+const DEFAULT_TRANSLATION_CHUNK_CHARS: usize = 6000;
+const TRANSLATION_CHUNK_CHARS_RANGE: std::ops::RangeInclusive<usize> = 1000..=50_000;
+/// Source characters a translated chunk may have per token of `max_output_tokens`. Translations
+/// run to about one output token per two source characters, so a larger chunk gets cut off.
+const TRANSLATION_CHARS_PER_OUTPUT_TOKEN: usize = 2;
+/// Content estimated below this many tokens is translated in a single request
+const DEFAULT_SINGLE_TRANSLATION_MAX_TOKENS: usize = 2000;
+const SINGLE_TRANSLATION_MAX_TOKENS_RANGE: std::ops::RangeInclusive<usize> = 0..=20_000;
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 120;  // 2 minute timeout for long translations
 
-#[derive(Serialize, Deserialize, Default)]
-struct Config {
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub(crate) struct Config {
     api_key: Option<String>,
+    translation_concurrency: Option<usize>,
+    prompt_caching: Option<bool>,
+    thinking: Option<bool>,
+    deterministic_mode: Option<bool>,
+    max_output_tokens: Option<u32>,
+    translation_chunk_chars: Option<usize>,
+    single_translation_max_tokens: Option<usize>,
+    connect_timeout_secs: Option<u64>,
+    request_timeout_secs: Option<u64>,
+    beta_features: Option<Vec<String>>,
+    /// Requests per minute; 0 or unset means no limit
+    rate_limit_rpm: Option<u32>,
 }
 
 #[derive(Serialize)]
 struct ClaudeRequest {
     model: String,
     max_tokens: u32,
-    messages: Vec<Message>,
+    /// Top-level system prompt; Claude has no "system" role in `messages`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<RequestContent>,
+    messages: Vec<RequestMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<ThinkingConfig>,
+}
+
+#[derive(Serialize)]
+struct ThinkingConfig {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    budget_tokens: u32,
+}
+
+/// Wire form of a message; content is either plain text or blocks (needed for cache_control)
+#[derive(Serialize)]
+struct RequestMessage {
+    role: String,
+    content: RequestContent,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum RequestContent {
+    Text(String),
+    Blocks(Vec<RequestBlock>),
+}
+
+#[derive(Serialize)]
+struct RequestBlock {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<CacheControl>,
+}
+
+#[derive(Serialize)]
+struct CacheControl {
+    #[serde(rename = "type")]
+    kind: &'static str,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -29,11 +119,29 @@ pub struct Message {
 #[derive(Deserialize)]
 struct ClaudeResponse {
     content: Vec<ContentBlock>,
+    /// "end_turn", "max_tokens", "refusal", ...
+    stop_reason: Option<String>,
+    usage: Option<ClaudeUsage>,
+}
+
+#[derive(Deserialize)]
+struct ClaudeUsage {
+    output_tokens: u64,
 }
 
 #[derive(Deserialize)]
 struct ContentBlock {
+    #[serde(rename = "type", default)]
+    kind: String,
     text: Option<String>,
+    thinking: Option<String>,
+}
+
+/// Final answer plus the reasoning from any `thinking` blocks
+struct ClaudeReply {
+    text: String,
+    thinking: Option<String>,
+    output_tokens: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -46,52 +154,225 @@ struct ErrorDetail {
     message: String,
 }
 
+/// Result of a chunked translation; on failure `translated` holds the parts done before `failed_chunk`
+#[derive(Serialize, Clone, Debug)]
+pub struct TranslationOutcome {
+    pub translated: String,
+    pub total_chunks: usize,
+    pub completed_chunks: usize,
+    pub failed_chunk: Option<usize>,
+    pub error: Option<String>,
+}
+
+/// How `translate` would split a document, computed without calling the API
+#[derive(Serialize, Clone, Debug)]
+pub struct TranslationPlan {
+    pub chunk_count: usize,
+    pub estimated_tokens: usize,
+    pub chunks: Vec<ChunkPlan>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ChunkPlan {
+    pub index: usize,
+    pub chars: usize,
+    pub estimated_tokens: usize,
+    pub preview: String,
+}
+
+/// Target length for a constrained summary; either or both may be set
+#[derive(Deserialize, Clone, Debug)]
+pub struct SummaryConstraint {
+    pub bullets: Option<usize>,
+    pub max_words: Option<usize>,
+}
+
+/// Shape of the summary text returned to the caller
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SummaryFormat {
+    #[default]
+    Markdown,
+    Plain,
+    Json,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StructuredSummary {
+    pub title: String,
+    pub key_points: Vec<String>,
+}
+
 pub struct ClaudeClient {
-    client: Client,
+    client: Arc<RwLock<Client>>,
     api_key: Arc<RwLock<Option<String>>>,
+    translation_concurrency: Arc<RwLock<usize>>,
+    capture_raw: AtomicBool,
+    last_raw_response: Arc<RwLock<Option<String>>>,
+    prompt_caching: AtomicBool,
+    /// Extra `anthropic-beta` flags, e.g. "context-1m-2025-08-07"
+    beta_features: Arc<RwLock<Vec<String>>>,
+    thinking: AtomicBool,
+    deterministic: AtomicBool,
+    max_output_tokens: AtomicU32,
+    translation_chunk_chars: AtomicUsize,
+    single_translation_max_tokens: AtomicUsize,
+    breaker: CircuitBreaker,
+    limiter: RateLimiter,
+}
+
+/// Up to `radius` chars either side of the first occurrence of `selection` in `full`.
+/// Falls back to the start of the document when the selection isn't found verbatim.
+fn context_window(full: &str, selection: &str, radius: usize) -> String {
+    let chars: Vec<char> = full.chars().collect();
+    let (start, end) = match full.find(selection.trim()) {
+        Some(byte_pos) => {
+            let char_pos = full[..byte_pos].chars().count();
+            let selection_len = selection.trim().chars().count();
+            (
+                char_pos.saturating_sub(radius),
+                (char_pos + selection_len + radius).min(chars.len()),
+            )
+        }
+        None => (0, (radius * 2).min(chars.len())),
+    };
+
+    let mut window: String = chars[start..end].iter().collect();
+    if start > 0 {
+        window.insert_str(0, "...");
+    }
+    if end < chars.len() {
+        window.push_str("...");
+    }
+    window
 }
 
 impl ClaudeClient {
     pub fn new() -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(120))  // 2 minute timeout for long translations
-            .connect_timeout(Duration::from_secs(30))
-            .build()
-            .unwrap_or_else(|_| Client::new());
+        let config = Self::load_config();
+        let client = build_client(
+            config.connect_timeout_secs.unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS),
+            config.request_timeout_secs.unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS),
+        );
 
         Self {
-            client,
+            client: Arc::new(RwLock::new(client)),
             api_key: Arc::new(RwLock::new(None)),
+            translation_concurrency: Arc::new(RwLock::new(
+                config
+                    .translation_concurrency
+                    .unwrap_or(DEFAULT_TRANSLATION_CONCURRENCY),
+            )),
+            capture_raw: AtomicBool::new(false),
+            last_raw_response: Arc::new(RwLock::new(None)),
+            prompt_caching: AtomicBool::new(config.prompt_caching.unwrap_or(false)),
+            beta_features: Arc::new(RwLock::new(config.beta_features.unwrap_or_default())),
+            thinking: AtomicBool::new(config.thinking.unwrap_or(false)),
+            deterministic: AtomicBool::new(config.deterministic_mode.unwrap_or(false)),
+            max_output_tokens: AtomicU32::new(
+                config
+                    .max_output_tokens
+                    .unwrap_or(DEFAULT_MAX_TOKENS)
+                    .clamp(1, MAX_OUTPUT_CEILING),
+            ),
+            translation_chunk_chars: AtomicUsize::new(
+                config
+                    .translation_chunk_chars
+                    .filter(|c| TRANSLATION_CHUNK_CHARS_RANGE.contains(c))
+                    .unwrap_or(DEFAULT_TRANSLATION_CHUNK_CHARS),
+            ),
+            single_translation_max_tokens: AtomicUsize::new(
+                config
+                    .single_translation_max_tokens
+                    .filter(|t| SINGLE_TRANSLATION_MAX_TOKENS_RANGE.contains(t))
+                    .unwrap_or(DEFAULT_SINGLE_TRANSLATION_MAX_TOKENS),
+            ),
+            breaker: CircuitBreaker::new("Claude"),
+            limiter: RateLimiter::new(config.rate_limit_rpm.unwrap_or(0)),
         }
     }
 
-    fn get_config_path() -> Option<PathBuf> {
-        dirs::config_dir().map(|p| p.join(CONFIG_FILE_NAME))
+    pub(crate) fn get_config_path() -> Option<PathBuf> {
+        config_file_path(CONFIG_FILE_NAME)
     }
 
+    fn try_load_config() -> Result<Config, String> {
+        let path = Self::get_config_path().ok_or("Could not determine config directory")?;
+        read_json(&path)
+    }
+
+    /// Defaults when the file can't be read; updates go through `try_load_config` so a bad file is never overwritten
     fn load_config() -> Config {
-        if let Some(path) = Self::get_config_path() {
-            if let Ok(content) = std::fs::read_to_string(&path) {
-                if let Ok(config) = serde_json::from_str(&content) {
-                    return config;
-                }
-            }
-        }
-        Config::default()
+        Self::try_load_config().unwrap_or_default()
+    }
+
+    /// Secrets are encrypted on the way out when a master passphrase is set
+    pub(crate) fn save_config(config: &Config) -> Result<(), String> {
+        Self::stage_config(config)?.commit()
     }
 
-    fn save_config(config: &Config) -> Result<(), String> {
+    /// `save_config` without moving the file into place, for writes that go together
+    fn stage_config(config: &Config) -> Result<StagedFile, String> {
         let path = Self::get_config_path().ok_or("Could not determine config directory")?;
-        let content = serde_json::to_string_pretty(config)
-            .map_err(|e| format!("Failed to serialize config: {}", e))?;
-        std::fs::write(&path, content)
-            .map_err(|e| format!("Failed to write config file: {}", e))?;
-        Ok(())
+        let mut config = config.clone();
+        config.api_key = vault::seal(config.api_key)?;
+        stage_json(&path, &config)
+    }
+
+    /// `save_config` for a passphrase change: secrets are sealed under the new key and the file
+    /// is only staged, to be committed together with the vault and the other configs
+    pub(crate) fn stage_config_resealed(
+        config: &Config,
+        passphrase: &NewPassphrase,
+    ) -> Result<StagedFile, String> {
+        let path = Self::get_config_path().ok_or("Could not determine config directory")?;
+        let mut config = config.clone();
+        config.api_key = passphrase.seal(config.api_key)?;
+        stage_json(&path, &config)
+    }
+
+    /// Saved config with secrets decrypted, for re-encrypting under a new passphrase
+    pub(crate) fn load_config_revealed() -> Result<Config, String> {
+        let mut config = Self::try_load_config()?;
+        config.api_key = vault::reveal(config.api_key)?;
+        Ok(config)
+    }
+
+    /// Saved settings for export; the API key is left out unless `include_secrets`, and stays
+    /// encrypted when a master passphrase is set
+    pub(crate) fn export_settings(include_secrets: bool) -> Result<Config, String> {
+        let mut config = Self::try_load_config()?;
+        if !include_secrets {
+            config.api_key = None;
+        }
+        Ok(config)
+    }
+
+    /// Exported settings merged into the saved ones, staged for `commit_all`; values missing
+    /// from the import are kept. See `vault::merge_imported` for `replacing_vault`.
+    pub(crate) fn stage_import(incoming: Config, replacing_vault: bool) -> Result<StagedFile, String> {
+        let mut config = Self::try_load_config()?;
+        config.api_key = vault::merge_imported(incoming.api_key, config.api_key, replacing_vault)?;
+        config.translation_concurrency = incoming.translation_concurrency.or(config.translation_concurrency);
+        config.prompt_caching = incoming.prompt_caching.or(config.prompt_caching);
+        if let Some(features) = incoming.beta_features {
+            config.beta_features = Some(Self::clean_beta_features(features)?);
+        }
+        config.rate_limit_rpm = incoming.rate_limit_rpm.or(config.rate_limit_rpm);
+        config.thinking = incoming.thinking.or(config.thinking);
+        config.deterministic_mode = incoming.deterministic_mode.or(config.deterministic_mode);
+        config.max_output_tokens = incoming.max_output_tokens.or(config.max_output_tokens);
+        config.translation_chunk_chars = incoming.translation_chunk_chars.or(config.translation_chunk_chars);
+        config.single_translation_max_tokens =
+            incoming.single_translation_max_tokens.or(config.single_translation_max_tokens);
+        config.connect_timeout_secs = incoming.connect_timeout_secs.or(config.connect_timeout_secs);
+        config.request_timeout_secs = incoming.request_timeout_secs.or(config.request_timeout_secs);
+        Self::stage_config(&config)
     }
 
     pub async fn load_api_key(&self) -> Option<String> {
         let config = Self::load_config();
-        if let Some(key) = config.api_key.clone() {
+        if let Some(key) = vault::reveal(config.api_key).ok().flatten() {
             let mut api_key = self.api_key.write().await;
             *api_key = Some(key.clone());
             return Some(key);
@@ -104,9 +385,8 @@ impl ClaudeClient {
             let mut api_key = self.api_key.write().await;
             *api_key = Some(key.clone());
         }
-        let config = Config {
-            api_key: Some(key),
-        };
+        let mut config = Self::try_load_config()?;
+        config.api_key = Some(key);
         Self::save_config(&config)?;
         Ok(())
     }
@@ -121,164 +401,484 @@ impl ClaudeClient {
             let mut api_key = self.api_key.write().await;
             *api_key = None;
         }
-        let config = Config { api_key: None };
+        let mut config = Self::try_load_config()?;
+        config.api_key = None;
         Self::save_config(&config)?;
         Ok(())
     }
 
+    pub async fn get_translation_concurrency(&self) -> usize {
+        // Clamp in case the config file was edited by hand
+        (*self.translation_concurrency.read().await).clamp(1, MAX_TRANSLATION_CONCURRENCY)
+    }
+
+    pub async fn set_translation_concurrency(&self, concurrency: usize) -> Result<(), String> {
+        if !(1..=MAX_TRANSLATION_CONCURRENCY).contains(&concurrency) {
+            return Err(format!(
+                "Concurrency must be between 1 and {}",
+                MAX_TRANSLATION_CONCURRENCY
+            ));
+        }
+        *self.translation_concurrency.write().await = concurrency;
+
+        let mut config = Self::try_load_config()?;
+        config.translation_concurrency = Some(concurrency);
+        Self::save_config(&config)
+    }
+
+    /// (max chars per chunk, single-request threshold in estimated tokens). The chunk size is
+    /// capped by what `max_output_tokens` can hold, in case that was lowered after it was set.
+    pub fn get_translation_chunk_size(&self) -> (usize, usize) {
+        (
+            self.translation_chunk_chars
+                .load(Ordering::SeqCst)
+                .min(self.max_translation_chunk_chars()),
+            self.single_translation_max_tokens.load(Ordering::SeqCst),
+        )
+    }
+
+    /// Largest chunk whose translation fits in `max_output_tokens`
+    fn max_translation_chunk_chars(&self) -> usize {
+        self.max_output_tokens.load(Ordering::SeqCst) as usize * TRANSLATION_CHARS_PER_OUTPUT_TOKEN
+    }
+
+    pub fn set_translation_chunk_size(&self, chunk_chars: usize, single_max_tokens: usize) -> Result<(), String> {
+        if !TRANSLATION_CHUNK_CHARS_RANGE.contains(&chunk_chars) {
+            return Err(format!(
+                "Chunk size must be between {} and {} characters",
+                TRANSLATION_CHUNK_CHARS_RANGE.start(),
+                TRANSLATION_CHUNK_CHARS_RANGE.end()
+            ));
+        }
+        if chunk_chars > self.max_translation_chunk_chars() {
+            return Err(format!(
+                "Chunk size {} is too large for max_output_tokens {}; use at most {} characters or raise max_output_tokens",
+                chunk_chars,
+                self.get_max_output_tokens(),
+                self.max_translation_chunk_chars()
+            ));
+        }
+        if !SINGLE_TRANSLATION_MAX_TOKENS_RANGE.contains(&single_max_tokens) {
+            return Err(format!(
+                "Single-request threshold must be between {} and {} tokens",
+                SINGLE_TRANSLATION_MAX_TOKENS_RANGE.start(),
+                SINGLE_TRANSLATION_MAX_TOKENS_RANGE.end()
+            ));
+        }
+        self.translation_chunk_chars.store(chunk_chars, Ordering::SeqCst);
+        self.single_translation_max_tokens.store(single_max_tokens, Ordering::SeqCst);
+
+        let mut config = Self::try_load_config()?;
+        config.translation_chunk_chars = Some(chunk_chars);
+        config.single_translation_max_tokens = Some(single_max_tokens);
+        Self::save_config(&config)
+    }
+
+    pub fn get_prompt_caching(&self) -> bool {
+        self.prompt_caching.load(Ordering::SeqCst)
+    }
+
+    pub fn set_prompt_caching(&self, enabled: bool) -> Result<(), String> {
+        self.prompt_caching.store(enabled, Ordering::SeqCst);
+
+        let mut config = Self::try_load_config()?;
+        config.prompt_caching = Some(enabled);
+        Self::save_config(&config)
+    }
+
+    pub async fn get_beta_features(&self) -> Vec<String> {
+        self.beta_features.read().await.clone()
+    }
+
+    /// Beta flags sent in the `anthropic-beta` header with every request; an empty list sends none
+    pub async fn set_beta_features(&self, features: Vec<String>) -> Result<(), String> {
+        let cleaned = Self::clean_beta_features(features)?;
+        *self.beta_features.write().await = cleaned.clone();
+
+        let mut config = Self::try_load_config()?;
+        config.beta_features = Some(cleaned);
+        Self::save_config(&config)
+    }
+
+    /// Trimmed, de-duplicated flag names; anything but letters, digits, `-` and `_` is rejected
+    fn clean_beta_features(features: Vec<String>) -> Result<Vec<String>, String> {
+        let mut cleaned: Vec<String> = Vec::new();
+        for feature in features {
+            let feature = feature.trim().to_string();
+            if feature.is_empty() {
+                continue;
+            }
+            if !feature.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                return Err(format!("Invalid beta feature name: {}", feature));
+            }
+            if !cleaned.contains(&feature) {
+                cleaned.push(feature);
+            }
+        }
+        Ok(cleaned)
+    }
+
+    /// Value of the `anthropic-beta` header: the configured flags plus prompt caching when it is on
+    async fn beta_header(&self) -> Option<String> {
+        let mut features = self.beta_features.read().await.clone();
+        if self.prompt_caching.load(Ordering::SeqCst) && !features.iter().any(|f| f == PROMPT_CACHING_BETA) {
+            features.push(PROMPT_CACHING_BETA.to_string());
+        }
+        (!features.is_empty()).then(|| features.join(","))
+    }
+
+    /// Requests per minute; 0 means no limit
+    pub fn get_rate_limit(&self) -> u32 {
+        self.limiter.rpm()
+    }
+
+    pub fn set_rate_limit(&self, rpm: u32) -> Result<(), String> {
+        self.limiter.set_rpm(rpm);
+
+        let mut config = Self::try_load_config()?;
+        config.rate_limit_rpm = Some(rpm);
+        Self::save_config(&config)
+    }
+
+    pub fn breaker_status(&self) -> BreakerStatus {
+        self.breaker.status()
+    }
+
+    /// Snapshot of the current client so no lock is held while a request is in flight
+    async fn http(&self) -> Client {
+        self.client.read().await.clone()
+    }
+
+    pub fn get_max_output_tokens(&self) -> u32 {
+        self.max_output_tokens.load(Ordering::SeqCst)
+    }
+
+    pub fn set_max_output_tokens(&self, tokens: u32) -> Result<(), String> {
+        if !(1..=MAX_OUTPUT_CEILING).contains(&tokens) {
+            return Err(format!("max_output_tokens for claude must be between 1 and {}", MAX_OUTPUT_CEILING));
+        }
+        self.max_output_tokens.store(tokens, Ordering::SeqCst);
+
+        let mut config = Self::try_load_config()?;
+        config.max_output_tokens = Some(tokens);
+        Self::save_config(&config)
+    }
+
+    pub fn get_thinking(&self) -> bool {
+        self.thinking.load(Ordering::SeqCst)
+    }
+
+    /// Extended thinking for questions; off by default since it costs extra output tokens
+    pub fn set_thinking(&self, enabled: bool) -> Result<(), String> {
+        self.thinking.store(enabled, Ordering::SeqCst);
+
+        let mut config = Self::try_load_config()?;
+        config.thinking = Some(enabled);
+        Self::save_config(&config)
+    }
+
+    pub fn get_deterministic_mode(&self) -> bool {
+        self.deterministic.load(Ordering::SeqCst)
+    }
+
+    /// Saved deterministic mode; the one copy of the setting, shared with `AiClients`
+    pub(crate) fn load_deterministic_mode() -> bool {
+        Self::load_config().deterministic_mode.unwrap_or(false)
+    }
+
+    /// Temperature 0 on every request; extended thinking requests still go without a temperature.
+    /// Saved first so a failed save leaves the setting as it was.
+    pub fn set_deterministic_mode(&self, enabled: bool) -> Result<(), String> {
+        let mut config = Self::try_load_config()?;
+        config.deterministic_mode = Some(enabled);
+        Self::save_config(&config)?;
+
+        self.deterministic.store(enabled, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// (connect, request) timeouts in seconds
+    pub fn get_timeouts() -> (u64, u64) {
+        let config = Self::load_config();
+        (
+            config.connect_timeout_secs.unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS),
+            config.request_timeout_secs.unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS),
+        )
+    }
+
+    /// Timeouts are fixed when a reqwest client is built, so swap in a new one.
+    /// In-flight requests keep the client they started with.
+    pub async fn set_timeouts(&self, connect_secs: u64, request_secs: u64) -> Result<(), String> {
+        *self.client.write().await = build_client(connect_secs, request_secs);
+
+        let mut config = Self::try_load_config()?;
+        config.connect_timeout_secs = Some(connect_secs);
+        config.request_timeout_secs = Some(request_secs);
+        Self::save_config(&config)
+    }
+
+    /// Anthropic has no public model-list endpoint, so this is a curated list
+    pub fn available_models() -> Vec<String> {
+        MODELS.iter().map(|m| m.id.to_string()).collect()
+    }
+
+    /// Keep raw response bodies for bug reports; off unless debugging
+    pub fn set_capture_raw(&self, enabled: bool) {
+        self.capture_raw.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Error for a failed request: the API's message when the body has one, else the body,
+    /// with `api_key` and anything else that looks like a key masked
+    fn error_message(status: reqwest::StatusCode, body: &str, api_key: &str) -> String {
+        match serde_json::from_str::<ClaudeError>(body) {
+            Ok(error) => redact_with(&format!("API error: {}", error.error.message), api_key),
+            Err(_) => redact_with(&format!("API error ({}): {}", status, body), api_key),
+        }
+    }
+
+    async fn record_raw_response(&self, body: &str, api_key: &str) {
+        if self.capture_raw.load(Ordering::SeqCst) {
+            *self.last_raw_response.write().await = Some(redact_with(body, api_key));
+        }
+    }
+
+    pub async fn last_raw_response(&self) -> Option<String> {
+        self.last_raw_response.read().await.clone()
+    }
+
     pub async fn send_message(&self, prompt: &str) -> Result<String, String> {
-        let api_key = self.api_key.read().await;
-        let api_key = api_key.as_ref().ok_or("API key not set")?;
+        self.send_message_with(prompt, &RequestOptions::default())
+            .await
+            .map_err(String::from)
+    }
 
-        let request = ClaudeRequest {
-            model: "claude-sonnet-4-20250514".to_string(),
-            max_tokens: 4096,  // Reduced for faster responses
-            messages: vec![Message {
+    pub async fn send_message_with(&self, prompt: &str, options: &RequestOptions) -> Result<String, ProviderError> {
+        self.request(
+            vec![RequestMessage {
                 role: "user".to_string(),
-                content: prompt.to_string(),
+                content: RequestContent::Text(prompt.to_string()),
             }],
+            options,
+        )
+        .await
+    }
+
+    /// Multi-turn request with `system` as the top-level system prompt. With prompt caching on,
+    /// the system prompt (which carries the document in `ask_with_history`) is marked cacheable.
+    pub async fn send_messages_with_system(
+        &self,
+        system: &str,
+        messages: Vec<Message>,
+        options: &RequestOptions,
+    ) -> Result<String, String> {
+        let system = if self.prompt_caching.load(Ordering::SeqCst) {
+            RequestContent::Blocks(vec![RequestBlock {
+                kind: "text",
+                text: system.to_string(),
+                cache_control: Some(CacheControl { kind: "ephemeral" }),
+            }])
+        } else {
+            RequestContent::Text(system.to_string())
         };
+        let messages = messages
+            .into_iter()
+            .map(|m| RequestMessage {
+                role: m.role,
+                content: RequestContent::Text(m.content),
+            })
+            .collect();
 
-        let response = self
-            .client
-            .post(CLAUDE_API_URL)
-            .header("x-api-key", api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&request)
-            .send()
+        self.request_reply_with_system(Some(system), messages, options)
             .await
-            .map_err(|e| {
-                if e.is_timeout() {
-                    "Request timed out. Please try again or use shorter content.".to_string()
-                } else if e.is_connect() {
-                    "Connection failed. Please check your internet connection.".to_string()
-                } else {
-                    format!("Network error: {}. Please check your connection.", e)
-                }
-            })?;
-
-        let status = response.status();
-        let body = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
-
-        if !status.is_success() {
-            if let Ok(error) = serde_json::from_str::<ClaudeError>(&body) {
-                return Err(format!("API error: {}", error.error.message));
-            }
-            return Err(format!("API error ({}): {}", status, body));
-        }
+            .map(|reply| reply.text)
+            .map_err(String::from)
+    }
 
-        let response: ClaudeResponse =
-            serde_json::from_str(&body).map_err(|e| format!("Failed to parse response: {}", e))?;
+    async fn request(
+        &self,
+        messages: Vec<RequestMessage>,
+        options: &RequestOptions,
+    ) -> Result<String, ProviderError> {
+        self.request_reply(messages, options).await.map(|reply| reply.text)
+    }
 
-        response
-            .content
-            .first()
-            .and_then(|block| block.text.clone())
-            .ok_or_else(|| "Empty response from Claude".to_string())
+    async fn request_reply(
+        &self,
+        messages: Vec<RequestMessage>,
+        options: &RequestOptions,
+    ) -> Result<ClaudeReply, ProviderError> {
+        self.request_reply_with_system(None, messages, options).await
     }
 
-    pub async fn send_messages(&self, messages: Vec<Message>) -> Result<String, String> {
+    async fn request_reply_with_system(
+        &self,
+        system: Option<RequestContent>,
+        messages: Vec<RequestMessage>,
+        options: &RequestOptions,
+    ) -> Result<ClaudeReply, ProviderError> {
         let api_key = self.api_key.read().await;
         let api_key = api_key.as_ref().ok_or("API key not set")?;
 
+        let model = resolve_model("Claude", options.model.as_deref(), &MODELS, DEFAULT_MODEL)?;
+        if options.thinking_budget.is_some() && !model.thinking {
+            return Err(format!("{} does not support extended thinking", model.id).into());
+        }
+        let thinking = options.thinking_budget.map(|budget| ThinkingConfig {
+            kind: "enabled",
+            budget_tokens: budget.max(1024),
+        });
         let request = ClaudeRequest {
-            model: "claude-sonnet-4-20250514".to_string(),
-            max_tokens: 4096,  // Reduced for faster responses
+            model: model.id.to_string(),
+            max_tokens: (self.max_output_tokens.load(Ordering::SeqCst)
+                + thinking.as_ref().map(|t| t.budget_tokens).unwrap_or(0))
+            .min(model.max_output),
+            system,
             messages,
+            // Thinking does not allow a custom temperature
+            temperature: if thinking.is_some() {
+                None
+            } else if self.deterministic.load(Ordering::SeqCst) {
+                Some(0.0)
+            } else {
+                options.temperature
+            },
+            thinking,
         };
 
-        let response = self
-            .client
+        self.breaker.check().map_err(ProviderError::retryable)?;
+        self.limiter.acquire().await;
+        let mut builder = self
+            .http()
+            .await
             .post(CLAUDE_API_URL)
             .header("x-api-key", api_key)
             .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| {
-                if e.is_timeout() {
-                    "Request timed out. Please try again or use shorter content.".to_string()
-                } else if e.is_connect() {
-                    "Connection failed. Please check your internet connection.".to_string()
-                } else {
-                    format!("Network error: {}. Please check your connection.", e)
-                }
-            })?;
+            .header("content-type", "application/json");
+
+        if let Some(beta) = self.beta_header().await {
+            builder = builder.header("anthropic-beta", beta);
+        }
+
+        let response = builder.json(&request).send().await;
+        self.breaker.record_response(&response);
+        let response = response.map_err(|e| {
+            ProviderError::retryable(if e.is_timeout() {
+                "Request timed out. Please try again or use shorter content.".to_string()
+            } else if e.is_connect() {
+                "Connection failed. Please check your internet connection.".to_string()
+            } else {
+                redact_secrets(&format!("Network error: {}. Please check your connection.", e))
+            })
+        })?;
 
         let status = response.status();
         let body = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
+        self.record_raw_response(&body, api_key).await;
 
         if !status.is_success() {
-            if let Ok(error) = serde_json::from_str::<ClaudeError>(&body) {
-                return Err(format!("API error: {}", error.error.message));
-            }
-            return Err(format!("API error ({}): {}", status, body));
+            return Err(ProviderError::from_status(status, Self::error_message(status, &body, api_key)));
         }
 
         let response: ClaudeResponse =
             serde_json::from_str(&body).map_err(|e| format!("Failed to parse response: {}", e))?;
 
-        response
+        let stop_reason = response.stop_reason.as_deref().unwrap_or_default();
+        if stop_reason == "refusal" || response.content.iter().any(|block| block.kind == "refusal") {
+            return Err("Claude declined to answer this request (refusal)".into());
+        }
+
+        // With thinking on, the answer follows one or more thinking blocks
+        let text: Vec<String> = response
+            .content
+            .iter()
+            .filter(|block| block.kind == "text" || block.kind.is_empty())
+            .filter_map(|block| block.text.clone())
+            .collect();
+        let thinking: Vec<String> = response
             .content
-            .first()
-            .and_then(|block| block.text.clone())
-            .ok_or_else(|| "Empty response from Claude".to_string())
+            .iter()
+            .filter(|block| block.kind == "thinking")
+            .filter_map(|block| block.thinking.clone())
+            .collect();
+
+        if stop_reason == "max_tokens" {
+            return Err(ProviderError::truncated("Claude", text.join("\n\n")));
+        }
+        if text.is_empty() {
+            return Err("Empty response from Claude".into());
+        }
+
+        Ok(ClaudeReply {
+            text: text.join("\n\n"),
+            thinking: if thinking.is_empty() {
+                None
+            } else {
+                Some(thinking.join("\n\n"))
+            },
+            output_tokens: response.usage.map(|u| u.output_tokens),
+        })
     }
 
     /// Estimate token count (rough approximation: ~3 chars per token for mixed content)
-    fn estimate_tokens(text: &str) -> usize {
+    pub(crate) fn estimate_tokens(text: &str) -> usize {
         text.len() / 3
     }
 
-    /// Split content into chunks that fit within token limits
-    /// Tries to split at paragraph boundaries for better context
-    fn split_content_for_translation(content: &str, max_chars: usize) -> Vec<String> {
+    /// Split content into chunks of at most `max_chars` characters (not bytes, so Hangul counts
+    /// the same as Latin). Tries to split at paragraph boundaries for better context
+    pub(crate) fn split_content_for_translation(content: &str, max_chars: usize) -> Vec<String> {
         let mut chunks = Vec::new();
         let paragraphs: Vec<&str> = content.split("\n\n").collect();
         let mut current_chunk = String::new();
+        // Length of `current_chunk` in chars, kept alongside so it isn't recounted
+        let mut current_len = 0;
 
         for para in paragraphs {
+            let para_len = para.chars().count();
             // If single paragraph exceeds limit, split by sentences
-            if para.len() > max_chars {
+            if para_len > max_chars {
                 if !current_chunk.is_empty() {
-                    chunks.push(current_chunk.clone());
-                    current_chunk.clear();
+                    chunks.push(std::mem::take(&mut current_chunk));
+                    current_len = 0;
                 }
 
                 // Split by sentences (period, question mark, exclamation)
-                let sentences: Vec<&str> = para.split_inclusive(|c| c == '.' || c == '?' || c == '!' || c == '。' || c == '？' || c == '！')
-                    .collect();
+                let sentences: Vec<&str> = para.split_inclusive(['.', '?', '!', '。', '？', '！']).collect();
 
                 for sentence in sentences {
-                    if current_chunk.len() + sentence.len() > max_chars {
+                    let sentence_len = sentence.chars().count();
+                    if current_len + sentence_len > max_chars {
                         if !current_chunk.is_empty() {
-                            chunks.push(current_chunk.clone());
-                            current_chunk.clear();
+                            chunks.push(std::mem::take(&mut current_chunk));
+                            current_len = 0;
                         }
                         // If single sentence is still too long, just add it as is
-                        if sentence.len() > max_chars {
+                        if sentence_len > max_chars {
                             chunks.push(sentence.to_string());
                         } else {
                             current_chunk = sentence.to_string();
+                            current_len = sentence_len;
                         }
                     } else {
                         current_chunk.push_str(sentence);
+                        current_len += sentence_len;
                     }
                 }
-            } else if current_chunk.len() + para.len() + 2 > max_chars {
+            } else if current_len + para_len + 2 > max_chars {
                 // Current chunk would exceed limit, save it and start new
                 if !current_chunk.is_empty() {
-                    chunks.push(current_chunk.clone());
+                    chunks.push(std::mem::take(&mut current_chunk));
                 }
                 current_chunk = para.to_string();
+                current_len = para_len;
             } else {
                 // Add paragraph to current chunk
                 if !current_chunk.is_empty() {
                     current_chunk.push_str("\n\n");
+                    current_len += 2;
                 }
                 current_chunk.push_str(para);
+                current_len += para_len;
             }
         }
 
@@ -295,71 +895,591 @@ impl ClaudeClient {
         chunks
     }
 
-    pub async fn translate(&self, content: &str, target_lang: &str) -> Result<String, String> {
-        // Max chars per chunk (~2500 tokens worth, leaving room for prompt and response)
-        const MAX_CHUNK_CHARS: usize = 6000;
+    /// Whether an API error means the input was too large for the model's context. A bad
+    /// `max_tokens` value is not one of these: splitting the input would not fix it.
+    fn is_token_limit_error(error: &str) -> bool {
+        let error = error.to_lowercase();
+        error.contains("prompt is too long")
+            || error.contains("too many tokens")
+            || error.contains("maximum context length")
+            || error.contains("context window")
+    }
 
-        let estimated_tokens = Self::estimate_tokens(content);
+    /// Split text roughly in half, preferring a line break, then whitespace.
+    /// Returns the two halves and the separator to rejoin them with.
+    fn split_in_half(text: &str) -> (String, String, &'static str) {
+        let mut mid = text.len() / 2;
+        while !text.is_char_boundary(mid) {
+            mid += 1;
+        }
 
-        // If content is small enough, translate in one go
-        if estimated_tokens < 2000 {
-            let prompt = format!(
-                "Translate the following content to {}. Only provide the translation, no explanations:\n\n{}",
-                target_lang, content
-            );
-            return self.send_message(&prompt).await;
+        let (head, _) = text.split_at(mid);
+        let split_at = head
+            .rfind('\n')
+            .map(|i| (i, "\n"))
+            .or_else(|| head.rfind(char::is_whitespace).map(|i| (i, " ")))
+            .filter(|(i, _)| *i > 0);
+
+        match split_at {
+            Some((i, sep)) => {
+                let (first, second) = text.split_at(i);
+                (first.to_string(), second.trim_start().to_string(), sep)
+            }
+            None => {
+                let (first, second) = text.split_at(mid);
+                (first.to_string(), second.to_string(), "")
+            }
         }
+    }
 
-        // Split content into manageable chunks
-        let chunks = Self::split_content_for_translation(content, MAX_CHUNK_CHARS);
-        let total_chunks = chunks.len();
+    /// Translate a single chunk, re-splitting it into halves when the API rejects it as too large
+    async fn translate_chunk(
+        &self,
+        chunk: &str,
+        target_lang: &str,
+        part: usize,
+        total_parts: usize,
+    ) -> Result<String, String> {
+        // Each level halves the chunk, so 4 levels allow up to 16 pieces per chunk
+        const MAX_SPLIT_DEPTH: usize = 4;
 
-        let mut translated_parts = Vec::new();
+        // Work queue processed front-first so output order is preserved
+        let mut pending: std::collections::VecDeque<(String, usize, &'static str)> =
+            std::collections::VecDeque::new();
+        pending.push_back((chunk.to_string(), 0, ""));
+        let mut output = String::new();
 
-        for (i, chunk) in chunks.iter().enumerate() {
-            let prompt = if total_chunks > 1 {
+        while let Some((piece, depth, separator)) = pending.pop_front() {
+            let prompt = if total_parts > 1 {
                 format!(
                     "Translate the following content to {}. This is part {} of {} parts. \
                     Only provide the translation, maintain consistency with previous parts, no explanations:\n\n{}",
-                    target_lang, i + 1, total_chunks, chunk
+                    target_lang, part, total_parts, piece
                 )
             } else {
                 format!(
                     "Translate the following content to {}. Only provide the translation, no explanations:\n\n{}",
-                    target_lang, chunk
+                    target_lang, piece
                 )
             };
 
-            let translated = self.send_message(&prompt).await?;
-            translated_parts.push(translated);
+            // Too long to send, or its translation too long to fit the output limit: halve it
+            match self.send_message_with(&prompt, &RequestOptions::default()).await {
+                Ok(translated) => {
+                    output.push_str(separator);
+                    output.push_str(&translated);
+                }
+                Err(e)
+                    if (e.partial.is_some() || Self::is_token_limit_error(&e.message))
+                        && depth < MAX_SPLIT_DEPTH
+                        && piece.chars().count() > 1 =>
+                {
+                    let (first, second, sep) = Self::split_in_half(&piece);
+                    pending.push_front((second, depth + 1, sep));
+                    pending.push_front((first, depth + 1, separator));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(output)
+    }
+
+    pub async fn translate(&self, content: &str, target_lang: &str) -> Result<String, String> {
+        let outcome = self.translate_from(content, target_lang, 0).await;
+        match outcome.error {
+            Some(error) if outcome.completed_chunks > 0 => Err(format!(
+                "{} (part {} of {} failed; {} parts were translated)",
+                error,
+                outcome.failed_chunk.map(|i| i + 1).unwrap_or(0),
+                outcome.total_chunks,
+                outcome.completed_chunks
+            )),
+            Some(error) => Err(error),
+            None => Ok(outcome.translated),
+        }
+    }
+
+    /// `translate` for bilingual documents: paragraphs already in `target_lang` are kept as is,
+    /// as are code blocks and paragraphs whose language can't be told, and each run of other
+    /// paragraphs is translated, then everything is put back in order. Falls back to `translate`
+    /// when `target_lang` is not one `detect_language` recognizes.
+    pub async fn translate_skipping_target(&self, content: &str, target_lang: &str) -> Result<String, String> {
+        let Some(target) = language_name(target_lang) else {
+            return self.translate(content, target_lang).await;
+        };
+
+        // Chunk every run up front so all chunks share one concurrency limit, rather than
+        // each run fanning out on its own
+        let (chunk_chars, single_max_tokens) = self.get_translation_chunk_size();
+        let mut pieces: Vec<(Option<(usize, usize)>, String)> = Vec::new();
+        for (translate, text) in translation_runs(content, target) {
+            if !translate {
+                pieces.push((None, text));
+                continue;
+            }
+            let chunks = if Self::estimate_tokens(&text) < single_max_tokens {
+                vec![text]
+            } else {
+                Self::split_content_for_translation(&text, chunk_chars)
+            };
+            let total = chunks.len();
+            pieces.extend(chunks.into_iter().enumerate().map(|(i, chunk)| (Some((i + 1, total)), chunk)));
         }
 
-        // Join all translated parts
-        Ok(translated_parts.join("\n\n"))
+        let concurrency = self.get_translation_concurrency().await;
+        let mut results = stream::iter(pieces)
+            .map(|(part, text)| async move {
+                match part {
+                    Some((part, total)) => self.translate_chunk(&text, target_lang, part, total).await,
+                    None => Ok(text),
+                }
+            })
+            .buffered(concurrency);
+
+        let mut parts = Vec::new();
+        while let Some(result) = results.next().await {
+            parts.push(result?);
+        }
+        Ok(parts.join("\n\n"))
     }
 
-    pub async fn summarize(&self, content: &str) -> Result<String, String> {
+    /// Translate only `selection`, giving the model the surrounding document for
+    /// pronouns and terminology. Only the part of the document around the selection is sent.
+    pub async fn translate_with_context(
+        &self,
+        selection: &str,
+        full_content: &str,
+        target_lang: &str,
+    ) -> Result<String, String> {
+        const CONTEXT_RADIUS_CHARS: usize = 3000;
+
+        if selection.trim().is_empty() {
+            return Err("Selection must not be empty".to_string());
+        }
+        let context = context_window(full_content, selection, CONTEXT_RADIUS_CHARS);
+
+        let prompt = format!(
+            "Translate the text inside <selection> to {}. The <context> is the document it was taken from; \
+            use it only to resolve pronouns, terminology and tone. Do not translate the context. \
+            Only provide the translation of the selection, no explanations or tags.\n\n\
+            <context>\n{}\n</context>\n\n<selection>\n{}\n</selection>",
+            target_lang, context, selection
+        );
+
+        self.send_message(&prompt).await.map(|t| t.trim().to_string())
+    }
+
+    /// Improve an earlier translation of `source` following one correction from the reviewer
+    /// ("use formal tone", "keep product names in English"); the rest of the translation is kept.
+    /// Long sources are split like `translate_from` and refined part by part.
+    pub async fn refine_translation(
+        &self,
+        source: &str,
+        previous_translation: &str,
+        instruction: &str,
+        target_lang: &str,
+    ) -> Result<String, String> {
+        if instruction.trim().is_empty() {
+            return Err("Correction instruction must not be empty".to_string());
+        }
+        if previous_translation.trim().is_empty() {
+            return Err("Previous translation must not be empty".to_string());
+        }
+
+        let (chunk_chars, single_max_tokens) = self.get_translation_chunk_size();
+        let chunks = if Self::estimate_tokens(source) < single_max_tokens {
+            vec![source.to_string()]
+        } else {
+            Self::split_content_for_translation(source, chunk_chars)
+        };
+        let previous_parts = Self::align_translation(previous_translation, &chunks);
+        let total_parts = chunks.len();
+
+        let concurrency = self.get_translation_concurrency().await;
+        let mut results = stream::iter(chunks.iter().zip(&previous_parts).enumerate())
+            .map(|(i, (chunk, previous))| async move {
+                (i, self.refine_chunk(chunk, previous, instruction.trim(), target_lang, i + 1, total_parts).await)
+            })
+            .buffered(concurrency);
+
+        let mut refined = Vec::with_capacity(total_parts);
+        while let Some((i, result)) = results.next().await {
+            match result {
+                Ok(text) => refined.push(text),
+                Err(e) if total_parts > 1 => return Err(format!("{} (part {} of {} failed)", e, i + 1, total_parts)),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(refined.join("\n\n"))
+    }
+
+    async fn refine_chunk(
+        &self,
+        source: &str,
+        previous: &str,
+        instruction: &str,
+        target_lang: &str,
+        part: usize,
+        total_parts: usize,
+    ) -> Result<String, String> {
+        let part_note = if total_parts > 1 {
+            format!(" This is part {} of {} parts; keep consistency with the other parts.", part, total_parts)
+        } else {
+            String::new()
+        };
+        let prompt = format!(
+            "The <translation> is a {} translation of the <source>. A reviewer asked for this correction:\n\n\
+            <instruction>\n{}\n</instruction>\n\n\
+            Produce the improved translation, applying the correction throughout. Keep everything the \
+            correction does not touch as it is, and keep the markdown structure.{} \
+            Only provide the translation, no explanations or tags.\n\n\
+            <source>\n{}\n</source>\n\n<translation>\n{}\n</translation>",
+            target_lang, instruction, part_note, source, previous
+        );
+
+        self.send_message(&prompt).await.map(|t| t.trim().to_string())
+    }
+
+    /// Cut `translation` at paragraph breaks into one piece per source chunk, giving each chunk
+    /// the paragraphs that sit at the same relative position in the text. Translations keep
+    /// the paragraph structure, so this lines up with the source closely enough to refine by part.
+    fn align_translation(translation: &str, chunks: &[String]) -> Vec<String> {
+        let mut pieces = vec![String::new(); chunks.len()];
+        let Some(last) = chunks.len().checked_sub(1) else {
+            return pieces;
+        };
+
+        // Where each source chunk ends, in chars from the start of the source
+        let mut ends = Vec::with_capacity(chunks.len());
+        let mut source_len = 0;
+        for chunk in chunks {
+            source_len += chunk.chars().count();
+            ends.push(source_len);
+        }
+        let translation_len = translation.chars().count().max(1);
+
+        let mut offset = 0;
+        for para in translation.split("\n\n") {
+            let len = para.chars().count();
+            // Place the paragraph by its midpoint, scaled onto the source
+            let midpoint = (offset + len / 2) * source_len / translation_len;
+            let index = ends.iter().position(|&end| midpoint < end).unwrap_or(last);
+            if !pieces[index].is_empty() {
+                pieces[index].push_str("\n\n");
+            }
+            pieces[index].push_str(para);
+            offset += len + 2;
+        }
+        pieces
+    }
+
+    /// Chunking that `translate_from` will use for `content`
+    pub fn plan_translation(&self, content: &str) -> TranslationPlan {
+        const PREVIEW_CHARS: usize = 80;
+
+        let (chunk_chars, single_max_tokens) = self.get_translation_chunk_size();
+        let estimated_tokens = Self::estimate_tokens(content);
+        let chunks = if estimated_tokens < single_max_tokens {
+            vec![content.to_string()]
+        } else {
+            Self::split_content_for_translation(content, chunk_chars)
+        };
+
+        let chunks: Vec<ChunkPlan> = chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| ChunkPlan {
+                index,
+                chars: chunk.chars().count(),
+                estimated_tokens: Self::estimate_tokens(chunk),
+                preview: truncate_chars(&chunk.trim().replace('\n', " "), PREVIEW_CHARS),
+            })
+            .collect();
+
+        TranslationPlan {
+            chunk_count: chunks.len(),
+            estimated_tokens,
+            chunks,
+        }
+    }
+
+    /// Translate starting at chunk `start_chunk`, keeping everything translated before a failure
+    /// so the caller can show partial output and retry from the failed chunk.
+    pub async fn translate_from(
+        &self,
+        content: &str,
+        target_lang: &str,
+        start_chunk: usize,
+    ) -> TranslationOutcome {
+        let (chunk_chars, single_max_tokens) = self.get_translation_chunk_size();
+        let estimated_tokens = Self::estimate_tokens(content);
+
+        // If content is small enough, translate in one go
+        if estimated_tokens < single_max_tokens {
+            return match self.translate_chunk(content, target_lang, 1, 1).await {
+                Ok(translated) => TranslationOutcome {
+                    translated,
+                    total_chunks: 1,
+                    completed_chunks: 1,
+                    failed_chunk: None,
+                    error: None,
+                },
+                Err(e) => TranslationOutcome {
+                    translated: String::new(),
+                    total_chunks: 1,
+                    completed_chunks: 0,
+                    failed_chunk: Some(0),
+                    error: Some(e),
+                },
+            };
+        }
+
+        // Split content into manageable chunks
+        let chunks = Self::split_content_for_translation(content, chunk_chars);
+        let total_chunks = chunks.len();
+
+        let mut translated_parts = Vec::new();
+        let mut failure = None;
+
+        // Up to N chunks in flight; `buffered` yields results in input order
+        let concurrency = self.get_translation_concurrency().await;
+        let mut results = stream::iter(chunks.iter().enumerate().skip(start_chunk))
+            .map(|(i, chunk)| async move {
+                (i, self.translate_chunk(chunk, target_lang, i + 1, total_chunks).await)
+            })
+            .buffered(concurrency);
+
+        while let Some((i, result)) = results.next().await {
+            match result {
+                Ok(translated) => translated_parts.push(translated),
+                Err(e) => {
+                    // Dropping the stream cancels the chunks still in flight
+                    failure = Some((i, e));
+                    break;
+                }
+            }
+        }
+
+        let completed_chunks = translated_parts.len();
+        let (failed_chunk, error) = match failure {
+            Some((i, e)) => (Some(i), Some(e)),
+            None => (None, None),
+        };
+
+        TranslationOutcome {
+            // Join all translated parts
+            translated: translated_parts.join("\n\n"),
+            total_chunks,
+            completed_chunks,
+            failed_chunk,
+            error,
+        }
+    }
+
+    pub async fn summarize(&self, content: &str) -> Result<String, ProviderError> {
         let prompt = format!(
             "다음 내용을 핵심 포인트 중심으로 간결하게 요약해주세요. 반드시 한글로 작성해주세요.\n\n{}",
             content
         );
-        self.send_message(&prompt).await
+        self.send_message_with(&prompt, &RequestOptions::default()).await
     }
 
-    pub async fn ask_question(&self, content: &str, question: &str) -> Result<String, String> {
+    fn count_bullets(text: &str) -> usize {
+        text.lines()
+            .map(|l| l.trim_start())
+            .filter(|l| {
+                l.starts_with("- ")
+                    || l.starts_with("* ")
+                    || l.starts_with("• ")
+                    || l.split_once(". ")
+                        .map(|(n, _)| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+                        .unwrap_or(false)
+            })
+            .count()
+    }
+
+    /// Whether the summary misses the requested length by a wide margin
+    fn overshoots(summary: &str, constraint: &SummaryConstraint) -> bool {
+        if let Some(bullets) = constraint.bullets {
+            if Self::count_bullets(summary) > bullets + 1 {
+                return true;
+            }
+        }
+        if let Some(max_words) = constraint.max_words {
+            // Allow 20% slack before asking again
+            if summary.split_whitespace().count() * 5 > max_words * 6 {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub async fn summarize_formatted(&self, content: &str, format: SummaryFormat) -> Result<String, String> {
+        let instructions = match format {
+            SummaryFormat::Markdown => return self.summarize(content).await.map_err(String::from),
+            SummaryFormat::Plain => {
+                "마크다운 기호(#, -, *, ` 등) 없이 일반 문장으로만 작성해주세요."
+            }
+            SummaryFormat::Json => {
+                "다른 설명 없이 다음 형식의 JSON 객체만 출력해주세요:\n\
+                {\"title\": \"요약 제목\", \"key_points\": [\"핵심 포인트\", ...]}"
+            }
+        };
+
+        let prompt = format!(
+            "다음 내용을 핵심 포인트 중심으로 간결하게 요약해주세요. 반드시 한글로 작성해주세요.\n\
+            {}\n\n{}",
+            instructions, content
+        );
+        let summary = self.send_message(&prompt).await?;
+
+        if format != SummaryFormat::Json {
+            return Ok(summary);
+        }
+
+        let parsed = match Self::parse_structured_summary(&summary) {
+            Some(parsed) => parsed,
+            None => {
+                // One retry when the model wrapped the JSON in prose or broke the schema
+                let retry_prompt = format!(
+                    "{}\n\n이전 응답이 올바른 JSON이 아니었습니다. JSON 객체만 출력해주세요.\n\n\
+                    ## 이전 응답:\n{}",
+                    prompt, summary
+                );
+                let retry = self.send_message(&retry_prompt).await?;
+                Self::parse_structured_summary(&retry)
+                    .ok_or_else(|| "Model did not return a valid JSON summary".to_string())?
+            }
+        };
+
+        serde_json::to_string(&parsed).map_err(|e| format!("Failed to serialize summary: {}", e))
+    }
+
+    /// The outermost `{...}` in the response, tolerating code fences and surrounding prose
+    fn parse_structured_summary(text: &str) -> Option<StructuredSummary> {
+        let start = text.find('{')?;
+        let end = text.rfind('}')?;
+        if end < start {
+            return None;
+        }
+        serde_json::from_str(&text[start..=end]).ok()
+    }
+
+    pub async fn summarize_constrained(
+        &self,
+        content: &str,
+        constraint: &SummaryConstraint,
+    ) -> Result<String, String> {
+        let mut rules = Vec::new();
+        if let Some(bullets) = constraint.bullets {
+            rules.push(format!("정확히 {}개의 글머리 기호(- )로 작성해주세요.", bullets));
+        }
+        if let Some(max_words) = constraint.max_words {
+            rules.push(format!("전체 분량은 {}단어 이내로 작성해주세요.", max_words));
+        }
+        if rules.is_empty() {
+            return self.summarize(content).await.map_err(String::from);
+        }
+
         let prompt = format!(
-            "당신은 해당 분야의 전문가입니다. 다음 문서를 바탕으로 질문에 전문적이고 상세하게 답변해주세요.\n\n\
+            "다음 내용을 핵심 포인트 중심으로 간결하게 요약해주세요. 반드시 한글로 작성해주세요.\n\
+            {}\n\n{}",
+            rules.join("\n"),
+            content
+        );
+        let summary = self.send_message(&prompt).await?;
+
+        if !Self::overshoots(&summary, constraint) {
+            return Ok(summary);
+        }
+
+        // One retry with the previous attempt as a negative example
+        let retry_prompt = format!(
+            "{}\n\n이전 요약이 분량 조건을 지키지 않았습니다. 조건을 반드시 지켜서 다시 작성해주세요.\n\n\
+            ## 이전 요약:\n{}",
+            prompt, summary
+        );
+        self.send_message(&retry_prompt).await
+    }
+
+    pub async fn ask_question_with(
+        &self,
+        content: &str,
+        question: &str,
+        options: &RequestOptions,
+    ) -> Result<String, ProviderError> {
+        self.send_message_with(&Self::question_prompt(content, question, options.untrusted_content), options)
+            .await
+    }
+
+    /// `ask_question_with`, also giving the output tokens Claude reported
+    pub async fn ask_question_metered(
+        &self,
+        content: &str,
+        question: &str,
+        options: &RequestOptions,
+    ) -> Result<MeteredAnswer, ProviderError> {
+        let reply = self
+            .request_reply(
+                vec![RequestMessage {
+                    role: "user".to_string(),
+                    content: RequestContent::Text(Self::question_prompt(content, question, options.untrusted_content)),
+                }],
+                options,
+            )
+            .await?;
+        Ok(MeteredAnswer {
+            text: reply.text,
+            output_tokens: reply.output_tokens,
+        })
+    }
+
+    /// `ask_question` with extended thinking; `include_reasoning` puts the reasoning above the answer
+    pub async fn ask_question_thinking(
+        &self,
+        content: &str,
+        question: &str,
+        options: &RequestOptions,
+        include_reasoning: bool,
+    ) -> Result<String, ProviderError> {
+        let options = RequestOptions {
+            thinking_budget: Some(DEFAULT_THINKING_BUDGET),
+            ..options.clone()
+        };
+        let reply = self
+            .request_reply(
+                vec![RequestMessage {
+                    role: "user".to_string(),
+                    content: RequestContent::Text(Self::question_prompt(content, question, options.untrusted_content)),
+                }],
+                &options,
+            )
+            .await?;
+
+        match reply.thinking {
+            Some(thinking) if include_reasoning => {
+                Ok(format!("## 사고 과정\n{}\n\n---\n\n## 답변\n{}", thinking, reply.text))
+            }
+            _ => Ok(reply.text),
+        }
+    }
+
+    fn question_prompt(content: &str, question: &str, untrusted: bool) -> String {
+        format!(
+            "당신은 해당 분야의 전문가입니다. <document> 태그 안의 문서를 바탕으로 질문에 전문적이고 상세하게 답변해주세요.\n\n\
             ## 답변 가이드라인:\n\
             - 전문 용어가 있다면 쉽게 설명해주세요\n\
             - 관련 배경 지식도 함께 제공해주세요\n\
             - 실용적인 예시나 활용 방안이 있다면 포함해주세요\n\
             - 논리적인 구조로 답변을 구성해주세요\n\n\
             ## 질문:\n{}\n\n\
-            ## 참고 문서:\n{}\n\n\
+            {}## 참고 문서:\n{}\n\n\
             반드시 한글로 상세하게 답변해주세요.",
-            question, content
-        );
-        self.send_message(&prompt).await
+            question,
+            content_guard(untrusted),
+            document_block(content)
+        )
     }
 }
 
@@ -368,3 +1488,78 @@ impl Default for ClaudeClient {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(content: &str, max_chars: usize) -> Vec<String> {
+        ClaudeClient::split_content_for_translation(content, max_chars)
+    }
+
+    #[test]
+    fn error_bodies_never_carry_the_key() {
+        // A key without a recognized prefix is only caught by matching the configured key
+        let key = "proxy-key-0123456789abcdef";
+        let structured = format!(r#"{{"type": "error", "error": {{"type": "authentication_error", "message": "invalid x-api-key: {}"}}}}"#, key);
+        let plain = format!("upstream rejected {} (sk-ant-REDACTED)", key);
+        for body in [structured, plain] {
+            let message = ClaudeClient::error_message(reqwest::StatusCode::UNAUTHORIZED, &body, key);
+            assert!(!message.contains(key), "key leaked: {}", message);
+            assert!(!message.contains("AbCdEf0123456789"), "key leaked: {}", message);
+        }
+    }
+
+    #[test]
+    fn align_translation_gives_each_chunk_its_paragraphs() {
+        let chunks = split("aaaa\n\nbbbb\n\ncccc", 5);
+        assert_eq!(chunks.len(), 3);
+        let aligned = ClaudeClient::align_translation("AAAAAA\n\nBBBBBB\n\nCCCCCC", &chunks);
+        assert_eq!(aligned, vec!["AAAAAA", "BBBBBB", "CCCCCC"]);
+    }
+
+    #[test]
+    fn split_packs_whole_paragraphs_up_to_the_limit() {
+        assert_eq!(split("aaaa\n\nbbbb\n\ncccc", 10), vec!["aaaa\n\nbbbb", "cccc"]);
+    }
+
+    #[test]
+    fn split_breaks_long_paragraphs_at_sentence_ends() {
+        assert_eq!(split("One. Two. Three.", 10), vec!["One. Two.", " Three."]);
+        assert_eq!(split("가나다。라마바。", 4), vec!["가나다。", "라마바。"]);
+    }
+
+    #[test]
+    fn split_counts_characters_not_bytes() {
+        // 12 Hangul syllables are 36 bytes but fit a 12-character chunk
+        let korean = "가나다라마바사아자차카타";
+        assert_eq!(split(korean, 12), vec![korean]);
+        let paragraphs = format!("{}\n\n{}", korean, korean);
+        assert_eq!(split(&paragraphs, 26), vec![paragraphs.clone()]);
+        assert_eq!(split(&paragraphs, 25), vec![korean, korean]);
+    }
+
+    #[test]
+    fn split_keeps_an_oversized_sentence_whole() {
+        let long = "x".repeat(30);
+        assert_eq!(split(&format!("Hi. {}", long), 10), vec!["Hi.".to_string(), format!(" {}", long)]);
+    }
+
+    #[test]
+    fn split_loses_no_text_and_respects_the_limit() {
+        let content = (0..40)
+            .map(|i| format!("Paragraph {} has a sentence. And another one here.", i))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let chunks = split(&content, 200);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.chars().count() <= 200));
+        let squash = |s: &str| s.split_whitespace().collect::<String>();
+        assert_eq!(squash(&chunks.concat()), squash(&content));
+    }
+
+    #[test]
+    fn split_of_empty_content_is_one_empty_chunk() {
+        assert_eq!(split("", 100), vec![""]);
+    }
+}
@@ -1,11 +1,34 @@
-use crate::ai_clients::AiClients;
-use crate::claude::ClaudeClient;
-use crate::mcp::{DatabaseInfo, McpClient, RecentDatabase};
-use crate::web::WebClient;
-use serde::Deserialize;
+use crate::ai_clients::{
+    content_guard, AiClients, AiConfig, ImageInput, OpenAiFunction, OpenAiMessage, OpenAiToolReply, ProviderError,
+    RequestOptions,
+};
+use crate::claude::{
+    ClaudeClient, Config as ClaudeConfig, SummaryConstraint, SummaryFormat, TranslationOutcome,
+    TranslationPlan,
+};
+use crate::breaker::BreakerStatus;
+use crate::config_file;
+use crate::content_cache::CacheListing;
+use crate::distill::DistillCache;
+use crate::mcp::{
+    Config as NotionConfig, DatabaseInfo, McpClient, NotionBackend, NotionTokenStatus,
+    PageInspection, RecentDatabase, SaveTarget, SearchObjectType,
+};
+use crate::operations::{Operation, OperationKind, OperationLog, OperationParams};
+use crate::preferences::{self, UiPreferences};
+use crate::seen_content::{self, SeenContent};
+use crate::sessions::SessionStore;
+use crate::templates::{self, PromptTemplate};
+use crate::text_util::{self, truncate_chars, AlignedSegment, ExtractedReferences};
+use crate::vault::{self, VaultFile};
+use crate::web::{CheckedContent, ContentSelectors, FeedEntry, WebClient, WebConfig};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::ipc::Channel;
 use tauri::State;
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ConversationMessage {
     pub role: String,
     pub content: String,
@@ -46,6 +69,14 @@ pub async fn get_notion_token(mcp: State<'_, McpClient>) -> Result<Option<String
     Ok(mcp.get_notion_token().await)
 }
 
+#[tauri::command]
+pub async fn verify_notion_token(
+    mcp: State<'_, McpClient>,
+    token: Option<String>,
+) -> Result<NotionTokenStatus, String> {
+    mcp.verify_notion_token(token).await
+}
+
 #[tauri::command]
 pub async fn connect_mcp(
     mcp: State<'_, McpClient>,
@@ -61,6 +92,19 @@ pub async fn disconnect_mcp(mcp: State<'_, McpClient>) -> Result<(), String> {
     mcp.disconnect().await
 }
 
+/// Seconds without Notion requests before the MCP server is stopped; 0 keeps it running
+#[tauri::command]
+pub fn get_mcp_idle_timeout(mcp: State<'_, McpClient>) -> Result<u64, String> {
+    Ok(mcp.get_idle_timeout())
+}
+
+/// 0 disables the idle disconnect; otherwise the server is stopped after `secs` idle seconds
+/// and started again with the same command on the next request
+#[tauri::command]
+pub fn set_mcp_idle_timeout(mcp: State<'_, McpClient>, secs: u64) -> Result<(), String> {
+    mcp.set_idle_timeout(secs)
+}
+
 #[tauri::command]
 pub async fn is_mcp_connected(mcp: State<'_, McpClient>) -> Result<bool, String> {
     Ok(mcp.is_connected().await)
@@ -71,418 +115,3705 @@ pub async fn fetch_notion_page(mcp: State<'_, McpClient>, page_url: String) -> R
     mcp.fetch_notion_page(&page_url).await
 }
 
+/// Block type counts and skipped blocks, for debugging incomplete page content
 #[tauri::command]
-pub async fn fetch_web_page(web: State<'_, WebClient>, url: String) -> Result<String, String> {
-    web.fetch_page(&url).await
-}
-
-/// Determine if URL is a Notion page or regular web page
-fn is_notion_url(url: &str) -> bool {
-    url.contains("notion.so") || url.contains("notion.site")
+pub async fn inspect_notion_page(mcp: State<'_, McpClient>, page_url: String) -> Result<PageInspection, String> {
+    mcp.inspect_notion_page(&page_url).await
 }
 
 #[tauri::command]
-pub async fn translate(
-    mcp: State<'_, McpClient>,
+pub async fn fetch_web_page(
     web: State<'_, WebClient>,
-    claude: State<'_, ClaudeClient>,
-    page_url: String,
-    target_language: String,
+    url: String,
+    headers: Option<HashMap<String, String>>,
 ) -> Result<String, String> {
-    let content = if is_notion_url(&page_url) {
-        mcp.fetch_notion_page(&page_url).await?
-    } else {
-        web.fetch_page(&page_url).await?
-    };
-    claude.translate(&content, &target_language).await
+    web.fetch_page_with_headers(&url, &headers.unwrap_or_default())
+        .await
+}
+
+#[derive(Serialize)]
+pub struct FetchSettings {
+    pub retries: u32,
+    pub timeout_secs: u64,
 }
 
 #[tauri::command]
-pub async fn summarize(
-    mcp: State<'_, McpClient>,
-    web: State<'_, WebClient>,
-    claude: State<'_, ClaudeClient>,
-    page_url: String,
-) -> Result<String, String> {
-    let content = if is_notion_url(&page_url) {
-        mcp.fetch_notion_page(&page_url).await?
-    } else {
-        web.fetch_page(&page_url).await?
-    };
-    claude.summarize(&content).await
+pub fn get_fetch_settings(web: State<'_, WebClient>) -> Result<FetchSettings, String> {
+    let (retries, timeout_secs) = web.get_fetch_settings();
+    Ok(FetchSettings {
+        retries,
+        timeout_secs,
+    })
 }
 
 #[tauri::command]
-pub async fn ask_question(
-    mcp: State<'_, McpClient>,
+pub fn set_fetch_settings(
     web: State<'_, WebClient>,
-    claude: State<'_, ClaudeClient>,
-    page_url: String,
-    question: String,
-) -> Result<String, String> {
-    let content = if is_notion_url(&page_url) {
-        mcp.fetch_notion_page(&page_url).await?
-    } else {
-        web.fetch_page(&page_url).await?
-    };
-    claude.ask_question(&content, &question).await
+    retries: u32,
+    timeout_secs: u64,
+) -> Result<(), String> {
+    web.set_fetch_settings(retries, timeout_secs)
 }
 
 #[tauri::command]
-pub async fn set_database_id(mcp: State<'_, McpClient>, database_id: String) -> Result<(), String> {
-    mcp.set_database_id(database_id).await
+pub fn get_content_selectors() -> Result<ContentSelectors, String> {
+    Ok(WebClient::get_content_selectors())
 }
 
+/// CSS selectors for the main content of pages: for one `host` when given, otherwise the
+/// general list. An empty list drops the host override or restores the built-in defaults.
 #[tauri::command]
-pub async fn load_database_id(mcp: State<'_, McpClient>) -> Result<Option<String>, String> {
-    Ok(mcp.load_database_id().await)
+pub async fn set_content_selectors(web: State<'_, WebClient>, selectors: Vec<String>, host: Option<String>) -> Result<(), String> {
+    web.set_content_selectors(host.as_deref(), selectors).await
 }
 
 #[tauri::command]
-pub async fn save_to_notion(
-    mcp: State<'_, McpClient>,
-    database_id: String,
-    title: String,
-    content: String,
-    source_url: String,
-) -> Result<String, String> {
-    mcp.save_to_notion(&database_id, &title, &content, &source_url).await
+pub fn get_guard_fetched_content(web: State<'_, WebClient>) -> Result<bool, String> {
+    Ok(web.get_guard_fetched_content())
 }
 
 #[tauri::command]
-pub async fn search_databases(
-    mcp: State<'_, McpClient>,
-    query: String,
-) -> Result<Vec<DatabaseInfo>, String> {
-    mcp.search_databases(&query).await
+pub fn set_guard_fetched_content(web: State<'_, WebClient>, enabled: bool) -> Result<(), String> {
+    web.set_guard_fetched_content(enabled)
 }
 
 #[tauri::command]
-pub fn load_recent_databases() -> Result<Vec<RecentDatabase>, String> {
-    Ok(McpClient::load_recent_databases())
+pub fn get_strip_injection_lines(web: State<'_, WebClient>) -> Result<bool, String> {
+    Ok(web.get_strip_injection_lines())
 }
 
 #[tauri::command]
-pub fn add_recent_database(id: String, name: String) -> Result<(), String> {
-    McpClient::add_recent_database(RecentDatabase { id, name })
+pub fn set_strip_injection_lines(web: State<'_, WebClient>, enabled: bool) -> Result<(), String> {
+    web.set_strip_injection_lines(enabled)
 }
 
-#[tauri::command]
-pub async fn list_mcp_tools(mcp: State<'_, McpClient>) -> Result<Vec<String>, String> {
-    mcp.list_tools().await
+/// A document prepared for a question, and the options to ask with. Every document Q&A path goes
+/// through here. With the guard on, the prompt tells the model the `<document>` block is data;
+/// lines with a blatant injection phrase are only dropped when that is turned on as well.
+/// Best-effort: a page written to steer the model can still get through.
+fn guarded_content(web: &WebClient, content: String) -> (String, RequestOptions) {
+    let options = RequestOptions {
+        untrusted_content: web.get_guard_fetched_content(),
+        ..Default::default()
+    };
+    if web.get_strip_injection_lines() {
+        return (text_util::strip_injection_phrases(&content), options);
+    }
+    (content, options)
 }
 
-// OpenAI Commands
-#[tauri::command]
-pub async fn set_openai_key(ai: State<'_, AiClients>, api_key: String) -> Result<(), String> {
-    ai.set_openai_key(api_key).await
+/// A bare Notion page id: 32 hex chars or a dashed UUID
+fn is_notion_page_id(input: &str) -> bool {
+    let is_hex = |s: &str| s.chars().all(|c| c.is_ascii_hexdigit());
+    match input.len() {
+        32 => is_hex(input),
+        36 => {
+            let parts: Vec<&str> = input.split('-').collect();
+            parts.iter().map(|p| p.len()).eq([8, 4, 4, 4, 12]) && parts.iter().all(|p| is_hex(p))
+        }
+        _ => false,
+    }
 }
 
-#[tauri::command]
-pub async fn load_openai_key(ai: State<'_, AiClients>) -> Result<Option<String>, String> {
-    Ok(ai.load_openai_key().await)
+/// Determine if URL is a Notion page or regular web page.
+/// `extra_hosts` are user-configured custom domains that proxy Notion.
+fn is_notion_url(url: &str, extra_hosts: &[String]) -> bool {
+    let url = url.trim();
+    if is_notion_page_id(url) {
+        return true;
+    }
+
+    let host = match reqwest::Url::parse(url) {
+        Ok(parsed) => parsed.host_str().unwrap_or_default().to_lowercase(),
+        Err(_) => return url.contains("notion.so") || url.contains("notion.site"),
+    };
+
+    let matches_host = |domain: &str| host == domain || host.ends_with(&format!(".{}", domain));
+
+    matches_host("notion.so")
+        || matches_host("notion.site")
+        || extra_hosts
+            .iter()
+            .map(|h| h.trim().to_lowercase())
+            .any(|h| !h.is_empty() && matches_host(&h))
 }
 
-#[tauri::command]
-pub async fn get_openai_key(ai: State<'_, AiClients>) -> Result<Option<String>, String> {
-    Ok(ai.get_openai_key().await)
+/// Fetch a URL through Notion or the web scraper depending on what it points at.
+/// Content is cached for a few minutes, so repeated commands on one page fetch it once.
+async fn fetch_url_content(mcp: &McpClient, web: &WebClient, url: &str) -> Result<String, String> {
+    fetch_url_content_with(mcp, web, url, true).await
 }
 
-#[tauri::command]
-pub async fn clear_openai_key(ai: State<'_, AiClients>) -> Result<(), String> {
-    ai.clear_openai_key().await
+/// `fetch_url_content` that goes to the source when `use_cache` is false; the fresh
+/// content still replaces what was cached
+async fn fetch_url_content_with(
+    mcp: &McpClient,
+    web: &WebClient,
+    url: &str,
+    use_cache: bool,
+) -> Result<String, String> {
+    if use_cache {
+        if let Some(cached) = web.content_cache().get(url).await {
+            return Ok(cached);
+        }
+    }
+
+    let content = if is_notion_url(url, &McpClient::load_notion_hosts()) {
+        mcp.fetch_notion_page(url).await?
+    } else {
+        web.fetch_page(url).await?
+    };
+    web.content_cache().insert(url, &content).await;
+    Ok(content)
 }
 
+/// Extracted text of a page or URL without invoking any model. `bypass_cache` fetches it
+/// again instead of using a copy cached by an earlier command.
 #[tauri::command]
-pub async fn ask_question_openai(
+pub async fn fetch_content(
     mcp: State<'_, McpClient>,
     web: State<'_, WebClient>,
-    ai: State<'_, AiClients>,
-    page_url: String,
-    question: String,
+    url: String,
+    bypass_cache: Option<bool>,
 ) -> Result<String, String> {
-    let content = if is_notion_url(&page_url) {
-        mcp.fetch_notion_page(&page_url).await?
-    } else {
-        web.fetch_page(&page_url).await?
-    };
-    ai.ask_openai(&content, &question).await
+    fetch_url_content_with(&mcp, &web, &url, !bypass_cache.unwrap_or(false)).await
 }
 
-// Gemini Commands
+/// Fetch a page and flag content that is likely a stub (cookie wall, "enable JavaScript",
+/// mostly links), so the UI can suggest another URL or pasting the text instead
 #[tauri::command]
-pub async fn set_gemini_key(ai: State<'_, AiClients>, api_key: String) -> Result<(), String> {
-    ai.set_gemini_key(api_key).await
+pub async fn content_quality_check(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
+    url: String,
+) -> Result<CheckedContent, String> {
+    if is_notion_url(&url, &McpClient::load_notion_hosts()) {
+        let content = fetch_url_content(&mcp, &web, &url).await?;
+        let quality = WebClient::check_quality(&content, None);
+        return Ok(CheckedContent { content, quality });
+    }
+
+    let checked = web.fetch_page_checked(&url).await?;
+    web.content_cache().insert(&url, &checked.content).await;
+    Ok(checked)
 }
 
+/// Cached pages with their size and age, plus the total size of the cache
 #[tauri::command]
-pub async fn load_gemini_key(ai: State<'_, AiClients>) -> Result<Option<String>, String> {
-    Ok(ai.load_gemini_key().await)
+pub async fn list_cache_entries(web: State<'_, WebClient>) -> Result<CacheListing, String> {
+    Ok(web.content_cache().list().await)
 }
 
+/// Drop one cached page so the next request fetches it again; false when it wasn't cached
 #[tauri::command]
-pub async fn get_gemini_key(ai: State<'_, AiClients>) -> Result<Option<String>, String> {
-    Ok(ai.get_gemini_key().await)
+pub async fn evict_cache_entry(web: State<'_, WebClient>, url: String) -> Result<bool, String> {
+    Ok(web.content_cache().evict(&url).await)
 }
 
 #[tauri::command]
-pub async fn clear_gemini_key(ai: State<'_, AiClients>) -> Result<(), String> {
-    ai.clear_gemini_key().await
+pub async fn clear_content_cache(web: State<'_, WebClient>) -> Result<(), String> {
+    web.content_cache().clear().await;
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct SourceError {
+    pub url: String,
+    pub error: String,
+}
+
+#[derive(Serialize)]
+pub struct MergedContent {
+    pub content: String,
+    pub failed: Vec<SourceError>,
+}
+
+/// Fetch several URLs (a few at a time) and join them under `## Source:` headings.
+/// Sources that fail are reported instead of failing the whole merge.
+async fn merge_sources(mcp: &McpClient, web: &WebClient, urls: &[String]) -> Result<MergedContent, String> {
+    use futures::stream::{self, StreamExt};
+
+    const SOURCE_CONCURRENCY: usize = 3;
+
+    let urls: Vec<&str> = urls.iter().map(|u| u.trim()).filter(|u| !u.is_empty()).collect();
+    if urls.is_empty() {
+        return Err("No URLs given".to_string());
+    }
+
+    let results = stream::iter(urls)
+        .map(|url| async move { (url, fetch_url_content(mcp, web, url).await) })
+        .buffered(SOURCE_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut sections = Vec::new();
+    let mut failed = Vec::new();
+    for (url, result) in results {
+        match result {
+            Ok(content) => sections.push(format!("## Source: {}\n\n{}", url, content.trim())),
+            Err(error) => failed.push(SourceError {
+                url: url.to_string(),
+                error,
+            }),
+        }
+    }
+
+    if sections.is_empty() {
+        return Err(format!(
+            "Could not fetch any of the sources: {}",
+            failed
+                .iter()
+                .map(|f| format!("{} ({})", f.url, f.error))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    Ok(MergedContent {
+        content: sections.join("\n\n---\n\n"),
+        failed,
+    })
 }
 
 #[tauri::command]
-pub async fn ask_question_gemini(
+pub async fn fetch_and_merge(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
+    urls: Vec<String>,
+) -> Result<MergedContent, String> {
+    merge_sources(&mcp, &web, &urls).await
+}
+
+#[derive(Serialize)]
+pub struct CrossSourceAnswer {
+    pub answer: String,
+    pub failed: Vec<SourceError>,
+}
+
+/// Fetch and merge several sources, then answer one question over all of them
+#[tauri::command]
+pub async fn ask_across_sources(
     mcp: State<'_, McpClient>,
     web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
     ai: State<'_, AiClients>,
-    page_url: String,
+    urls: Vec<String>,
     question: String,
-) -> Result<String, String> {
-    let content = if is_notion_url(&page_url) {
-        mcp.fetch_notion_page(&page_url).await?
-    } else {
-        web.fetch_page(&page_url).await?
+    model: String,
+) -> Result<CrossSourceAnswer, String> {
+    let merged = merge_sources(&mcp, &web, &urls).await?;
+    let (content, options) = guarded_content(&web, merged.content);
+
+    let answer = match model.as_str() {
+        "claude" => claude.ask_question_with(&content, &question, &options).await?,
+        "openai" => ai.ask_openai_with(&content, &question, &options).await?,
+        "gemini" => ai.ask_gemini_with(&content, &question, &options).await?,
+        _ => return Err(format!("Unknown model: {}", model)),
     };
-    ai.ask_gemini(&content, &question).await
+
+    Ok(CrossSourceAnswer {
+        answer,
+        failed: merged.failed,
+    })
 }
 
-// File Export Command
+// Operation history
+const DEFAULT_RECENT_OPERATIONS: usize = 10;
+
+/// Recent summarize/ask/translate calls, newest first, with the inputs needed to re-run them
 #[tauri::command]
-pub async fn export_notes_to_file(
-    file_path: String,
-    content: String,
-) -> Result<(), String> {
-    std::fs::write(&file_path, &content)
-        .map_err(|e| format!("Failed to write file: {}", e))
+pub async fn list_recent_operations(
+    log: State<'_, OperationLog>,
+    limit: Option<usize>,
+) -> Result<Vec<Operation>, String> {
+    Ok(log.recent(limit.unwrap_or(DEFAULT_RECENT_OPERATIONS)).await)
 }
 
-// Content-based API Commands (no URL fetch needed)
+/// Summary as the summarize commands produce it: Claude's own prompts unless another provider
+/// or a specific model is asked for. Formats other than markdown are Claude's only.
+async fn run_summarize(
+    claude: &ClaudeClient,
+    ai: &AiClients,
+    params: &OperationParams,
+    content: &str,
+) -> Result<String, String> {
+    let provider = params.provider.as_deref().unwrap_or("claude");
+    let format = params.output_format.unwrap_or_default();
+    if format != SummaryFormat::Markdown {
+        if provider != "claude" || params.model.is_some() {
+            return Err("Plain and JSON summaries are only available with Claude's configured model".to_string());
+        }
+        return claude.summarize_formatted(content, format).await;
+    }
+    let options = RequestOptions {
+        model: params.model.clone(),
+        ..Default::default()
+    };
+    Ok(summarize_with_model(claude, ai, provider, content, &options).await?)
+}
+
+/// Answer as the ask commands produce it; `options` carries the fetched-content guard, if any
+async fn run_ask(
+    claude: &ClaudeClient,
+    ai: &AiClients,
+    params: &OperationParams,
+    content: &str,
+    options: RequestOptions,
+) -> Result<String, String> {
+    let question = params.question.as_deref().ok_or("Operation has no question")?;
+    let options = RequestOptions {
+        model: params.model.clone(),
+        ..options
+    };
+    let answer = match params.provider.as_deref().unwrap_or("claude") {
+        "claude" if params.thinking == Some(true) => {
+            let include_reasoning = params.include_reasoning.unwrap_or(false);
+            claude
+                .ask_question_thinking(content, question, &options, include_reasoning)
+                .await?
+        }
+        "claude" => claude.ask_question_with(content, question, &options).await?,
+        "openai" => ai.ask_openai_with(content, question, &options).await?,
+        "gemini" => ai.ask_gemini_with(content, question, &options).await?,
+        provider => return Err(format!("Unknown model: {}", provider)),
+    };
+    Ok(answer)
+}
+
+/// Translation as the translate commands produce it; only Claude's configured model translates
+async fn run_translate(claude: &ClaudeClient, params: &OperationParams, content: &str) -> Result<String, String> {
+    if params.provider.as_deref().unwrap_or("claude") != "claude" || params.model.is_some() {
+        return Err("Translation is only available with Claude's configured model".to_string());
+    }
+    let target_language = params.target_language.as_deref().ok_or("Operation has no target language")?;
+    translate_maybe_skipping(claude, content, target_language, params.skip_target_language).await
+}
+
+/// Run a recorded operation again, e.g. the same URL with another provider; fields set in
+/// `overrides` replace the recorded ones. URLs are fetched again (subject to the content cache).
+/// Runs through the same code as the original command and is logged with the provider it ran on.
 #[tauri::command]
-pub async fn translate_content(
+#[allow(clippy::too_many_arguments)]
+pub async fn rerun_operation(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
     claude: State<'_, ClaudeClient>,
-    content: String,
-    target_language: String,
+    ai: State<'_, AiClients>,
+    log: State<'_, OperationLog>,
+    id: String,
+    overrides: Option<OperationParams>,
 ) -> Result<String, String> {
-    claude.translate(&content, &target_language).await
+    let operation = log.get(&id).await?;
+    let mut params = operation.params.merged(&overrides.unwrap_or_default());
+    params.provider.get_or_insert_with(|| "claude".to_string());
+
+    let content = match (&params.url, &operation.content) {
+        (Some(url), _) => fetch_url_content(&mcp, &web, url).await?,
+        (None, Some(content)) => content.clone(),
+        (None, None) => return Err("Operation has no URL or content to re-run".to_string()),
+    };
+
+    let result = match operation.kind {
+        OperationKind::Summarize => run_summarize(&claude, &ai, &params, &content).await?,
+        OperationKind::Ask => {
+            let (content, options) = guarded_content(&web, content);
+            run_ask(&claude, &ai, &params, &content, options).await?
+        }
+        OperationKind::Translate => run_translate(&claude, &params, &content).await?,
+    };
+
+    log.record(operation.kind, params, operation.content.as_deref()).await;
+    Ok(result)
 }
 
+/// `skip_target_language` works as in `translate_content`, `bypass_cache` as in `fetch_content`
 #[tauri::command]
-pub async fn summarize_content(
+#[allow(clippy::too_many_arguments)]
+pub async fn translate(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
     claude: State<'_, ClaudeClient>,
-    content: String,
+    log: State<'_, OperationLog>,
+    page_url: String,
+    target_language: String,
+    skip_target_language: Option<bool>,
+    bypass_cache: Option<bool>,
 ) -> Result<String, String> {
-    claude.summarize(&content).await
+    let content = fetch_url_content_with(&mcp, &web, &page_url, !bypass_cache.unwrap_or(false)).await?;
+    let params = OperationParams {
+        url: Some(page_url),
+        provider: Some("claude".to_string()),
+        target_language: Some(target_language),
+        skip_target_language,
+        ..Default::default()
+    };
+    let translated = run_translate(&claude, &params, &content).await?;
+    log.record(OperationKind::Translate, params, None).await;
+    Ok(translated)
 }
 
+/// URL counterpart of `translate_content_resumable`: keeps the chunks translated before a
+/// failure; pass `start_chunk` (the previous `failed_chunk`) to retry the remaining portion
 #[tauri::command]
-pub async fn ask_claude_content(
+#[allow(clippy::too_many_arguments)]
+pub async fn translate_resumable(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
     claude: State<'_, ClaudeClient>,
-    content: String,
-    question: String,
-) -> Result<String, String> {
-    claude.ask_question(&content, &question).await
+    log: State<'_, OperationLog>,
+    page_url: String,
+    target_language: String,
+    start_chunk: Option<usize>,
+    bypass_cache: Option<bool>,
+) -> Result<TranslationOutcome, String> {
+    let content = fetch_url_content_with(&mcp, &web, &page_url, !bypass_cache.unwrap_or(false)).await?;
+    let outcome = claude
+        .translate_from(&content, &target_language, start_chunk.unwrap_or(0))
+        .await;
+    if outcome.error.is_none() {
+        let params = OperationParams {
+            url: Some(page_url),
+            provider: Some("claude".to_string()),
+            target_language: Some(target_language),
+            ..Default::default()
+        };
+        log.record(OperationKind::Translate, params, None).await;
+    }
+    Ok(outcome)
 }
 
+/// Fetch, translate and save the translation to Notion in one call; returns the created page id.
+/// `skip_target_language` works as in `translate_content`.
 #[tauri::command]
-pub async fn ask_openai_content(
-    ai: State<'_, AiClients>,
-    content: String,
-    question: String,
+#[allow(clippy::too_many_arguments)]
+pub async fn translate_and_save(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
+    page_url: String,
+    target_language: String,
+    database_id: String,
+    title: String,
+    skip_target_language: Option<bool>,
+    bypass_cache: Option<bool>,
 ) -> Result<String, String> {
-    ai.ask_openai(&content, &question).await
+    let content = fetch_url_content_with(&mcp, &web, &page_url, !bypass_cache.unwrap_or(false)).await?;
+    let translated = translate_maybe_skipping(&claude, &content, &target_language, skip_target_language).await?;
+
+    let title = if title.trim().is_empty() {
+        first_line_title(&translated)
+    } else {
+        truncate_title(&title, NOTION_TEXT_LIMIT)
+    };
+    mcp.save_to_notion(&database_id, &title, &translated, &page_url).await
 }
 
+/// Translate a Notion page and save the result as a new page under `destination`
+/// (a database or a parent page); returns the new page id. `skip_target_language` works as
+/// in `translate_content`.
 #[tauri::command]
-pub async fn ask_gemini_content(
-    ai: State<'_, AiClients>,
-    content: String,
-    question: String,
+pub async fn translate_notion_page(
+    mcp: State<'_, McpClient>,
+    claude: State<'_, ClaudeClient>,
+    page_url: String,
+    target_language: String,
+    destination: String,
+    skip_target_language: Option<bool>,
 ) -> Result<String, String> {
-    ai.ask_gemini(&content, &question).await
+    let (original_title, content) = mcp.fetch_notion_page_with_title(&page_url).await?;
+    let translated = translate_maybe_skipping(&claude, &content, &target_language, skip_target_language).await?;
+
+    let title = truncate_title(
+        &format!("[번역] {}", original_title.as_deref().unwrap_or("Untitled")),
+        NOTION_TEXT_LIMIT,
+    );
+    mcp.save_under_parent(&destination, &title, &translated).await
 }
 
-// Follow-up question with conversation history
 #[tauri::command]
-pub async fn ask_with_history(
+pub async fn summarize(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
     claude: State<'_, ClaudeClient>,
     ai: State<'_, AiClients>,
-    model: String,
-    messages: Vec<ConversationMessage>,
-    content: String,
+    log: State<'_, OperationLog>,
+    page_url: String,
+    bypass_cache: Option<bool>,
 ) -> Result<String, String> {
-    // Build messages with context
-    let system_prompt = format!(
-        "당신은 해당 분야의 전문가입니다. 다음 문서를 바탕으로 질문에 전문적이고 상세하게 답변해주세요.\n\n\
-        ## 답변 가이드라인:\n\
-        - 전문 용어가 있다면 쉽게 설명해주세요\n\
-        - 관련 배경 지식도 함께 제공해주세요\n\
-        - 실용적인 예시나 활용 방안이 있다면 포함해주세요\n\
-        - 논리적인 구조로 답변을 구성해주세요\n\
-        - 이전 대화 맥락을 고려해서 답변해주세요\n\n\
-        ## 참고 문서:\n{}\n\n\
-        반드시 한글로 상세하게 답변해주세요.",
+    let content = fetch_url_content_with(&mcp, &web, &page_url, !bypass_cache.unwrap_or(false)).await?;
+    let params = OperationParams {
+        url: Some(page_url),
+        provider: Some("claude".to_string()),
+        ..Default::default()
+    };
+    let summary = run_summarize(&claude, &ai, &params, &content).await?;
+    log.record(OperationKind::Summarize, params, None).await;
+    Ok(summary)
+}
+
+/// Summary with the chosen provider; Claude's own summary prompt is used unless a specific model is requested
+async fn summarize_with_model(
+    claude: &ClaudeClient,
+    ai: &AiClients,
+    model: &str,
+    content: &str,
+    options: &RequestOptions,
+) -> Result<String, ProviderError> {
+    if model == "claude" && options.model.is_none() {
+        return claude.summarize(content).await;
+    }
+    let prompt = format!(
+        "다음 내용을 핵심 포인트 중심으로 간결하게 요약해주세요. 반드시 한글로 작성해주세요.\n\n{}",
         content
     );
+    send_prompt_with(claude, ai, model, &prompt, options).await
+}
 
-    match model.as_str() {
-        "claude" => {
-            let mut claude_messages: Vec<crate::claude::Message> = Vec::new();
+#[derive(Serialize)]
+pub struct CheckedResult {
+    pub result: String,
+    /// The content hashed the same as on the last run, so `result` is the stored one
+    pub unchanged: bool,
+    pub content_hash: String,
+}
 
-            // Add system context as first user message
-            claude_messages.push(crate::claude::Message {
-                role: "user".to_string(),
-                content: system_prompt.clone(),
-            });
-            claude_messages.push(crate::claude::Message {
-                role: "assistant".to_string(),
-                content: "네, 문서를 이해했습니다. 질문해주세요.".to_string(),
-            });
+/// Polling helper: hash the fetched content and return the stored result when it matches the
+/// last run under `key`; otherwise run `process` and remember its result with the new hash
+async fn process_if_changed<F, Fut, E>(key: &str, content: &str, process: F) -> Result<CheckedResult, String>
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = Result<String, E>>,
+    String: From<E>,
+{
+    let hash = text_util::content_hash(content);
+    if let Some(seen) = seen_content::last_seen(key).filter(|seen| seen.hash == hash) {
+        return Ok(CheckedResult {
+            result: seen.result,
+            unchanged: true,
+            content_hash: hash,
+        });
+    }
 
-            // Add conversation history
-            for msg in messages {
-                claude_messages.push(crate::claude::Message {
-                    role: msg.role,
-                    content: msg.content,
-                });
-            }
+    let result = process().await?;
+    seen_content::remember(key, hash.clone(), result.clone())?;
+    Ok(CheckedResult {
+        result,
+        unchanged: false,
+        content_hash: hash,
+    })
+}
 
-            claude.send_messages(claude_messages).await
-        }
+/// `summarize` that skips the model call when the page hasn't changed since the last check.
+/// The page is always fetched fresh: a cached copy would hide the change being checked for.
+#[tauri::command]
+pub async fn summarize_if_changed(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
+    page_url: String,
+) -> Result<CheckedResult, String> {
+    let content = fetch_url_content_with(&mcp, &web, &page_url, false).await?;
+    let key = format!("summarize {}", page_url.trim());
+    process_if_changed(&key, &content, || claude.summarize(&content)).await
+}
+
+/// `translate` that skips the model call when the page hasn't changed since the last check;
+/// fetched fresh like `summarize_if_changed`
+#[tauri::command]
+pub async fn translate_if_changed(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
+    page_url: String,
+    target_language: String,
+) -> Result<CheckedResult, String> {
+    let content = fetch_url_content_with(&mcp, &web, &page_url, false).await?;
+    let key = format!("translate {} {}", target_language.trim(), page_url.trim());
+    process_if_changed(&key, &content, || claude.translate(&content, &target_language)).await
+}
+
+#[derive(Serialize)]
+pub struct SavedSummary {
+    pub page_id: String,
+    pub summary: String,
+}
+
+/// "Capture this page": fetch, summarize with the chosen model and save the summary to Notion
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn summarize_and_save(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    page_url: String,
+    database_id: String,
+    title: Option<String>,
+    model: String,
+    bypass_cache: Option<bool>,
+) -> Result<SavedSummary, String> {
+    let content = fetch_url_content_with(&mcp, &web, &page_url, !bypass_cache.unwrap_or(false)).await?;
+
+    let summary = summarize_with_model(&claude, &ai, &model, &content, &RequestOptions::default()).await?;
+
+    let title = match title.as_deref().map(str::trim) {
+        Some(title) if !title.is_empty() => truncate_title(title, NOTION_TEXT_LIMIT),
+        _ => generate_title(&claude, &ai, &summary).await,
+    };
+
+    let page_id = mcp.save_to_notion(&database_id, &title, &summary, &page_url).await?;
+    Ok(SavedSummary { page_id, summary })
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn ask_question(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    log: State<'_, OperationLog>,
+    page_url: String,
+    question: String,
+    bypass_cache: Option<bool>,
+) -> Result<String, String> {
+    let content = fetch_url_content_with(&mcp, &web, &page_url, !bypass_cache.unwrap_or(false)).await?;
+    let (content, options) = guarded_content(&web, content);
+    let params = OperationParams {
+        url: Some(page_url),
+        provider: Some("claude".to_string()),
+        question: Some(question),
+        ..Default::default()
+    };
+    let answer = run_ask(&claude, &ai, &params, &content, options).await?;
+    log.record(OperationKind::Ask, params, None).await;
+    Ok(answer)
+}
+
+#[tauri::command]
+pub fn load_notion_hosts() -> Result<Vec<String>, String> {
+    Ok(McpClient::load_notion_hosts())
+}
+
+#[tauri::command]
+pub fn set_notion_hosts(hosts: Vec<String>) -> Result<(), String> {
+    McpClient::set_notion_hosts(hosts)
+}
+
+#[tauri::command]
+pub async fn set_database_id(mcp: State<'_, McpClient>, database_id: String) -> Result<(), String> {
+    mcp.set_database_id(database_id).await
+}
+
+#[tauri::command]
+pub async fn load_database_id(mcp: State<'_, McpClient>) -> Result<Option<String>, String> {
+    Ok(mcp.load_database_id().await)
+}
+
+/// Named databases for the save dropdown; the save commands accept a target name wherever
+/// they take a database or parent id
+#[tauri::command]
+pub fn list_save_targets() -> Result<Vec<SaveTarget>, String> {
+    Ok(McpClient::load_save_targets())
+}
+
+/// `title_property` is only needed when the database's title field isn't called "title"
+#[tauri::command]
+pub fn add_save_target(name: String, database_id: String, title_property: Option<String>) -> Result<(), String> {
+    McpClient::add_save_target(SaveTarget {
+        name,
+        database_id,
+        title_property,
+    })
+}
+
+#[tauri::command]
+pub fn remove_save_target(name: String) -> Result<(), String> {
+    McpClient::remove_save_target(&name)
+}
+
+/// Notion rejects rich-text values longer than this
+const NOTION_TEXT_LIMIT: usize = 2000;
+
+/// Truncate a title by characters, marking the cut with an ellipsis
+fn truncate_title(text: &str, max_chars: usize) -> String {
+    truncate_chars(text.trim(), max_chars)
+}
+
+/// Cheapest provider with a key configured, used for small housekeeping prompts
+async fn cheapest_configured_model(claude: &ClaudeClient, ai: &AiClients) -> Option<&'static str> {
+    if ai.get_gemini_key().await.is_some() {
+        Some("gemini")
+    } else if ai.get_openai_key().await.is_some() {
+        Some("openai")
+    } else if claude.get_api_key().await.is_some() {
+        Some("claude")
+    } else {
+        None
+    }
+}
+
+/// Ask the cheapest configured model for a concise title, falling back to the first line of content
+async fn generate_title(claude: &ClaudeClient, ai: &AiClients, content: &str) -> String {
+    const TITLE_SOURCE_CHARS: usize = 500;
+
+    let excerpt: String = content.chars().take(TITLE_SOURCE_CHARS).collect();
+
+    if let Some(model) = cheapest_configured_model(claude, ai).await {
+        let prompt = format!(
+            "다음 문서의 내용을 대표하는 간결한 제목을 한 줄로 작성해주세요. \
+            따옴표나 설명 없이 제목만 출력해주세요.\n\n{}",
+            excerpt
+        );
+        if let Ok(title) = send_prompt(claude, ai, model, &prompt).await {
+            let title = title
+                .lines()
+                .next()
+                .unwrap_or("")
+                .trim()
+                .trim_matches('"')
+                .trim_start_matches('#')
+                .trim();
+            if !title.is_empty() {
+                return truncate_title(title, NOTION_TEXT_LIMIT);
+            }
+        }
+    }
+
+    first_line_title(content)
+}
+
+/// First non-empty line of the content, without heading markers
+fn first_line_title(content: &str) -> String {
+    let first_line = content
+        .lines()
+        .map(|l| l.trim().trim_start_matches('#').trim())
+        .find(|l| !l.is_empty())
+        .unwrap_or("Untitled");
+    truncate_title(first_line, 100)
+}
+
+/// First JSON array in the model output that parses as a list of `T`; models often wrap it
+/// in prose or a code fence
+fn parse_json_array<T: DeserializeOwned>(text: &str) -> Option<Vec<T>> {
+    text.match_indices('[').find_map(|(start, _)| {
+        let array = WebClient::extract_json_array(text, start)?;
+        serde_json::from_str::<Vec<T>>(array).ok()
+    })
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn save_to_notion(
+    mcp: State<'_, McpClient>,
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    database_id: String,
+    title: String,
+    content: String,
+    source_url: String,
+    auto_title: Option<bool>,
+) -> Result<String, String> {
+    let title = if title.trim().is_empty() && auto_title.unwrap_or(false) {
+        generate_title(&claude, &ai, &content).await
+    } else {
+        truncate_title(&title, NOTION_TEXT_LIMIT)
+    };
+    mcp.save_to_notion(&database_id, &title, &content, &source_url).await
+}
+
+/// Build a page title from the question, truncated to keep Notion titles readable
+fn answer_note_title(question: &str) -> String {
+    let question = question.lines().next().unwrap_or("").trim();
+    if question.is_empty() {
+        return "Q&A".to_string();
+    }
+    truncate_title(question, 80)
+}
+
+#[tauri::command]
+pub async fn save_answer_to_notion(
+    mcp: State<'_, McpClient>,
+    database_id: String,
+    question: String,
+    answer: String,
+    source_url: String,
+) -> Result<String, String> {
+    let title = answer_note_title(&question);
+    let body = format!("## 질문\n{}\n\n## 답변\n{}", question, answer);
+    mcp.save_answer_to_notion(&database_id, &title, &body, &source_url).await
+}
+
+#[tauri::command]
+pub async fn search_databases(
+    mcp: State<'_, McpClient>,
+    query: String,
+    object_type: Option<SearchObjectType>,
+) -> Result<Vec<DatabaseInfo>, String> {
+    mcp.search_databases(&query, object_type.unwrap_or_default()).await
+}
+
+#[tauri::command]
+pub async fn search_pages(
+    mcp: State<'_, McpClient>,
+    query: String,
+) -> Result<Vec<DatabaseInfo>, String> {
+    mcp.search_pages(&query).await
+}
+
+#[tauri::command]
+pub fn load_recent_databases() -> Result<Vec<RecentDatabase>, String> {
+    Ok(McpClient::load_recent_databases())
+}
+
+#[tauri::command]
+pub fn add_recent_database(id: String, name: String) -> Result<(), String> {
+    McpClient::add_recent_database(RecentDatabase { id, name })
+}
+
+#[tauri::command]
+pub fn load_preferences() -> Result<UiPreferences, String> {
+    Ok(preferences::load())
+}
+
+#[tauri::command]
+pub fn save_preferences(preferences: UiPreferences) -> Result<(), String> {
+    preferences::save(&preferences)
+}
+
+#[tauri::command]
+pub async fn get_notion_backend(mcp: State<'_, McpClient>) -> Result<NotionBackend, String> {
+    Ok(mcp.get_backend().await)
+}
+
+#[tauri::command]
+pub async fn set_notion_backend(mcp: State<'_, McpClient>, backend: NotionBackend) -> Result<(), String> {
+    mcp.set_backend(backend).await
+}
+
+#[tauri::command]
+pub async fn list_mcp_tools(mcp: State<'_, McpClient>) -> Result<Vec<String>, String> {
+    mcp.list_tools().await
+}
+
+// OpenAI Commands
+#[tauri::command]
+pub async fn set_openai_key(ai: State<'_, AiClients>, api_key: String) -> Result<(), String> {
+    ai.set_openai_key(api_key).await
+}
+
+#[tauri::command]
+pub async fn load_openai_key(ai: State<'_, AiClients>) -> Result<Option<String>, String> {
+    Ok(ai.load_openai_key().await)
+}
+
+#[tauri::command]
+pub async fn get_openai_key(ai: State<'_, AiClients>) -> Result<Option<String>, String> {
+    Ok(ai.get_openai_key().await)
+}
+
+#[tauri::command]
+pub async fn clear_openai_key(ai: State<'_, AiClients>) -> Result<(), String> {
+    ai.clear_openai_key().await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn ask_question_openai(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    log: State<'_, OperationLog>,
+    page_url: String,
+    question: String,
+    bypass_cache: Option<bool>,
+) -> Result<String, String> {
+    let content = fetch_url_content_with(&mcp, &web, &page_url, !bypass_cache.unwrap_or(false)).await?;
+    let (content, options) = guarded_content(&web, content);
+    let params = OperationParams {
+        url: Some(page_url),
+        provider: Some("openai".to_string()),
+        question: Some(question),
+        ..Default::default()
+    };
+    let answer = run_ask(&claude, &ai, &params, &content, options).await?;
+    log.record(OperationKind::Ask, params, None).await;
+    Ok(answer)
+}
+
+#[tauri::command]
+pub async fn list_openai_models(ai: State<'_, AiClients>) -> Result<Vec<String>, String> {
+    ai.list_openai_models().await
+}
+
+#[tauri::command]
+pub fn list_claude_models() -> Result<Vec<String>, String> {
+    Ok(ClaudeClient::available_models())
+}
+
+/// Ask OpenAI or Gemini about an image (data URL or base64 with `mime_type`)
+#[tauri::command]
+pub async fn ask_about_image(
+    ai: State<'_, AiClients>,
+    image_data: String,
+    mime_type: Option<String>,
+    question: String,
+    model: String,
+) -> Result<String, String> {
+    let image = ImageInput::from_data(&image_data, mime_type.as_deref())?;
+    match model.as_str() {
+        "openai" => ai.ask_openai_with_image(&image, &question).await,
+        "gemini" => ai.ask_gemini_with_image(&image, &question).await,
+        _ => Err(format!("Image questions are not supported for model: {}", model)),
+    }
+}
+
+// Gemini Commands
+#[tauri::command]
+pub async fn set_gemini_key(ai: State<'_, AiClients>, api_key: String) -> Result<(), String> {
+    ai.set_gemini_key(api_key).await
+}
+
+#[tauri::command]
+pub async fn load_gemini_key(ai: State<'_, AiClients>) -> Result<Option<String>, String> {
+    Ok(ai.load_gemini_key().await)
+}
+
+#[tauri::command]
+pub async fn get_gemini_key(ai: State<'_, AiClients>) -> Result<Option<String>, String> {
+    Ok(ai.get_gemini_key().await)
+}
+
+#[tauri::command]
+pub async fn clear_gemini_key(ai: State<'_, AiClients>) -> Result<(), String> {
+    ai.clear_gemini_key().await
+}
+
+#[tauri::command]
+pub async fn list_gemini_models(ai: State<'_, AiClients>) -> Result<Vec<String>, String> {
+    ai.list_gemini_models().await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn ask_question_gemini(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    log: State<'_, OperationLog>,
+    page_url: String,
+    question: String,
+    bypass_cache: Option<bool>,
+) -> Result<String, String> {
+    let content = fetch_url_content_with(&mcp, &web, &page_url, !bypass_cache.unwrap_or(false)).await?;
+    let (content, options) = guarded_content(&web, content);
+    let params = OperationParams {
+        url: Some(page_url),
+        provider: Some("gemini".to_string()),
+        question: Some(question),
+        ..Default::default()
+    };
+    let answer = run_ask(&claude, &ai, &params, &content, options).await?;
+    log.record(OperationKind::Ask, params, None).await;
+    Ok(answer)
+}
+
+/// Raw Notion blocks of a page, nested children included, for editing and saving back without loss
+#[tauri::command]
+pub async fn fetch_notion_blocks_raw(
+    mcp: State<'_, McpClient>,
+    page_url: String,
+) -> Result<Vec<serde_json::Value>, String> {
+    mcp.fetch_blocks_raw(&page_url).await
+}
+
+/// Tables of a document as CSV, for analysts who want the data rather than the prose.
+/// `content_or_url` is a URL (web pages are parsed as HTML, Notion pages from their table blocks),
+/// pasted HTML, or markdown with pipe tables. With `output_dir`, each table is also written to
+/// the first free `table_<n>.csv` there, with a UTF-8 BOM so spreadsheet apps read Hangul correctly.
+#[tauri::command]
+pub async fn extract_tables(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
+    content_or_url: String,
+    output_dir: Option<String>,
+) -> Result<Vec<text_util::ExtractedTable>, String> {
+    let input = content_or_url.trim();
+    let is_url = (input.starts_with("http://") || input.starts_with("https://")) && !input.contains(char::is_whitespace);
+
+    let tables = if is_url && !is_notion_url(input, &McpClient::load_notion_hosts()) {
+        WebClient::html_tables(&web.fetch_html(input).await?)
+    } else if is_url {
+        mcp.fetch_tables(input).await?
+    } else if input.to_lowercase().contains("<table") {
+        WebClient::html_tables(input)
+    } else {
+        text_util::markdown_tables(input)
+    };
+    if tables.is_empty() {
+        return Err("No tables found".to_string());
+    }
+
+    let output_dir = output_dir.as_deref().map(str::trim).filter(|d| !d.is_empty());
+    if let Some(dir) = output_dir {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir, e))?;
+    }
+
+    let mut extracted = Vec::new();
+    let mut next_number = 1;
+    for (caption, rows) in tables {
+        let csv = text_util::rows_to_csv(&rows);
+        let path = match output_dir {
+            Some(dir) => Some(write_new_csv(std::path::Path::new(dir), &mut next_number, &csv)?),
+            None => None,
+        };
+        extracted.push(text_util::ExtractedTable { caption, csv, path });
+    }
+    Ok(extracted)
+}
+
+/// Write `csv` to the first `table_<n>.csv` in `dir` that doesn't exist yet, from `*next_number` on
+fn write_new_csv(dir: &std::path::Path, next_number: &mut usize, csv: &str) -> Result<String, String> {
+    use std::io::Write;
+
+    loop {
+        let path = dir.join(format!("table_{}.csv", next_number));
+        *next_number += 1;
+        let mut file = match std::fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("Failed to write {}: {}", path.display(), e)),
+        };
+        file.write_all(format!("\u{FEFF}{}", csv).as_bytes())
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        return Ok(path.display().to_string());
+    }
+}
+
+// File Export Command
+#[tauri::command]
+pub async fn export_notes_to_file(
+    file_path: String,
+    content: String,
+) -> Result<(), String> {
+    std::fs::write(&file_path, &content)
+        .map_err(|e| format!("Failed to write file: {}", e))
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConversationFormat {
+    #[default]
+    Markdown,
+    Json,
+}
+
+/// Transcript with a 질문/답변 heading per message
+fn conversation_markdown(messages: &[ConversationMessage]) -> String {
+    messages
+        .iter()
+        .map(|m| {
+            let label = match m.role.as_str() {
+                "user" => "질문",
+                "assistant" | "model" => "답변",
+                other => other,
+            };
+            format!("## {}\n{}", label, m.content.trim())
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Write an `ask_with_history` conversation to disk as markdown (default) or JSON
+#[tauri::command]
+pub async fn export_conversation(
+    messages: Vec<ConversationMessage>,
+    format: Option<ConversationFormat>,
+    file_path: String,
+) -> Result<(), String> {
+    if messages.is_empty() {
+        return Err("The conversation has no messages".to_string());
+    }
+
+    let content = match format.unwrap_or_default() {
+        ConversationFormat::Markdown => conversation_markdown(&messages),
+        ConversationFormat::Json => serde_json::to_string_pretty(&messages)
+            .map_err(|e| format!("Failed to serialize conversation: {}", e))?,
+    };
+    export_notes_to_file(file_path, content).await
+}
+
+/// Save a conversation as a Notion page; an empty title uses the first question
+#[tauri::command]
+pub async fn save_conversation_to_notion(
+    mcp: State<'_, McpClient>,
+    database_id: String,
+    title: String,
+    messages: Vec<ConversationMessage>,
+    source_url: String,
+) -> Result<String, String> {
+    if messages.is_empty() {
+        return Err("The conversation has no messages".to_string());
+    }
+
+    let title = if title.trim().is_empty() {
+        let question = messages
+            .iter()
+            .find(|m| m.role == "user")
+            .map(|m| m.content.as_str())
+            .unwrap_or_default();
+        answer_note_title(question)
+    } else {
+        truncate_title(&title, NOTION_TEXT_LIMIT)
+    };
+
+    let mut body = conversation_markdown(&messages);
+    if !source_url.trim().is_empty() {
+        body.push_str(&format!("\n\n---\n## 출처\n{}", source_url.trim()));
+    }
+
+    mcp.save_to_notion(&database_id, &title, &body, &source_url).await
+}
+
+// Content-based API Commands (no URL fetch needed)
+/// `skip_target_language` leaves paragraphs already in the target language untranslated,
+/// for pages that interleave two languages
+#[tauri::command]
+pub async fn translate_content(
+    claude: State<'_, ClaudeClient>,
+    log: State<'_, OperationLog>,
+    content: String,
+    target_language: String,
+    skip_target_language: Option<bool>,
+) -> Result<String, String> {
+    let params = OperationParams {
+        provider: Some("claude".to_string()),
+        target_language: Some(target_language),
+        skip_target_language,
+        ..Default::default()
+    };
+    let translated = run_translate(&claude, &params, &content).await?;
+    log.record(OperationKind::Translate, params, Some(&content)).await;
+    Ok(translated)
+}
+
+/// `translate`, or `translate_skipping_target` when the caller asked to skip the target language
+async fn translate_maybe_skipping(
+    claude: &ClaudeClient,
+    content: &str,
+    target_language: &str,
+    skip_target_language: Option<bool>,
+) -> Result<String, String> {
+    if skip_target_language.unwrap_or(false) {
+        return claude.translate_skipping_target(content, target_language).await;
+    }
+    claude.translate(content, target_language).await
+}
+
+#[derive(Serialize)]
+pub struct LanguageTranslation {
+    pub translated: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Translate one document into several languages at once, keyed by target language.
+/// A language that fails is reported with its error; the others still complete.
+#[tauri::command]
+pub async fn translate_multi(
+    claude: State<'_, ClaudeClient>,
+    content: String,
+    target_languages: Vec<String>,
+) -> Result<HashMap<String, LanguageTranslation>, String> {
+    use futures::stream::{self, StreamExt};
+
+    // Each translation already splits long content into concurrent chunks
+    const LANGUAGE_CONCURRENCY: usize = 3;
+
+    let mut languages: Vec<String> = Vec::new();
+    for language in target_languages {
+        let language = language.trim().to_string();
+        if !language.is_empty() && !languages.contains(&language) {
+            languages.push(language);
+        }
+    }
+    if languages.is_empty() {
+        return Err("No target languages given".to_string());
+    }
+
+    let (claude, content) = (&*claude, content.as_str());
+    let translations = stream::iter(languages)
+        .map(|language| async move {
+            let (translated, error) = match claude.translate(content, &language).await {
+                Ok(translated) => (Some(translated), None),
+                Err(error) => (None, Some(error)),
+            };
+            (language, LanguageTranslation { translated, error })
+        })
+        .buffer_unordered(LANGUAGE_CONCURRENCY)
+        .collect::<HashMap<_, _>>()
+        .await;
+
+    Ok(translations)
+}
+
+/// Translate a highlighted passage using the rest of the document as context; returns only the passage
+#[tauri::command]
+pub async fn translate_with_context(
+    claude: State<'_, ClaudeClient>,
+    selection: String,
+    full_content: String,
+    target_language: String,
+) -> Result<String, String> {
+    claude
+        .translate_with_context(&selection, &full_content, &target_language)
+        .await
+}
+
+/// Re-translate with one correction applied to the previous result, for an iterative review loop
+#[tauri::command]
+pub async fn refine_translation(
+    claude: State<'_, ClaudeClient>,
+    source: String,
+    previous_translation: String,
+    instruction: String,
+    target_language: String,
+) -> Result<String, String> {
+    claude
+        .refine_translation(&source, &previous_translation, &instruction, &target_language)
+        .await
+}
+
+/// Chunked translation that returns partial output on failure; pass `start_chunk`
+/// (the previous `failed_chunk`) to retry just the remaining portion
+#[tauri::command]
+pub async fn translate_content_resumable(
+    claude: State<'_, ClaudeClient>,
+    content: String,
+    target_language: String,
+    start_chunk: Option<usize>,
+) -> Result<TranslationOutcome, String> {
+    Ok(claude
+        .translate_from(&content, &target_language, start_chunk.unwrap_or(0))
+        .await)
+}
+
+/// Chunk count and sizes `translate` would use, without calling the API
+#[tauri::command]
+pub fn plan_translation(claude: State<'_, ClaudeClient>, content: String) -> Result<TranslationPlan, String> {
+    Ok(claude.plan_translation(&content))
+}
+
+#[derive(Serialize)]
+pub struct TranslationChunkSize {
+    pub chunk_chars: usize,
+    pub single_request_max_tokens: usize,
+}
+
+#[tauri::command]
+pub fn get_translation_chunk_size(claude: State<'_, ClaudeClient>) -> Result<TranslationChunkSize, String> {
+    let (chunk_chars, single_request_max_tokens) = claude.get_translation_chunk_size();
+    Ok(TranslationChunkSize {
+        chunk_chars,
+        single_request_max_tokens,
+    })
+}
+
+/// `single_request_max_tokens` keeps its current value when omitted
+#[tauri::command]
+pub fn set_translation_chunk_size(
+    claude: State<'_, ClaudeClient>,
+    chunk_chars: usize,
+    single_request_max_tokens: Option<usize>,
+) -> Result<(), String> {
+    let single_max_tokens =
+        single_request_max_tokens.unwrap_or_else(|| claude.get_translation_chunk_size().1);
+    claude.set_translation_chunk_size(chunk_chars, single_max_tokens)
+}
+
+#[tauri::command]
+pub async fn get_translation_concurrency(claude: State<'_, ClaudeClient>) -> Result<usize, String> {
+    Ok(claude.get_translation_concurrency().await)
+}
+
+#[tauri::command]
+pub async fn set_translation_concurrency(
+    claude: State<'_, ClaudeClient>,
+    concurrency: usize,
+) -> Result<(), String> {
+    claude.set_translation_concurrency(concurrency).await
+}
+
+#[tauri::command]
+pub fn get_prompt_caching(claude: State<'_, ClaudeClient>) -> Result<bool, String> {
+    Ok(claude.get_prompt_caching())
+}
+
+#[tauri::command]
+pub fn set_prompt_caching(claude: State<'_, ClaudeClient>, enabled: bool) -> Result<(), String> {
+    claude.set_prompt_caching(enabled)
+}
+
+#[tauri::command]
+pub async fn get_claude_beta_features(claude: State<'_, ClaudeClient>) -> Result<Vec<String>, String> {
+    Ok(claude.get_beta_features().await)
+}
+
+/// Anthropic beta flags (1M context, extended output, ...) sent with every Claude request
+#[tauri::command]
+pub async fn set_claude_beta_features(claude: State<'_, ClaudeClient>, features: Vec<String>) -> Result<(), String> {
+    claude.set_beta_features(features).await
+}
+
+/// Output token limit per provider; a missing entry means the provider default
+#[tauri::command]
+pub fn get_max_output_tokens(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+) -> Result<HashMap<String, u32>, String> {
+    let mut limits = HashMap::new();
+    limits.insert("claude".to_string(), claude.get_max_output_tokens());
+    for provider in ["openai", "gemini"] {
+        if let Some(tokens) = ai.get_max_output_tokens(provider)? {
+            limits.insert(provider.to_string(), tokens);
+        }
+    }
+    Ok(limits)
+}
+
+#[tauri::command]
+pub fn set_max_output_tokens(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    provider: String,
+    tokens: u32,
+) -> Result<(), String> {
+    match provider.as_str() {
+        "claude" => claude.set_max_output_tokens(tokens),
+        _ => ai.set_max_output_tokens(&provider, tokens),
+    }
+}
+
+/// Requests per minute per provider; 0 means no limit
+#[tauri::command]
+pub fn get_rate_limits(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+) -> Result<HashMap<String, u32>, String> {
+    let mut limits = HashMap::new();
+    limits.insert("claude".to_string(), claude.get_rate_limit());
+    for provider in ["openai", "gemini"] {
+        limits.insert(provider.to_string(), ai.get_rate_limit(provider)?);
+    }
+    Ok(limits)
+}
+
+/// Cap requests per minute to `provider` across every command; requests over the limit
+/// wait their turn. 0 removes the limit.
+#[tauri::command]
+pub fn set_rate_limit(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    provider: String,
+    rpm: u32,
+) -> Result<(), String> {
+    match provider.as_str() {
+        "claude" => claude.set_rate_limit(rpm),
+        _ => ai.set_rate_limit(&provider, rpm),
+    }
+}
+
+#[tauri::command]
+pub fn get_thinking(claude: State<'_, ClaudeClient>) -> Result<bool, String> {
+    Ok(claude.get_thinking())
+}
+
+#[tauri::command]
+pub fn set_thinking(claude: State<'_, ClaudeClient>, enabled: bool) -> Result<(), String> {
+    claude.set_thinking(enabled)
+}
+
+#[tauri::command]
+pub fn get_deterministic_mode(claude: State<'_, ClaudeClient>) -> Result<bool, String> {
+    Ok(claude.get_deterministic_mode())
+}
+
+/// Forces temperature 0 on every provider and a fixed `seed` on OpenAI and Gemini, overriding
+/// per-request temperatures. This makes output repeatable in practice but no provider guarantees
+/// it: the seeds are best-effort (OpenAI's breaks when its `system_fingerprint` changes), Claude
+/// has no seed and can still vary slightly at temperature 0, and Claude requests with extended
+/// thinking cannot set a temperature at all.
+#[tauri::command]
+pub fn set_deterministic_mode(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    enabled: bool,
+) -> Result<(), String> {
+    claude.set_deterministic_mode(enabled)?;
+    ai.apply_deterministic_mode(enabled);
+    Ok(())
+}
+
+/// Claude keeps a longer default for chunked translation; OpenAI and Gemini share a client
+#[derive(Serialize)]
+pub struct AiTimeouts {
+    pub claude_connect_secs: u64,
+    pub claude_request_secs: u64,
+    pub connect_secs: u64,
+    pub request_secs: u64,
+}
+
+#[tauri::command]
+pub fn get_ai_timeouts() -> Result<AiTimeouts, String> {
+    let (claude_connect_secs, claude_request_secs) = ClaudeClient::get_timeouts();
+    let (connect_secs, request_secs) = AiClients::get_timeouts();
+    Ok(AiTimeouts {
+        claude_connect_secs,
+        claude_request_secs,
+        connect_secs,
+        request_secs,
+    })
+}
+
+/// Applies the same timeouts to every provider
+#[tauri::command]
+pub async fn set_ai_timeouts(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    connect_secs: u64,
+    request_secs: u64,
+) -> Result<(), String> {
+    if !(1..=120).contains(&connect_secs) {
+        return Err("Connect timeout must be between 1 and 120 seconds".to_string());
+    }
+    if !(5..=1800).contains(&request_secs) {
+        return Err("Request timeout must be between 5 and 1800 seconds".to_string());
+    }
+    if connect_secs > request_secs {
+        return Err("Connect timeout must not exceed the request timeout".to_string());
+    }
+
+    claude.set_timeouts(connect_secs, request_secs).await?;
+    ai.set_timeouts(connect_secs, request_secs).await
+}
+
+/// `output_format` "json" returns a validated `{ title, key_points }` object as a JSON string.
+/// Always Claude; `summarize_content_with_fallback` reports which provider answered.
+#[tauri::command]
+pub async fn summarize_content(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    log: State<'_, OperationLog>,
+    content: String,
+    output_format: Option<SummaryFormat>,
+) -> Result<String, String> {
+    let params = OperationParams {
+        provider: Some("claude".to_string()),
+        output_format,
+        ..Default::default()
+    };
+    let summary = run_summarize(&claude, &ai, &params, &content).await?;
+    log.record(OperationKind::Summarize, params, Some(&content)).await;
+    Ok(summary)
+}
+
+#[tauri::command]
+pub async fn summarize_constrained(
+    claude: State<'_, ClaudeClient>,
+    content: String,
+    constraint: SummaryConstraint,
+) -> Result<String, String> {
+    claude.summarize_constrained(&content, &constraint).await
+}
+
+/// `thinking` overrides the saved extended-thinking toggle for this question;
+/// `include_reasoning` prepends Claude's reasoning to the answer.
+/// `model` on the content commands switches models for this call only; saved settings are untouched.
+/// The `ask_*_content` commands stay on their own provider; `ask_content_with_fallback`
+/// reports which provider answered.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn ask_claude_content(
+    web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    log: State<'_, OperationLog>,
+    content: String,
+    question: String,
+    thinking: Option<bool>,
+    include_reasoning: Option<bool>,
+    model: Option<String>,
+) -> Result<String, String> {
+    let thinking = thinking.unwrap_or_else(|| claude.get_thinking());
+    let params = OperationParams {
+        provider: Some("claude".to_string()),
+        model,
+        question: Some(question),
+        thinking: Some(thinking),
+        include_reasoning: include_reasoning.filter(|_| thinking),
+        ..Default::default()
+    };
+    let (guarded, options) = guarded_content(&web, content.clone());
+    let answer = run_ask(&claude, &ai, &params, &guarded, options).await?;
+    log.record(OperationKind::Ask, params, Some(&content)).await;
+    Ok(answer)
+}
+
+// Provider fallback
+#[derive(Serialize)]
+pub struct ProviderFailure {
+    pub provider: String,
+    pub error: String,
+}
+
+#[derive(Serialize)]
+pub struct FallbackAnswer {
+    pub answer: String,
+    /// Provider that produced `answer`
+    pub provider: String,
+    /// Whether `provider` is not the one asked for
+    pub substituted: bool,
+    /// Why earlier providers were skipped, in order
+    pub failures: Vec<ProviderFailure>,
+}
+
+/// Whether `provider` has a key in memory or saved in its config, loading a saved one
+async fn has_provider_key(claude: &ClaudeClient, ai: &AiClients, provider: &str) -> bool {
+    match provider {
+        "claude" => claude.get_api_key().await.is_some() || claude.load_api_key().await.is_some(),
+        "openai" => ai.get_openai_key().await.is_some() || ai.load_openai_key().await.is_some(),
+        "gemini" => ai.get_gemini_key().await.is_some() || ai.load_gemini_key().await.is_some(),
+        _ => false,
+    }
+}
+
+/// Run `task` on `primary`, then on the providers after it in the configured fallback chain
+/// (those with a key) as long as the failures are retryable. With fallback off only `primary` runs,
+/// and a lone failure comes back as the provider's own error.
+/// Provider-specific models don't carry over, so `task` gets the provider name only.
+async fn with_provider_fallback<F, Fut>(
+    claude: &ClaudeClient,
+    ai: &AiClients,
+    primary: &str,
+    task: F,
+) -> Result<FallbackAnswer, String>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = Result<String, ProviderError>>,
+{
+    let mut providers = vec![primary.to_string()];
+    for provider in AiClients::load_provider_fallback() {
+        if !providers.contains(&provider) && has_provider_key(claude, ai, &provider).await {
+            providers.push(provider);
+        }
+    }
+
+    let mut failures = Vec::new();
+    for provider in providers {
+        match task(provider.clone()).await {
+            Ok(answer) => {
+                return Ok(FallbackAnswer {
+                    answer,
+                    substituted: provider != primary,
+                    provider,
+                    failures,
+                })
+            }
+            Err(error) => {
+                failures.push(ProviderFailure {
+                    provider,
+                    error: error.message,
+                });
+                if !error.retryable {
+                    break;
+                }
+            }
+        }
+    }
+
+    if let [failure] = failures.as_slice() {
+        return Err(failure.error.clone());
+    }
+    let summary = failures
+        .iter()
+        .map(|f| format!("{}: {}", f.provider, f.error))
+        .collect::<Vec<_>>()
+        .join("; ");
+    Err(summary)
+}
+
+/// Document Q&A on one provider
+async fn ask_provider(
+    claude: &ClaudeClient,
+    ai: &AiClients,
+    provider: &str,
+    content: &str,
+    question: &str,
+    options: &RequestOptions,
+) -> Result<String, ProviderError> {
+    match provider {
+        "claude" => claude.ask_question_with(content, question, options).await,
+        "openai" => ai.ask_openai_with(content, question, options).await,
+        "gemini" => ai.ask_gemini_with(content, question, options).await,
+        _ => Err(format!("Unknown model: {}", provider).into()),
+    }
+}
+
+#[tauri::command]
+pub fn get_provider_fallback() -> Result<Vec<String>, String> {
+    Ok(AiClients::load_provider_fallback())
+}
+
+/// Opt-in fallback order, e.g. `["claude", "openai", "gemini"]`; an empty list turns fallback off
+#[tauri::command]
+pub fn set_provider_fallback(chain: Vec<String>) -> Result<(), String> {
+    AiClients::set_provider_fallback(chain)
+}
+
+/// Ask about content on `model`, falling back along the configured chain on rate limits and
+/// outages; the reply says which provider answered
+#[tauri::command]
+pub async fn ask_content_with_fallback(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    content: String,
+    question: String,
+    model: String,
+) -> Result<FallbackAnswer, String> {
+    let (claude, ai) = (&*claude, &*ai);
+    let (content, question) = (content.as_str(), question.as_str());
+    with_provider_fallback(claude, ai, &model, |provider| async move {
+        ask_provider(claude, ai, &provider, content, question, &RequestOptions::default()).await
+    })
+    .await
+}
+
+/// `summarize` for pasted content with the same fallback as `ask_content_with_fallback`
+#[tauri::command]
+pub async fn summarize_content_with_fallback(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    content: String,
+    model: String,
+) -> Result<FallbackAnswer, String> {
+    let (claude, ai, content) = (&*claude, &*ai, content.as_str());
+    with_provider_fallback(claude, ai, &model, |provider| async move {
+        summarize_with_model(claude, ai, &provider, content, &RequestOptions::default()).await
+    })
+    .await
+}
+
+#[derive(Serialize)]
+pub struct ProviderBenchmark {
+    pub provider: String,
+    pub ok: bool,
+    pub latency_ms: u64,
+    /// Output tokens the provider reported for the answer
+    pub tokens: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Ask the same small question on every provider with a key, all at once, timing each.
+/// Providers without a key are left out rather than reported as failures.
+#[tauri::command]
+pub async fn benchmark_providers(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    sample_content: String,
+    sample_question: String,
+) -> Result<Vec<ProviderBenchmark>, String> {
+    let (claude, ai) = (&*claude, &*ai);
+    let mut providers = Vec::new();
+    for provider in ["claude", "openai", "gemini"] {
+        if has_provider_key(claude, ai, provider).await {
+            providers.push(provider);
+        }
+    }
+    if providers.is_empty() {
+        return Err("No provider has an API key configured".to_string());
+    }
+
+    let (content, question) = (sample_content.as_str(), sample_question.as_str());
+    let runs = providers.into_iter().map(|provider| async move {
+        let started = std::time::Instant::now();
+        let options = RequestOptions::default();
+        let result = match provider {
+            "claude" => claude.ask_question_metered(content, question, &options).await,
+            "openai" => ai.ask_openai_metered(content, question, &options).await,
+            "gemini" => ai.ask_gemini_metered(content, question, &options).await,
+            _ => Err(format!("Unknown model: {}", provider).into()),
+        };
+        let latency_ms = started.elapsed().as_millis() as u64;
+        match result {
+            Ok(answer) => ProviderBenchmark {
+                provider: provider.to_string(),
+                ok: true,
+                latency_ms,
+                tokens: answer.output_tokens,
+                error: None,
+            },
+            Err(error) => ProviderBenchmark {
+                provider: provider.to_string(),
+                ok: false,
+                latency_ms,
+                tokens: None,
+                error: Some(error.into()),
+            },
+        }
+    });
+
+    Ok(futures::future::join_all(runs).await)
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn ask_openai_content(
+    web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    log: State<'_, OperationLog>,
+    content: String,
+    question: String,
+    model: Option<String>,
+) -> Result<String, String> {
+    let params = OperationParams {
+        provider: Some("openai".to_string()),
+        model,
+        question: Some(question),
+        ..Default::default()
+    };
+    let (guarded, options) = guarded_content(&web, content.clone());
+    let answer = run_ask(&claude, &ai, &params, &guarded, options).await?;
+    log.record(OperationKind::Ask, params, Some(&content)).await;
+    Ok(answer)
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn ask_gemini_content(
+    web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    log: State<'_, OperationLog>,
+    content: String,
+    question: String,
+    model: Option<String>,
+) -> Result<String, String> {
+    let params = OperationParams {
+        provider: Some("gemini".to_string()),
+        model,
+        question: Some(question),
+        ..Default::default()
+    };
+    let (guarded, options) = guarded_content(&web, content.clone());
+    let answer = run_ask(&claude, &ai, &params, &guarded, options).await?;
+    log.record(OperationKind::Ask, params, Some(&content)).await;
+    Ok(answer)
+}
+
+/// Streamed OpenAI answer; deltas go to `on_delta` and the full text is returned at the end
+#[tauri::command]
+pub async fn ask_openai_stream(
+    web: State<'_, WebClient>,
+    ai: State<'_, AiClients>,
+    content: String,
+    question: String,
+    on_delta: Channel<String>,
+) -> Result<String, String> {
+    let (content, options) = guarded_content(&web, content);
+    ai.ask_openai_stream(&content, &question, &options, |text| send_delta(&on_delta, text))
+        .await
+}
+
+/// Streamed Gemini answer; deltas go to `on_delta` and the full text is returned at the end
+#[tauri::command]
+pub async fn ask_gemini_stream(
+    web: State<'_, WebClient>,
+    ai: State<'_, AiClients>,
+    content: String,
+    question: String,
+    on_delta: Channel<String>,
+) -> Result<String, String> {
+    let (content, options) = guarded_content(&web, content);
+    ai.ask_gemini_stream(&content, &question, &options, |text| send_delta(&on_delta, text))
+        .await
+}
+
+/// One OpenAI completion offering `tools`; returns the text answer or the tool calls the model requested
+#[tauri::command]
+pub async fn ask_openai_with_tools(
+    ai: State<'_, AiClients>,
+    messages: Vec<OpenAiMessage>,
+    tools: Vec<OpenAiFunction>,
+) -> Result<OpenAiToolReply, String> {
+    ai.ask_openai_with_tools(messages, tools).await
+}
+
+// Follow-up question with conversation history
+#[tauri::command]
+pub async fn ask_with_history(
+    web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    model: String,
+    messages: Vec<ConversationMessage>,
+    content: String,
+) -> Result<String, String> {
+    let (content, options) = guarded_content(&web, content);
+    answer_with_history(&claude, &ai, &model, messages, &content, &options, None).await
+}
+
+/// Same as `ask_with_history`, sending text to `on_delta` as it arrives.
+/// Claude has no streaming path yet, so its answer arrives as a single delta.
+#[tauri::command]
+pub async fn ask_with_history_stream(
+    web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    model: String,
+    messages: Vec<ConversationMessage>,
+    content: String,
+    on_delta: Channel<String>,
+) -> Result<String, String> {
+    let (content, options) = guarded_content(&web, content);
+    answer_with_history(&claude, &ai, &model, messages, &content, &options, Some(&on_delta)).await
+}
+
+/// Ask `new_message` on top of a copy of `messages` without touching any stored session,
+/// so one history can be tried with different models or temperatures side by side
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn branch_conversation(
+    web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    messages: Vec<ConversationMessage>,
+    new_message: String,
+    model: String,
+    temperature: Option<f32>,
+    content: Option<String>,
+) -> Result<String, String> {
+    if new_message.trim().is_empty() {
+        return Err("Message must not be empty".to_string());
+    }
+    if let Some(t) = temperature {
+        let max = if model == "claude" { 1.0 } else { 2.0 };
+        if !(0.0..=max).contains(&t) {
+            return Err(format!("Temperature for {} must be between 0 and {}", model, max));
+        }
+    }
+
+    let mut branch = messages;
+    branch.push(ConversationMessage {
+        role: "user".to_string(),
+        content: new_message,
+    });
+
+    let (content, options) = guarded_content(&web, content.unwrap_or_default());
+    let options = RequestOptions { temperature, ..options };
+    answer_with_history(&claude, &ai, &model, branch, &content, &options, None).await
+}
+
+/// The window may be gone mid-answer; the full text is still returned either way
+fn send_delta(channel: &Channel<String>, text: &str) {
+    let _ = channel.send(text.to_string());
+}
+
+async fn answer_with_history(
+    claude: &ClaudeClient,
+    ai: &AiClients,
+    model: &str,
+    messages: Vec<ConversationMessage>,
+    content: &str,
+    options: &RequestOptions,
+    on_delta: Option<&Channel<String>>,
+) -> Result<String, String> {
+    // Build messages with context
+    let system_prompt = format!(
+        "당신은 해당 분야의 전문가입니다. <document> 태그 안의 문서를 바탕으로 질문에 전문적이고 상세하게 답변해주세요.\n\n\
+        ## 답변 가이드라인:\n\
+        - 전문 용어가 있다면 쉽게 설명해주세요\n\
+        - 관련 배경 지식도 함께 제공해주세요\n\
+        - 실용적인 예시나 활용 방안이 있다면 포함해주세요\n\
+        - 논리적인 구조로 답변을 구성해주세요\n\
+        - 이전 대화 맥락을 고려해서 답변해주세요\n\n\
+        {}## 참고 문서:\n{}\n\n\
+        반드시 한글로 상세하게 답변해주세요.",
+        content_guard(options.untrusted_content),
+        text_util::document_block(content)
+    );
+
+    match model {
+        "claude" => {
+            // Conversation history; the document goes in the top-level system prompt
+            let claude_messages: Vec<crate::claude::Message> = messages
+                .into_iter()
+                .map(|msg| crate::claude::Message {
+                    role: msg.role,
+                    content: msg.content,
+                })
+                .collect();
+
+            let answer = claude
+                .send_messages_with_system(&system_prompt, claude_messages, options)
+                .await?;
+            if let Some(channel) = on_delta {
+                send_delta(channel, &answer);
+            }
+            Ok(answer)
+        }
         "openai" => {
             let mut openai_messages: Vec<crate::ai_clients::OpenAiMessage> = Vec::new();
 
-            // Add system context
-            openai_messages.push(crate::ai_clients::OpenAiMessage {
-                role: "system".to_string(),
-                content: system_prompt,
-            });
+            // Add system context
+            openai_messages.push(crate::ai_clients::OpenAiMessage {
+                role: "system".to_string(),
+                content: system_prompt,
+            });
+
+            // Add conversation history
+            for msg in messages {
+                openai_messages.push(crate::ai_clients::OpenAiMessage {
+                    role: msg.role,
+                    content: msg.content,
+                });
+            }
+
+            match on_delta {
+                Some(channel) => {
+                    ai.ask_openai_with_history_stream(openai_messages, options, |text| {
+                        send_delta(channel, text)
+                    })
+                    .await
+                }
+                None => ai.ask_openai_with_history(openai_messages, options).await,
+            }
+        }
+        "gemini" => {
+            // Gemini doesn't support system messages, so we include it in first user message
+            let mut first_message = system_prompt.clone();
+            if let Some(first) = messages.first() {
+                first_message = format!("{}\n\n사용자 질문: {}", system_prompt, first.content);
+            }
+
+            // For Gemini, just use the simple ask method with the combined prompt
+            let question = if messages.len() > 1 {
+                // Include conversation history in prompt
+                let history: Vec<String> = messages.iter()
+                    .map(|m| format!("{}: {}", if m.role == "user" { "사용자" } else { "AI" }, m.content))
+                    .collect();
+                format!("{}\n\n이전 대화:\n{}", first_message, history.join("\n"))
+            } else if let Some(msg) = messages.last() {
+                msg.content.clone()
+            } else {
+                return Err("No messages provided".to_string());
+            };
+
+            match on_delta {
+                Some(channel) => {
+                    ai.ask_gemini_stream(content, &question, options, |text| send_delta(channel, text))
+                        .await
+                }
+                None => ai.ask_gemini_with(content, &question, options).await.map_err(String::from),
+            }
+        }
+        _ => Err(format!("Unknown model: {}", model)),
+    }
+}
+
+/// Default cap for uploaded files and for the text extracted from them
+const DEFAULT_MAX_FILE_SIZE_MB: usize = 50;
+/// Highest limit a caller may ask for; the whole file is held in memory
+const MAX_FILE_SIZE_MB: usize = 500;
+
+fn file_too_large(size: usize, limit: usize) -> String {
+    format!(
+        "File too large ({:.1} MB), limit is {} MB",
+        size as f64 / (1024.0 * 1024.0),
+        limit / (1024 * 1024)
+    )
+}
+
+/// Decode base64 file data, bare or as a data URL, rejecting anything over `limit` bytes
+fn decode_file_data(file_data: &str, limit: usize) -> Result<Vec<u8>, String> {
+    use base64::Engine;
+
+    // Decode base64 data URL
+    let data = if file_data.contains(",") {
+        // Data URL format: data:mime;base64,xxxxx
+        file_data.split(',').nth(1).unwrap_or(file_data)
+    } else {
+        file_data
+    };
+
+    // Reject from the encoded length before allocating the decoded buffer
+    let estimated_size = data.len() / 4 * 3;
+    if estimated_size > limit {
+        return Err(file_too_large(estimated_size, limit));
+    }
+
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| format!("Failed to decode base64: {}", e))?;
+
+    if bytes.len() > limit {
+        return Err(file_too_large(bytes.len(), limit));
+    }
+
+    Ok(bytes)
+}
+
+// Extract text from file data (base64 encoded)
+#[tauri::command]
+pub async fn extract_text_from_file(
+    file_data: String,
+    file_type: String,
+    max_size_mb: Option<usize>,
+) -> Result<String, String> {
+    let limit = max_size_mb
+        .unwrap_or(DEFAULT_MAX_FILE_SIZE_MB)
+        .clamp(1, MAX_FILE_SIZE_MB)
+        .saturating_mul(1024 * 1024);
+    let bytes = decode_file_data(&file_data, limit)?;
+
+    let text = match file_type.as_str() {
+        "pdf" => extract_pdf_text(&bytes),
+        "xls" | "xlsx" => extract_excel_text(&bytes, &file_type),
+        "doc" | "docx" | "ppt" | "pptx" => {
+            // For Office formats, we return a placeholder message
+            // Full extraction would require additional libraries
+            Ok(format!(
+                "[{} 파일]\n\n이 파일 형식의 텍스트 추출은 현재 지원되지 않습니다.\n\
+                PDF 또는 Excel 파일을 사용하시거나, 텍스트를 직접 복사하여 붙여넣기 해주세요.",
+                file_type.to_uppercase()
+            ))
+        }
+        "image" => {
+            Ok("[이미지 파일]\n\n이미지에서 텍스트를 추출하려면 OCR이 필요합니다.\n\
+                현재는 이미지 미리보기만 지원됩니다.".to_string())
+        }
+        _ => Err(format!("Unsupported file type: {}", file_type)),
+    }?;
+
+    // Compressed formats can expand well beyond the file size
+    if text.len() > limit {
+        return Err(file_too_large(text.len(), limit));
+    }
+
+    Ok(text)
+}
+
+/// PDF text with a `## Page N` heading before each page so page references survive into prompts
+fn extract_pdf_text(bytes: &[u8]) -> Result<String, String> {
+    let sections: Vec<String> = extract_pdf_pages(bytes)?
+        .into_iter()
+        .map(|(number, text)| format!("## Page {}\n\n{}", number, text))
+        .collect();
+
+    Ok(sections.join("\n\n"))
+}
+
+/// Trimmed text of each PDF page with its 1-based page number; pages without text are left out
+fn extract_pdf_pages(bytes: &[u8]) -> Result<Vec<(usize, String)>, String> {
+    let pages = pdf_extract::extract_text_from_mem_by_pages(bytes)
+        .map_err(|e| format!("Failed to extract PDF text: {}", e))?;
+
+    Ok(pages
+        .iter()
+        .enumerate()
+        .filter(|(_, text)| !text.trim().is_empty())
+        .map(|(i, text)| (i + 1, text.trim().to_string()))
+        .collect())
+}
+
+/// The pages of a PDF as one markdown document. Page numbers are dropped, a sentence running
+/// over a page break is joined back together, and lines that look like section titles become
+/// headings (see `text_util::pdf_heading`).
+fn pdf_document(pages: &[(usize, String)]) -> String {
+    let is_page_number = |line: &str| {
+        let line = line.trim().trim_start_matches("Page").trim_start_matches("page");
+        let digits: String = line.chars().filter(|c| !matches!(c, '-' | '/' | ' ')).collect();
+        !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+    };
+
+    let mut document = String::new();
+    for (_, text) in pages {
+        let body = text
+            .lines()
+            .filter(|line| !is_page_number(line))
+            .map(|line| text_util::pdf_heading(line).unwrap_or_else(|| line.to_string()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let body = body.trim();
+        if body.is_empty() {
+            continue;
+        }
+        if !document.is_empty() {
+            let last_line = document.lines().last().unwrap_or_default();
+            let sentence_ended = last_line.starts_with('#')
+                || last_line.trim_end().ends_with(['.', '!', '?', '。', '！', '？', ':', '"', '”', ')']);
+            // A plain line break lets `normalize_text` join the halves of a split sentence
+            document.push_str(if sentence_ended { "\n\n" } else { "\n" });
+        }
+        document.push_str(body);
+    }
+    text_util::normalize_text(&document)
+}
+
+/// `document` cut before each heading; text before the first heading is a section of its own
+fn heading_sections(document: &str) -> Vec<String> {
+    let mut sections: Vec<String> = Vec::new();
+    for line in document.lines() {
+        if text_util::markdown_heading(line).is_some() || sections.is_empty() {
+            sections.push(String::new());
+        }
+        if let Some(section) = sections.last_mut() {
+            section.push_str(line);
+            section.push('\n');
+        }
+    }
+    sections.retain(|s| !s.trim().is_empty());
+    sections
+}
+
+#[derive(Serialize)]
+pub struct PdfTranslation {
+    pub page_id: String,
+    /// PDF pages with text
+    pub pages_processed: usize,
+    pub sections_translated: usize,
+    /// Sections whose translation failed; their original text is saved in their place
+    pub failed_sections: Vec<String>,
+}
+
+/// Extract a PDF, translate it section by section and save the translation to Notion.
+/// Pages are joined into one document first, so sentences across page breaks stay whole.
+/// A section that fails to translate is saved untranslated and reported rather than
+/// discarding the rest.
+#[tauri::command]
+pub async fn translate_pdf_to_notion(
+    mcp: State<'_, McpClient>,
+    claude: State<'_, ClaudeClient>,
+    pdf_base64: String,
+    target_language: String,
+    database_id: String,
+) -> Result<PdfTranslation, String> {
+    use futures::stream::{self, StreamExt};
+
+    // Each translation already splits long sections into concurrent chunks
+    const SECTION_CONCURRENCY: usize = 3;
+
+    let bytes = decode_file_data(&pdf_base64, DEFAULT_MAX_FILE_SIZE_MB * 1024 * 1024)?;
+    let pages = extract_pdf_pages(&bytes)?;
+    if pages.is_empty() {
+        return Err("No text found in the PDF".to_string());
+    }
+    let sections = heading_sections(&pdf_document(&pages));
+
+    let (claude, target_language) = (&*claude, target_language.as_str());
+    let results: Vec<(String, Result<String, String>)> = stream::iter(sections)
+        .map(|section| async move {
+            let result = claude.translate(&section, target_language).await;
+            (section, result)
+        })
+        .buffered(SECTION_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut parts = Vec::new();
+    let mut failed_sections = Vec::new();
+    for (index, (section, result)) in results.into_iter().enumerate() {
+        match result {
+            Ok(translated) => parts.push(translated.trim().to_string()),
+            Err(e) => {
+                failed_sections.push(format!("Section {} ({}): {}", index + 1, first_line_title(&section), e));
+                parts.push(format!("> (번역 실패: 원문을 그대로 둡니다)\n\n{}", section.trim()));
+            }
+        }
+    }
+    if failed_sections.len() == parts.len() {
+        return Err(format!("Translation failed for every section: {}", failed_sections.join("; ")));
+    }
+
+    let title = truncate_title(&format!("[번역] {}", first_line_title(&parts[0])), NOTION_TEXT_LIMIT);
+    let page_id = mcp
+        .save_to_notion(&database_id, &title, &parts.join("\n\n"), "")
+        .await?;
+
+    Ok(PdfTranslation {
+        page_id,
+        pages_processed: pages.len(),
+        sections_translated: parts.len() - failed_sections.len(),
+        failed_sections,
+    })
+}
+
+fn extract_excel_text(bytes: &[u8], file_type: &str) -> Result<String, String> {
+    use calamine::{Reader, Xlsx, Xls};
+    use std::io::Cursor;
+
+    let cursor = Cursor::new(bytes);
+    let mut result = String::new();
+
+    match file_type {
+        "xlsx" => {
+            let mut workbook: Xlsx<_> = calamine::open_workbook_from_rs(cursor)
+                .map_err(|e| format!("Failed to open xlsx: {}", e))?;
+
+            for sheet_name in workbook.sheet_names().to_vec() {
+                result.push_str(&format!("## Sheet: {}\n\n", sheet_name));
+                if let Ok(range) = workbook.worksheet_range(&sheet_name) {
+                    for row in range.rows() {
+                        let row_str: Vec<String> = row.iter()
+                            .map(|cell| cell.to_string())
+                            .collect();
+                        result.push_str(&row_str.join("\t"));
+                        result.push('\n');
+                    }
+                }
+                result.push('\n');
+            }
+        }
+        "xls" => {
+            let mut workbook: Xls<_> = calamine::open_workbook_from_rs(cursor)
+                .map_err(|e| format!("Failed to open xls: {}", e))?;
+
+            for sheet_name in workbook.sheet_names().to_vec() {
+                result.push_str(&format!("## Sheet: {}\n\n", sheet_name));
+                if let Ok(range) = workbook.worksheet_range(&sheet_name) {
+                    for row in range.rows() {
+                        let row_str: Vec<String> = row.iter()
+                            .map(|cell| cell.to_string())
+                            .collect();
+                        result.push_str(&row_str.join("\t"));
+                        result.push('\n');
+                    }
+                }
+                result.push('\n');
+            }
+        }
+        _ => return Err(format!("Unsupported Excel format: {}", file_type)),
+    }
+
+    Ok(result)
+}
+
+/// Send a raw prompt through the selected provider
+async fn send_prompt(
+    claude: &ClaudeClient,
+    ai: &AiClients,
+    model: &str,
+    prompt: &str,
+) -> Result<String, String> {
+    send_prompt_with(claude, ai, model, prompt, &RequestOptions::default())
+        .await
+        .map_err(String::from)
+}
+
+async fn send_prompt_with(
+    claude: &ClaudeClient,
+    ai: &AiClients,
+    model: &str,
+    prompt: &str,
+    options: &RequestOptions,
+) -> Result<String, ProviderError> {
+    match model {
+        "claude" => claude.send_message_with(prompt, options).await,
+        "openai" => ai.send_openai_prompt_with(prompt, options).await,
+        "gemini" => ai.send_gemini_prompt_with(prompt, options).await,
+        _ => Err(format!("Unknown model: {}", model).into()),
+    }
+}
+
+// Prompt Template Commands
+#[tauri::command]
+pub fn list_templates() -> Result<Vec<PromptTemplate>, String> {
+    Ok(templates::list_templates())
+}
+
+#[tauri::command]
+pub fn save_template(name: String, description: String, prompt: String) -> Result<(), String> {
+    templates::save_template(name, description, prompt)
+}
+
+#[tauri::command]
+pub fn delete_template(name: String) -> Result<(), String> {
+    templates::delete_template(&name)
+}
+
+#[tauri::command]
+pub async fn run_template(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    template_name: String,
+    content: String,
+    extra_vars: Option<HashMap<String, String>>,
+    model: String,
+) -> Result<String, String> {
+    let template = templates::get_template(&template_name)
+        .ok_or_else(|| format!("Template not found: {}", template_name))?;
+    let prompt = templates::render(&template, &content, &extra_vars.unwrap_or_default());
+    send_prompt(&claude, &ai, &model, &prompt).await
+}
+
+// Server-side Q&A sessions so follow-ups don't resend the document
+#[tauri::command]
+pub async fn start_session(
+    sessions: State<'_, SessionStore>,
+    content: String,
+    model: String,
+) -> Result<String, String> {
+    Ok(sessions.start(content, model).await)
+}
+
+#[tauri::command]
+pub async fn continue_session(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    message: String,
+) -> Result<String, String> {
+    let session = sessions.get(&session_id).await?;
+
+    let user_message = ConversationMessage {
+        role: "user".to_string(),
+        content: message,
+    };
+    let mut messages = session.messages;
+    messages.push(user_message.clone());
+
+    let answer = answer_with_history(
+        &claude,
+        &ai,
+        &session.model,
+        messages,
+        &session.content,
+        &RequestOptions::default(),
+        None,
+    )
+    .await?;
+
+    sessions
+        .append(
+            &session_id,
+            vec![
+                user_message,
+                ConversationMessage {
+                    role: "assistant".to_string(),
+                    content: answer.clone(),
+                },
+            ],
+        )
+        .await?;
+
+    Ok(answer)
+}
+
+#[tauri::command]
+pub async fn end_session(sessions: State<'_, SessionStore>, session_id: String) -> Result<(), String> {
+    sessions.end(&session_id).await;
+    Ok(())
+}
+
+/// Clean up pasted text before sending it to a model; no API call
+#[tauri::command]
+pub fn normalize_text(content: String) -> Result<String, String> {
+    Ok(text_util::normalize_text(&content))
+}
+
+/// The reply without the markdown code fence the model may have wrapped it in
+fn unwrap_markdown_fence(reply: &str) -> &str {
+    let trimmed = reply.trim();
+    let Some(rest) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    let Some((info, body)) = rest.split_once('\n') else {
+        return trimmed;
+    };
+    if !matches!(info.trim(), "" | "markdown" | "md") {
+        return trimmed;
+    }
+    body.trim_end().strip_suffix("```").map(str::trim).unwrap_or(trimmed)
+}
+
+/// Model-based counterpart of `normalize_text` for messy OCR/PDF extractions: the content
+/// is reformatted as markdown (headings, lists, tables) without changing its meaning
+#[tauri::command]
+pub async fn to_markdown(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    content: String,
+    model: String,
+) -> Result<String, String> {
+    if content.trim().is_empty() {
+        return Err("Content must not be empty".to_string());
+    }
+
+    let prompt = format!(
+        "다음 텍스트를 깔끔하고 구조적인 마크다운으로 다시 정리해주세요.\n\n\
+        ## 규칙:\n\
+        - 내용을 요약, 번역, 추가하지 말고 원문의 의미와 언어를 그대로 유지하세요\n\
+        - 제목은 #, ## 등의 헤딩으로, 나열은 목록으로, 표 형태의 데이터는 마크다운 표로 만드세요\n\
+        - 줄바꿈으로 끊어진 문장과 하이픈으로 나뉜 단어는 이어 붙이세요\n\
+        - 페이지 번호, 반복되는 머리글/바닥글 같은 추출 잔여물은 제거하세요\n\
+        - 다른 설명 없이 마크다운만 출력하세요\n\n\
+        {}",
+        text_util::document_block(&content)
+    );
+
+    let reply = send_prompt(&claude, &ai, &model, &prompt).await?;
+    let markdown = unwrap_markdown_fence(&reply);
+    if markdown.is_empty() {
+        return Err("Model returned no content".to_string());
+    }
+    Ok(markdown.to_string())
+}
+
+/// Table of contents of a document: its markdown heading tree, or for content without headings,
+/// section titles proposed by the model (Claude unless `model` says otherwise)
+#[tauri::command]
+pub async fn outline(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    content: String,
+    model: Option<String>,
+) -> Result<Vec<text_util::OutlineNode>, String> {
+    let headings = text_util::outline(&content);
+    if !headings.is_empty() {
+        return Ok(headings);
+    }
+    if content.trim().is_empty() {
+        return Err("Content must not be empty".to_string());
+    }
+
+    let prompt = format!(
+        "다음 문서의 목차를 만들어주세요. 문서의 흐름에 따라 섹션 제목을 제안하고, \
+        하위 섹션이 필요하면 ## 또는 ###로 나눠주세요. 제목은 문서와 같은 언어로 작성하고, \
+        다른 설명 없이 마크다운 헤딩(#, ##, ###) 줄만 출력해주세요.\n\n{}",
+        text_util::document_block(&content)
+    );
+    let reply = send_prompt(&claude, &ai, model.as_deref().unwrap_or("claude"), &prompt).await?;
+
+    let mut proposed = text_util::outline(&reply);
+    if proposed.is_empty() {
+        return Err("Model did not return an outline".to_string());
+    }
+    clear_outline_lines(&mut proposed);
+    Ok(proposed)
+}
+
+/// Proposed sections have no place in the content to jump to
+fn clear_outline_lines(nodes: &mut [text_util::OutlineNode]) {
+    for node in nodes {
+        node.line = None;
+        clear_outline_lines(&mut node.children);
+    }
+}
+
+/// Heading titles in document order (depth first), the order `set_outline_titles` consumes them
+fn outline_titles(nodes: &[text_util::OutlineNode], titles: &mut Vec<String>) {
+    for node in nodes {
+        titles.push(node.title.clone());
+        outline_titles(&node.children, titles);
+    }
+}
+
+fn set_outline_titles(nodes: &mut [text_util::OutlineNode], titles: &mut impl Iterator<Item = String>) {
+    for node in nodes {
+        if let Some(title) = titles.next() {
+            node.title = title;
+        }
+        set_outline_titles(&mut node.children, titles);
+    }
+}
+
+/// The document's heading tree with only the heading text translated, in one request;
+/// `line` still points at the heading in the original content
+#[tauri::command]
+pub async fn translate_outline(
+    claude: State<'_, ClaudeClient>,
+    content: String,
+    target_language: String,
+) -> Result<Vec<text_util::OutlineNode>, String> {
+    let mut outline = text_util::outline(&content);
+    if outline.is_empty() {
+        return Err("No headings found in the content".to_string());
+    }
+    let mut titles = Vec::new();
+    outline_titles(&outline, &mut titles);
+
+    let prompt = format!(
+        "다음 JSON 배열의 각 제목을 {}(으)로 번역해주세요. 순서와 개수를 그대로 유지하고, \
+        다른 설명 없이 번역된 제목의 JSON 문자열 배열만 출력해주세요.\n\n{}",
+        target_language,
+        serde_json::to_string(&titles).map_err(|e| e.to_string())?
+    );
+    let reply = claude.send_message(&prompt).await?;
+
+    let translated = parse_json_array::<String>(&reply).ok_or("Model did not return a list of headings")?;
+    if translated.len() != titles.len() {
+        return Err(format!(
+            "Model returned {} headings for {} in the document",
+            translated.len(),
+            titles.len()
+        ));
+    }
+    set_outline_titles(&mut outline, &mut translated.into_iter());
+    Ok(outline)
+}
+
+/// Split a paper's reference list and footnotes from its body; no API call
+#[tauri::command]
+pub fn extract_references(content: String) -> Result<ExtractedReferences, String> {
+    Ok(text_util::extract_references(&content))
+}
+
+/// Source and translation paired paragraph by paragraph (or sentence by sentence when the
+/// paragraph counts differ) for a two-column review; no API call
+#[tauri::command]
+pub fn align_translation(source: String, translated: String) -> Result<Vec<AlignedSegment>, String> {
+    Ok(text_util::align_translation(&source, &translated))
+}
+
+// Sections
+#[derive(Serialize, Clone, Debug)]
+pub struct Section {
+    /// `None` for text before the first heading or for heading-less documents
+    pub heading: Option<String>,
+    pub body: String,
+}
+
+/// Split markdown at `#`/`##` headings, ignoring `#` lines inside code blocks
+fn split_markdown_sections(content: &str) -> Vec<Section> {
+    let mut sections = Vec::new();
+    let mut heading: Option<String> = None;
+    let mut body: Vec<&str> = Vec::new();
+    let mut fence = text_util::CodeFence::default();
+
+    let mut flush = |heading: Option<String>, body: &mut Vec<&str>| {
+        let text = body.join("\n").trim().to_string();
+        body.clear();
+        if heading.is_some() || !text.is_empty() {
+            sections.push(Section { heading, body: text });
+        }
+    };
+
+    for line in content.lines() {
+        let section_heading = if fence.is_code(line) {
+            None
+        } else {
+            text_util::markdown_heading(line)
+                .filter(|(level, _)| *level <= 2)
+                .map(|(_, title)| title)
+        };
+
+        match section_heading {
+            Some(next) => flush(heading.replace(next), &mut body),
+            None => body.push(line),
+        }
+    }
+    flush(heading, &mut body);
+
+    sections
+}
+
+/// Sections at markdown headings; heading-less documents are grouped by paragraph instead
+fn sections_of(content: &str) -> Vec<Section> {
+    const FALLBACK_SECTION_CHARS: usize = 3000;
+
+    let sections = split_markdown_sections(content);
+    if sections.iter().any(|s| s.heading.is_some()) {
+        return sections;
+    }
+
+    ClaudeClient::split_content_for_translation(content, FALLBACK_SECTION_CHARS)
+        .into_iter()
+        .map(|chunk| chunk.trim().to_string())
+        .filter(|chunk| !chunk.is_empty())
+        .map(|body| Section { heading: None, body })
+        .collect()
+}
+
+#[tauri::command]
+pub fn split_into_sections(content: String) -> Result<Vec<Section>, String> {
+    Ok(sections_of(&content))
+}
+
+#[derive(Serialize)]
+pub struct SavedDocument {
+    pub parent_page_id: String,
+    /// In section order
+    pub child_page_ids: Vec<String>,
+    /// Why saving stopped partway; the pages listed above were still created
+    pub error: Option<String>,
+}
+
+/// Save a document too large for one page as an index page with one child page per section.
+/// Notion lists child pages on their parent in the order they were created, so that list is
+/// the index; nothing else is written to the parent. If a section fails, the pages created
+/// so far are kept and returned along with the error.
+#[tauri::command]
+pub async fn save_large_document(
+    mcp: State<'_, McpClient>,
+    database_id: String,
+    title: String,
+    content: String,
+) -> Result<SavedDocument, String> {
+    let content = McpClient::strip_frontmatter(&content);
+    let title = if title.trim().is_empty() {
+        first_line_title(content)
+    } else {
+        truncate_title(&title, NOTION_TEXT_LIMIT)
+    };
+
+    let sections = sections_of(content);
+    if sections.is_empty() {
+        return Err("Nothing to save".to_string());
+    }
+    let child_titles: Vec<String> = sections
+        .iter()
+        .enumerate()
+        .map(|(i, section)| match &section.heading {
+            Some(heading) if !heading.is_empty() => truncate_title(heading, NOTION_TEXT_LIMIT),
+            _ => format!("{} ({})", title, i + 1),
+        })
+        .collect();
+
+    let parent_page_id = mcp.save_to_notion(&database_id, &title, "", "").await?;
+
+    let mut child_page_ids = Vec::with_capacity(sections.len());
+    let mut error = None;
+    for (section, child_title) in sections.iter().zip(&child_titles) {
+        match mcp
+            .save_under_parent(&parent_page_id, child_title, &section.body)
+            .await
+        {
+            Ok(id) => child_page_ids.push(id),
+            Err(e) => {
+                error = Some(format!(
+                    "Saved {} of {} sections before failing: {}",
+                    child_page_ids.len(),
+                    sections.len(),
+                    e
+                ));
+                break;
+            }
+        }
+    }
+
+    Ok(SavedDocument {
+        parent_page_id,
+        child_page_ids,
+        error,
+    })
+}
+
+#[derive(Deserialize)]
+struct CitedPoint {
+    point: String,
+    /// Kept loose so one malformed entry ("3절", null, 2.5) doesn't reject the whole list
+    #[serde(default)]
+    sections: Vec<serde_json::Value>,
+}
+
+/// Section number from one `sections` entry: an integer, or a string holding one
+fn section_number(value: &serde_json::Value) -> Option<i64> {
+    match value {
+        serde_json::Value::Number(n) => n.as_i64(),
+        serde_json::Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+#[derive(Serialize)]
+pub struct Citation {
+    pub point: String,
+    /// Index into `sections`
+    pub section_index: usize,
+}
+
+#[derive(Serialize)]
+pub struct CitedSummary {
+    /// Key points as a list, each followed by the numbers of the sections it came from
+    pub summary: String,
+    pub sections: Vec<Section>,
+    pub citations: Vec<Citation>,
+}
+
+/// Summary whose key points cite the numbered sections they came from. Citations of
+/// sections that don't exist are dropped; the point itself is kept.
+#[tauri::command]
+pub async fn summarize_with_citations(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    content: String,
+    model: Option<String>,
+) -> Result<CitedSummary, String> {
+    let sections = sections_of(&content);
+    if sections.is_empty() {
+        return Err("Content must not be empty".to_string());
+    }
+
+    let numbered = sections
+        .iter()
+        .enumerate()
+        .map(|(i, section)| match &section.heading {
+            Some(heading) => format!("[{}] {}\n{}", i + 1, heading, section.body),
+            None => format!("[{}]\n{}", i + 1, section.body),
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let prompt = format!(
+        "다음 문서는 [1], [2] 처럼 번호가 붙은 섹션으로 나뉘어 있습니다. 문서의 핵심 포인트를 요약하고, \
+        각 포인트가 근거로 삼은 섹션 번호를 표시해주세요.\n\n\
+        다른 설명 없이 JSON 배열만 출력해주세요. 각 항목은 다음 형식을 따릅니다:\n\
+        [{{\"point\": \"핵심 포인트\", \"sections\": [1, 3]}}]\n\
+        섹션 번호는 문서에 있는 번호만 사용하고, point는 한글로 작성해주세요.\n\n{}",
+        text_util::document_block(&numbered)
+    );
+
+    let model = model.unwrap_or_else(|| "claude".to_string());
+    let response = send_prompt(&claude, &ai, &model, &prompt).await?;
+    let points = parse_json_array::<CitedPoint>(&response)
+        .ok_or_else(|| "Could not find a list of cited points in the model response".to_string())?;
+
+    let mut lines = Vec::new();
+    let mut citations = Vec::new();
+    for cited in points {
+        let point = cited.point.trim().to_string();
+        if point.is_empty() {
+            continue;
+        }
+        let mut indices: Vec<usize> = cited
+            .sections
+            .iter()
+            .filter_map(section_number)
+            .filter(|n| (1..=sections.len() as i64).contains(n))
+            .map(|n| n as usize - 1)
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+
+        let markers: String = indices.iter().map(|i| format!("[{}]", i + 1)).collect();
+        lines.push(format!("- {} {}", point, markers).trim_end().to_string());
+        citations.extend(indices.into_iter().map(|section_index| Citation {
+            point: point.clone(),
+            section_index,
+        }));
+    }
+    if lines.is_empty() {
+        return Err("Model returned no key points".to_string());
+    }
+
+    Ok(CitedSummary {
+        summary: lines.join("\n"),
+        sections,
+        citations,
+    })
+}
+
+// Document comparison
+#[tauri::command]
+pub async fn compare_documents(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    content_a: String,
+    content_b: String,
+    model: String,
+) -> Result<String, String> {
+    // Above this combined size only the diff hunks are sent instead of both documents
+    const FULL_COMPARE_MAX_CHARS: usize = 12000;
+    const DIFF_CHUNK_CHARS: usize = 6000;
+
+    if content_a.trim() == content_b.trim() {
+        return Ok("두 문서 사이에 차이가 없습니다.".to_string());
+    }
+
+    let instructions = "두 문서 버전을 비교하여 무엇이 바뀌었는지 구조적으로 정리해주세요.\n\n\
+        ## 출력 형식:\n\
+        ## 추가된 내용\n- ...\n\
+        ## 삭제된 내용\n- ...\n\
+        ## 변경된 내용\n- ...\n\n\
+        해당 사항이 없는 항목은 \"없음\"으로 표시하고, 반드시 한글 마크다운으로 작성해주세요.";
+
+    if content_a.chars().count() + content_b.chars().count() <= FULL_COMPARE_MAX_CHARS {
+        let prompt = format!(
+            "{}\n\n## 이전 문서 (A):\n{}\n\n## 새 문서 (B):\n{}",
+            instructions, content_a, content_b
+        );
+        return send_prompt(&claude, &ai, &model, &prompt).await;
+    }
+
+    // Large inputs: send only the changed hunks with a little surrounding context
+    let diff = similar::TextDiff::from_lines(&content_a, &content_b);
+    let unified = diff
+        .unified_diff()
+        .context_radius(2)
+        .header("A", "B")
+        .to_string();
+
+    let chunks = ClaudeClient::split_content_for_translation(&unified, DIFF_CHUNK_CHARS);
+    let total = chunks.len();
+    let mut summaries = Vec::new();
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let part = if total > 1 {
+            format!(" 이것은 전체 변경 사항 중 {}/{} 부분입니다.", i + 1, total)
+        } else {
+            String::new()
+        };
+        let prompt = format!(
+            "{}{}\n\n아래는 이전 문서(A)와 새 문서(B)의 unified diff입니다. \
+            '-'로 시작하는 줄은 삭제, '+'로 시작하는 줄은 추가된 내용입니다.\n\n```diff\n{}\n```",
+            instructions, part, chunk
+        );
+        summaries.push(send_prompt(&claude, &ai, &model, &prompt).await?);
+    }
+
+    Ok(summaries.join("\n\n---\n\n"))
+}
+
+// Save verification
+#[derive(Serialize)]
+pub struct SavedPageCheck {
+    /// 0.0 to 1.0 over the normalized lines of both versions
+    pub similarity: f32,
+    /// Expected lines that the saved page doesn't contain
+    pub missing_lines: Vec<String>,
+}
+
+/// A line as it should survive the round trip through Notion blocks: without markdown
+/// markers, checkboxes, list numbers or extra whitespace
+fn round_trip_line(line: &str) -> String {
+    let mut line = line.trim().trim_start_matches('#').trim_start();
+    if let Some(rest) = ["- [ ] ", "- [x] ", "- [X] ", "- ", "* ", "> "]
+        .iter()
+        .find_map(|prefix| line.strip_prefix(prefix))
+    {
+        line = rest;
+    }
+    if let Some((number, rest)) = line.split_once(". ") {
+        if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) {
+            line = rest;
+        }
+    }
+    line.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn round_trip_lines(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(round_trip_line)
+        .filter(|l| !l.is_empty() && l != "---" && !l.starts_with("```"))
+        .collect()
+}
+
+/// Fetch a saved page back and compare it with what was sent, to catch content lost in block conversion
+#[tauri::command]
+pub async fn verify_saved_page(
+    mcp: State<'_, McpClient>,
+    page_id: String,
+    expected_content: String,
+) -> Result<SavedPageCheck, String> {
+    let saved = mcp.fetch_all_block_text(&page_id).await?;
+
+    let expected = round_trip_lines(McpClient::strip_frontmatter(&expected_content));
+    let actual = round_trip_lines(&saved);
+
+    let mut available: HashMap<&str, usize> = HashMap::new();
+    for line in &actual {
+        *available.entry(line.as_str()).or_default() += 1;
+    }
+    let missing_lines = expected
+        .iter()
+        .filter(|line| match available.get_mut(line.as_str()) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        })
+        .cloned()
+        .collect();
+
+    let (expected, actual) = (expected.join("\n"), actual.join("\n"));
+    let similarity = similar::TextDiff::from_lines(&expected, &actual).ratio();
+
+    Ok(SavedPageCheck {
+        similarity,
+        missing_lines,
+    })
+}
+
+// Action items
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ActionItem {
+    pub task: String,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub due: Option<String>,
+}
+
+#[tauri::command]
+pub async fn extract_action_items(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    content: String,
+    model: Option<String>,
+) -> Result<Vec<ActionItem>, String> {
+    let model = model.unwrap_or_else(|| "claude".to_string());
+    let prompt = format!(
+        "다음 문서에서 실행해야 할 항목(Action Items)만 추출해주세요.\n\n\
+        다른 설명 없이 JSON 배열만 출력해주세요. 각 항목은 다음 형식을 따릅니다:\n\
+        [{{\"task\": \"할 일\", \"owner\": \"담당자 또는 null\", \"due\": \"기한 또는 null\"}}]\n\
+        담당자나 기한이 문서에 없으면 null로 두고, 실행 항목이 없으면 빈 배열 []을 출력해주세요. \
+        task는 한글로 작성해주세요.\n\n## 문서:\n{}",
+        content
+    );
+
+    let response = send_prompt(&claude, &ai, &model, &prompt).await?;
+    let items = parse_json_array::<ActionItem>(&response)
+        .ok_or_else(|| "Could not find a list of action items in the model response".to_string())?;
+
+    Ok(items
+        .into_iter()
+        .filter(|item| !item.task.trim().is_empty())
+        .collect())
+}
+
+/// Save action items as Notion to-do blocks
+#[tauri::command]
+pub async fn save_action_items_to_notion(
+    mcp: State<'_, McpClient>,
+    database_id: String,
+    title: String,
+    items: Vec<ActionItem>,
+    source_url: String,
+) -> Result<String, String> {
+    let mut body = items
+        .iter()
+        .map(|item| {
+            let mut details = Vec::new();
+            if let Some(owner) = item.owner.as_deref().filter(|o| !o.trim().is_empty()) {
+                details.push(format!("담당: {}", owner.trim()));
+            }
+            if let Some(due) = item.due.as_deref().filter(|d| !d.trim().is_empty()) {
+                details.push(format!("기한: {}", due.trim()));
+            }
+            if details.is_empty() {
+                format!("- [ ] {}", item.task.trim())
+            } else {
+                format!("- [ ] {} ({})", item.task.trim(), details.join(", "))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if !source_url.trim().is_empty() {
+        body.push_str(&format!("\n\n---\n## 출처\n{}", source_url.trim()));
+    }
+
+    let title = if title.trim().is_empty() {
+        "Action Items".to_string()
+    } else {
+        truncate_title(&title, NOTION_TEXT_LIMIT)
+    };
+    mcp.save_to_notion(&database_id, &title, &body, &source_url).await
+}
+
+// Distillation
+#[tauri::command]
+pub fn get_distill_model(provider: String) -> Result<String, String> {
+    AiClients::distill_model(&provider)
+}
+
+/// Model `distill_content` uses for `provider`; no model restores its cheapest one
+#[tauri::command]
+pub fn set_distill_model(provider: String, model: Option<String>) -> Result<(), String> {
+    AiClients::set_distill_model(&provider, model)
+}
+
+/// Opt-in cleanup before asking: a cheap model of `provider` (its cheapest unless configured
+/// with `set_distill_model`) strips boilerplate such as cookie notices, menus and
+/// related-article lists. Cached per content and model, so repeated asks about the same
+/// page reuse the distilled text.
+#[tauri::command]
+pub async fn distill_content(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    cache: State<'_, DistillCache>,
+    content: String,
+    provider: String,
+) -> Result<String, String> {
+    let model = AiClients::distill_model(&provider)?;
+    if let Some(distilled) = cache.get(&provider, &model, &content).await {
+        return Ok(distilled);
+    }
+
+    let options = RequestOptions {
+        model: Some(model.clone()),
+        ..Default::default()
+    };
+    let prompt = format!(
+        "다음 문서에서 쿠키 안내, 메뉴, 광고, 관련 글 목록, 푸터 같은 부수적인 내용을 제거하고 \
+        본문만 남겨주세요. 본문은 요약하거나 번역하지 말고 원문 그대로 유지하며, \
+        다른 설명 없이 본문만 출력해주세요.\n\n## 문서:\n{}",
+        content
+    );
 
-            // Add conversation history
-            for msg in messages {
-                openai_messages.push(crate::ai_clients::OpenAiMessage {
-                    role: msg.role,
-                    content: msg.content,
-                });
-            }
+    let distilled = send_prompt_with(&claude, &ai, &provider, &prompt, &options).await?;
+    let distilled = distilled.trim().to_string();
+    if distilled.is_empty() {
+        return Err("Model returned no content".to_string());
+    }
 
-            ai.ask_openai_with_history(openai_messages).await
-        }
-        "gemini" => {
-            // Gemini doesn't support system messages, so we include it in first user message
-            let mut first_message = system_prompt.clone();
-            if let Some(first) = messages.first() {
-                first_message = format!("{}\n\n사용자 질문: {}", system_prompt, first.content);
-            }
+    cache.insert(&provider, &model, &content, distilled.clone()).await;
+    Ok(distilled)
+}
 
-            // For Gemini, just use the simple ask method with the combined prompt
-            if messages.len() > 1 {
-                // Include conversation history in prompt
-                let history: Vec<String> = messages.iter()
-                    .map(|m| format!("{}: {}", if m.role == "user" { "사용자" } else { "AI" }, m.content))
-                    .collect();
-                let combined = format!("{}\n\n이전 대화:\n{}", first_message, history.join("\n"));
-                ai.ask_gemini(&content, &combined).await
-            } else if let Some(msg) = messages.last() {
-                ai.ask_gemini(&content, &msg.content).await
-            } else {
-                Err("No messages provided".to_string())
+// Database rollup
+const DEFAULT_DATABASE_SUMMARY_PAGES: usize = 20;
+const MAX_DATABASE_SUMMARY_PAGES: usize = 100;
+
+#[derive(Serialize)]
+pub struct PageSummary {
+    pub page_id: String,
+    pub title: String,
+    pub summary: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DatabaseSummary {
+    /// Summary across every page that could be summarized
+    pub overall: String,
+    pub pages: Vec<PageSummary>,
+}
+
+/// Summarize each page of a database (a few at a time), then summarize the summaries.
+/// Pages that fail are reported in `pages` instead of failing the rollup.
+#[tauri::command]
+pub async fn summarize_database(
+    mcp: State<'_, McpClient>,
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    database_id: String,
+    model: String,
+    max_pages: Option<usize>,
+) -> Result<DatabaseSummary, String> {
+    use futures::stream::{self, StreamExt};
+
+    // Kept low so fetches stay within Notion's rate limit of about 3 requests per second
+    const PAGE_CONCURRENCY: usize = 3;
+
+    let max_pages = max_pages.unwrap_or(DEFAULT_DATABASE_SUMMARY_PAGES);
+    if !(1..=MAX_DATABASE_SUMMARY_PAGES).contains(&max_pages) {
+        return Err(format!("max_pages must be between 1 and {}", MAX_DATABASE_SUMMARY_PAGES));
+    }
+
+    let rows = mcp.list_database_pages(&database_id, max_pages).await?;
+    if rows.is_empty() {
+        return Err("The database has no pages".to_string());
+    }
+
+    let (mcp, claude, ai, model) = (&*mcp, &*claude, &*ai, model.as_str());
+    let pages = stream::iter(rows)
+        .map(|row| async move {
+            let summary = async {
+                let content = mcp.fetch_notion_page(&row.id).await?;
+                let prompt = format!(
+                    "다음 내용을 핵심 포인트 중심으로 간결하게 요약해주세요. 반드시 한글로 작성해주세요.\n\n{}",
+                    content
+                );
+                send_prompt(claude, ai, model, &prompt).await
             }
-        }
-        _ => Err(format!("Unknown model: {}", model)),
+            .await;
+            let (summary, error) = match summary {
+                Ok(summary) => (Some(summary), None),
+                Err(error) => (None, Some(error)),
+            };
+            PageSummary {
+                page_id: row.id,
+                title: row.name,
+                summary,
+                error,
+            }
+        })
+        .buffered(PAGE_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    let sections: Vec<String> = pages
+        .iter()
+        .filter_map(|page| {
+            let summary = page.summary.as_deref()?;
+            Some(format!("## {}\n{}", page.title, summary.trim()))
+        })
+        .collect();
+    if sections.is_empty() {
+        return Err(format!(
+            "Could not summarize any of the pages: {}",
+            pages
+                .iter()
+                .map(|p| format!("{} ({})", p.title, p.error.as_deref().unwrap_or_default()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
     }
+
+    let prompt = format!(
+        "다음은 한 데이터베이스에 속한 페이지들의 요약입니다. 페이지 전체에 걸친 공통 주제, \
+        주요 결정 사항, 흐름을 중심으로 종합 요약을 작성해주세요. 반드시 한글로 작성해주세요.\n\n{}",
+        sections.join("\n\n")
+    );
+    let overall = send_prompt(claude, ai, model, &prompt).await?;
+
+    Ok(DatabaseSummary { overall, pages })
 }
 
-// Extract text from file data (base64 encoded)
+// Flashcards
+const MAX_FLASHCARDS: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Flashcard {
+    pub question: String,
+    pub answer: String,
+}
+
+/// Question/answer study cards from the document; `count` is capped at 50
 #[tauri::command]
-pub async fn extract_text_from_file(
-    file_data: String,
-    file_type: String,
+pub async fn generate_flashcards(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    content: String,
+    count: usize,
+    model: Option<String>,
+) -> Result<Vec<Flashcard>, String> {
+    if !(1..=MAX_FLASHCARDS).contains(&count) {
+        return Err(format!("Flashcard count must be between 1 and {}", MAX_FLASHCARDS));
+    }
+
+    let model = model.unwrap_or_else(|| "claude".to_string());
+    let prompt = format!(
+        "다음 문서의 핵심 내용을 학습할 수 있도록 질문과 답변 쌍 {}개를 만들어주세요.\n\n\
+        다른 설명 없이 JSON 배열만 출력해주세요. 각 항목은 다음 형식을 따릅니다:\n\
+        [{{\"question\": \"질문\", \"answer\": \"답변\"}}]\n\
+        질문은 문서만 보고 답할 수 있어야 하며, 답변은 간결하게 한글로 작성해주세요.\n\n## 문서:\n{}",
+        count, content
+    );
+
+    let response = send_prompt(&claude, &ai, &model, &prompt).await?;
+    let cards = parse_json_array::<Flashcard>(&response)
+        .ok_or_else(|| "Could not find a list of flashcards in the model response".to_string())?;
+
+    Ok(cards
+        .into_iter()
+        .filter(|card| !card.question.trim().is_empty() && !card.answer.trim().is_empty())
+        .take(count)
+        .collect())
+}
+
+/// Save flashcards as Notion toggle blocks: the question opens to its answer
+#[tauri::command]
+pub async fn save_flashcards_to_notion(
+    mcp: State<'_, McpClient>,
+    database_id: String,
+    title: String,
+    cards: Vec<Flashcard>,
 ) -> Result<String, String> {
-    use base64::Engine;
+    let toggles: Vec<(String, String)> = cards
+        .into_iter()
+        .map(|card| (card.question.trim().to_string(), card.answer.trim().to_string()))
+        .collect();
 
-    // Decode base64 data URL
-    let data = if file_data.contains(",") {
-        // Data URL format: data:mime;base64,xxxxx
-        file_data.split(',').nth(1).unwrap_or(&file_data)
+    let title = if title.trim().is_empty() {
+        "Flashcards".to_string()
     } else {
-        &file_data
+        truncate_title(&title, NOTION_TEXT_LIMIT)
     };
+    mcp.save_toggles_to_notion(&database_id, &title, &toggles).await
+}
 
-    let bytes = base64::engine::general_purpose::STANDARD
-        .decode(data)
-        .map_err(|e| format!("Failed to decode base64: {}", e))?;
+// Readiness
+#[derive(Serialize)]
+pub struct AppStatus {
+    pub claude_key_set: bool,
+    pub openai_key_set: bool,
+    pub gemini_key_set: bool,
+    pub notion_token_set: bool,
+    pub mcp_connected: bool,
+    pub npx_found: bool,
+    pub npx_path: Option<String>,
+    pub config_writable: bool,
+    pub config_dir: Option<String>,
+    pub passphrase_set: bool,
+    /// Passphrase set but `unlock` not called yet, so keys read as missing
+    pub locked: bool,
+    /// One entry per provider; an open breaker fails calls immediately until its cooldown ends
+    pub circuit_breakers: Vec<BreakerStatus>,
+}
 
-    match file_type.as_str() {
-        "pdf" => extract_pdf_text(&bytes),
-        "xls" | "xlsx" => extract_excel_text(&bytes, &file_type),
-        "doc" | "docx" | "ppt" | "pptx" => {
-            // For Office formats, we return a placeholder message
-            // Full extraction would require additional libraries
-            Ok(format!(
-                "[{} 파일]\n\n이 파일 형식의 텍스트 추출은 현재 지원되지 않습니다.\n\
-                PDF 또는 Excel 파일을 사용하시거나, 텍스트를 직접 복사하여 붙여넣기 해주세요.",
-                file_type.to_uppercase()
-            ))
-        }
-        "image" => {
-            Ok("[이미지 파일]\n\n이미지에서 텍스트를 추출하려면 OCR이 필요합니다.\n\
-                현재는 이미지 미리보기만 지원됩니다.".to_string())
-        }
-        _ => Err(format!("Unsupported file type: {}", file_type)),
+/// Whether the config directory exists (or can be created) and accepts writes
+fn config_dir_writable() -> bool {
+    let Some(dir) = dirs::config_dir() else {
+        return false;
+    };
+    if std::fs::create_dir_all(&dir).is_err() {
+        return false;
     }
+    let probe = dir.join(".ainotework_write_test");
+    let writable = std::fs::write(&probe, b"ok").is_ok();
+    let _ = std::fs::remove_file(&probe);
+    writable
 }
 
-fn extract_pdf_text(bytes: &[u8]) -> Result<String, String> {
-    pdf_extract::extract_text_from_mem(bytes)
-        .map_err(|e| format!("Failed to extract PDF text: {}", e))
+/// Everything the onboarding/diagnostics panel needs in one call
+#[tauri::command]
+pub async fn get_status(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    mcp: State<'_, McpClient>,
+) -> Result<AppStatus, String> {
+    // Keys may be saved but not loaded into memory yet this session
+    let claude_key_set = claude.get_api_key().await.is_some() || claude.load_api_key().await.is_some();
+    let openai_key_set = ai.get_openai_key().await.is_some() || ai.load_openai_key().await.is_some();
+    let gemini_key_set = ai.get_gemini_key().await.is_some() || ai.load_gemini_key().await.is_some();
+    let notion_token_set = mcp.get_notion_token().await.is_some()
+        || mcp.load_notion_token().await.is_some()
+        || std::env::var("NOTION_TOKEN").is_ok();
+    let npx_path = McpClient::find_npx();
+    let passphrase_set = vault::is_enabled();
+
+    Ok(AppStatus {
+        claude_key_set,
+        openai_key_set,
+        gemini_key_set,
+        notion_token_set,
+        mcp_connected: mcp.is_connected().await,
+        npx_found: npx_path.is_some(),
+        npx_path,
+        config_writable: config_dir_writable(),
+        config_dir: dirs::config_dir().map(|d| d.to_string_lossy().to_string()),
+        passphrase_set,
+        locked: passphrase_set && !vault::is_unlocked(),
+        circuit_breakers: std::iter::once(claude.breaker_status())
+            .chain(ai.breaker_statuses())
+            .collect(),
+    })
 }
 
-fn extract_excel_text(bytes: &[u8], file_type: &str) -> Result<String, String> {
-    use calamine::{Reader, Xlsx, Xls};
-    use std::io::Cursor;
+#[derive(Serialize)]
+pub struct ConfigFileStatus {
+    pub name: String,
+    pub path: String,
+    pub exists: bool,
+    pub error: Option<String>,
+}
 
-    let cursor = Cursor::new(bytes);
-    let mut result = String::new();
+#[derive(Serialize)]
+pub struct ConfigLocation {
+    pub dir: Option<String>,
+    pub writable: bool,
+    pub files: Vec<ConfigFileStatus>,
+}
 
-    match file_type {
-        "xlsx" => {
-            let mut workbook: Xlsx<_> = calamine::open_workbook_from_rs(cursor)
-                .map_err(|e| format!("Failed to open xlsx: {}", e))?;
+/// Where settings live and whether each file is readable, so a "key won't save" can be tracked down
+#[tauri::command]
+pub async fn config_path() -> Result<ConfigLocation, String> {
+    let paths = [
+        ("claude", ClaudeClient::get_config_path()),
+        ("ai", AiClients::get_config_path()),
+        ("notion", McpClient::get_config_path()),
+        ("web", WebClient::get_config_path()),
+        ("templates", templates::get_config_path()),
+        ("preferences", preferences::get_config_path()),
+        ("seen_content", seen_content::get_config_path()),
+    ];
 
-            for sheet_name in workbook.sheet_names().to_vec() {
-                result.push_str(&format!("## Sheet: {}\n\n", sheet_name));
-                if let Ok(range) = workbook.worksheet_range(&sheet_name) {
-                    for row in range.rows() {
-                        let row_str: Vec<String> = row.iter()
-                            .map(|cell| cell.to_string())
-                            .collect();
-                        result.push_str(&row_str.join("\t"));
-                        result.push('\n');
-                    }
-                }
-                result.push('\n');
-            }
-        }
-        "xls" => {
-            let mut workbook: Xls<_> = calamine::open_workbook_from_rs(cursor)
-                .map_err(|e| format!("Failed to open xls: {}", e))?;
+    let files = paths
+        .into_iter()
+        .filter_map(|(name, path)| path.map(|p| (name, p)))
+        .map(|(name, path)| ConfigFileStatus {
+            name: name.to_string(),
+            exists: path.exists(),
+            error: config_file::check(&path),
+            path: path.to_string_lossy().to_string(),
+        })
+        .collect();
 
-            for sheet_name in workbook.sheet_names().to_vec() {
-                result.push_str(&format!("## Sheet: {}\n\n", sheet_name));
-                if let Ok(range) = workbook.worksheet_range(&sheet_name) {
-                    for row in range.rows() {
-                        let row_str: Vec<String> = row.iter()
-                            .map(|cell| cell.to_string())
-                            .collect();
-                        result.push_str(&row_str.join("\t"));
-                        result.push('\n');
-                    }
-                }
-                result.push('\n');
+    Ok(ConfigLocation {
+        dir: dirs::config_dir().map(|d| d.to_string_lossy().to_string()),
+        writable: config_dir_writable(),
+        files,
+    })
+}
+
+// Master passphrase
+/// Encrypt stored API keys and tokens under a passphrase; also used to change it.
+/// Secrets are decrypted with the current key first and re-sealed under the new one into
+/// staged files; the configs and the vault are then committed together, so a failure leaves
+/// the old passphrase and keys in place.
+#[tauri::command]
+pub async fn set_master_passphrase(passphrase: String) -> Result<(), String> {
+    let claude_config = ClaudeClient::load_config_revealed()?;
+    let ai_config = AiClients::load_config_revealed()?;
+    let notion_config = McpClient::load_config_revealed()?;
+
+    let passphrase = vault::prepare_passphrase(&passphrase)?;
+    let staged = vec![
+        ClaudeClient::stage_config_resealed(&claude_config, &passphrase)?,
+        AiClients::stage_config_resealed(&ai_config, &passphrase)?,
+        McpClient::stage_config_resealed(&notion_config, &passphrase)?,
+        passphrase.stage_vault()?,
+    ];
+    config_file::commit_all(staged)?;
+
+    passphrase.activate();
+    Ok(())
+}
+
+/// Decrypt stored secrets into memory; needed once per launch when a passphrase is set
+#[tauri::command]
+pub async fn unlock(
+    passphrase: String,
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    mcp: State<'_, McpClient>,
+) -> Result<(), String> {
+    vault::unlock(&passphrase)?;
+
+    claude.load_api_key().await;
+    ai.load_openai_key().await;
+    ai.load_gemini_key().await;
+    mcp.load_notion_token().await;
+    Ok(())
+}
+
+// Settings backup
+const SETTINGS_FORMAT: &str = "ainotework-settings";
+const SETTINGS_VERSION: u32 = 1;
+
+/// Every settings file in one document; sections left out of an import are not touched
+#[derive(Serialize, Deserialize)]
+struct SettingsBundle {
+    format: String,
+    version: u32,
+    #[serde(default)]
+    exported_at: u64,
+    #[serde(default)]
+    includes_secrets: bool,
+    #[serde(default)]
+    claude: Option<ClaudeConfig>,
+    #[serde(default)]
+    ai: Option<AiConfig>,
+    #[serde(default)]
+    notion: Option<NotionConfig>,
+    #[serde(default)]
+    web: Option<WebConfig>,
+    #[serde(default)]
+    templates: Option<Vec<PromptTemplate>>,
+    #[serde(default)]
+    preferences: Option<UiPreferences>,
+    #[serde(default)]
+    seen_content: Option<HashMap<String, SeenContent>>,
+    /// Only with secrets: the passphrase the keys in the file are encrypted with, if any
+    #[serde(default)]
+    vault: Option<VaultFile>,
+}
+
+#[derive(Serialize)]
+pub struct ImportedSettings {
+    pub sections: Vec<String>,
+    pub templates_imported: usize,
+    /// Timeouts, token limits and similar are read at startup
+    pub restart_required: bool,
+    /// The file brought its own master passphrase; keys can be used once it is unlocked
+    pub unlock_required: bool,
+}
+
+/// Write all settings to one file. API keys and tokens are only included when asked for, and are
+/// written as stored: encrypted, together with the vault, when a master passphrase is set.
+#[tauri::command]
+pub async fn export_settings(path: String, include_secrets: Option<bool>) -> Result<String, String> {
+    let include_secrets = include_secrets.unwrap_or(false);
+    let exported_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let bundle = SettingsBundle {
+        format: SETTINGS_FORMAT.to_string(),
+        version: SETTINGS_VERSION,
+        exported_at,
+        includes_secrets: include_secrets,
+        claude: Some(ClaudeClient::export_settings(include_secrets)?),
+        ai: Some(AiClients::export_settings(include_secrets)?),
+        notion: Some(McpClient::export_settings(include_secrets)?),
+        web: Some(WebClient::export_settings()?),
+        templates: Some(templates::export_templates()?),
+        preferences: Some(preferences::export()?),
+        seen_content: Some(seen_content::export_entries()?),
+        vault: include_secrets.then(vault::export_vault).transpose()?,
+    };
+
+    config_file::write_json(std::path::Path::new(&path), &bundle)?;
+    Ok(path)
+}
+
+/// Merge a file written by `export_settings` into the current settings.
+/// Every section is validated and staged first, then all files are written together.
+#[tauri::command]
+pub async fn import_settings(
+    path: String,
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    mcp: State<'_, McpClient>,
+) -> Result<ImportedSettings, String> {
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read settings file: {}", e))?;
+    let bundle: SettingsBundle = serde_json::from_str(&content)
+        .map_err(|e| format!("Not a valid settings file: {}", e))?;
+
+    if bundle.format != SETTINGS_FORMAT {
+        return Err(format!("Not an AINoteWork settings file (format: {})", bundle.format));
+    }
+    if bundle.version == 0 || bundle.version > SETTINGS_VERSION {
+        return Err(format!(
+            "Unsupported settings version {} (this app reads up to {})",
+            bundle.version, SETTINGS_VERSION
+        ));
+    }
+
+    // Another passphrase replaces every stored key, so all sections holding keys must be there
+    let replacing_vault = bundle.vault.as_ref().is_some_and(vault::replaces_current);
+    if replacing_vault && (bundle.claude.is_none() || bundle.ai.is_none() || bundle.notion.is_none()) {
+        return Err("Settings file has a master passphrase but is missing some of its keys".to_string());
+    }
+
+    let mut staged = Vec::new();
+    let mut sections = Vec::new();
+    if let Some(section) = bundle.claude {
+        staged.push(ClaudeClient::stage_import(section, replacing_vault)?);
+        sections.push("claude".to_string());
+    }
+    if let Some(section) = bundle.ai {
+        staged.push(AiClients::stage_import(section, replacing_vault)?);
+        sections.push("ai".to_string());
+    }
+    if let Some(section) = bundle.notion {
+        staged.push(McpClient::stage_import(section, replacing_vault)?);
+        sections.push("notion".to_string());
+    }
+    if let Some(section) = bundle.web {
+        staged.push(WebClient::stage_import(section)?);
+        sections.push("web".to_string());
+    }
+    let mut templates_imported = 0;
+    if let Some(imported) = bundle.templates {
+        let (file, count) = templates::stage_import(imported)?;
+        staged.push(file);
+        templates_imported = count;
+        sections.push("templates".to_string());
+    }
+    if let Some(section) = bundle.preferences {
+        staged.push(preferences::stage_import(section)?);
+        sections.push("preferences".to_string());
+    }
+    if let Some(entries) = bundle.seen_content {
+        staged.push(seen_content::stage_import(entries)?);
+        sections.push("seen_content".to_string());
+    }
+    if let Some(imported) = bundle.vault.filter(|_| replacing_vault) {
+        staged.push(vault::stage_imported(&imported)?);
+        sections.push("vault".to_string());
+    }
+    config_file::commit_all(staged)?;
+
+    // Credentials take effect right away; the rest is picked up on the next launch
+    if replacing_vault {
+        vault::lock();
+    }
+    claude.load_api_key().await;
+    ai.load_openai_key().await;
+    ai.load_gemini_key().await;
+    mcp.load_notion_token().await;
+
+    let restart_required = sections
+        .iter()
+        .any(|s| !matches!(s.as_str(), "templates" | "seen_content" | "vault"));
+    Ok(ImportedSettings {
+        sections,
+        templates_imported,
+        restart_required,
+        unlock_required: replacing_vault,
+    })
+}
+
+// App info
+#[derive(Serialize)]
+pub struct AppInfo {
+    pub version: String,
+    pub build_timestamp: String,
+    pub os: String,
+    pub arch: String,
+}
+
+/// Build details for the in-app diagnostics screen; the version comes from tauri.conf.json like the About menu
+#[tauri::command]
+pub fn get_app_info(app: tauri::AppHandle) -> Result<AppInfo, String> {
+    Ok(AppInfo {
+        version: app.package_info().version.to_string(),
+        build_timestamp: env!("BUILD_TIMESTAMP", "Unknown").to_string(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+    })
+}
+
+// Debugging
+#[tauri::command]
+pub fn set_debug_capture(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    enabled: bool,
+) -> Result<(), String> {
+    claude.set_capture_raw(enabled);
+    ai.set_capture_raw(enabled);
+    Ok(())
+}
+
+/// Untruncated (but key-redacted) body of the last response from each provider
+#[tauri::command]
+pub async fn debug_last_response(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    provider: Option<String>,
+) -> Result<HashMap<String, String>, String> {
+    let mut responses = HashMap::new();
+    let wants = |name: &str| provider.as_deref().map(|p| p == name).unwrap_or(true);
+
+    if wants("claude") {
+        if let Some(body) = claude.last_raw_response().await {
+            responses.insert("claude".to_string(), body);
+        }
+    }
+    for name in ["openai", "gemini"] {
+        if wants(name) {
+            if let Some(body) = ai.last_raw_response(name).await {
+                responses.insert(name.to_string(), body);
             }
         }
-        _ => return Err(format!("Unsupported Excel format: {}", file_type)),
     }
 
-    Ok(result)
+    Ok(responses)
+}
+
+// "Try again" with a deliberately different result
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn regenerate(
+    claude: State<'_, ClaudeClient>,
+    ai: State<'_, AiClients>,
+    operation: String,
+    content: String,
+    previous_output: String,
+    model: String,
+    target_language: Option<String>,
+    question: Option<String>,
+) -> Result<String, String> {
+    // OpenAI and Gemini default to 1.0 and accept up to 2.0. Claude's default of 1.0 is already its
+    // maximum, so there the prompt alone asks for something different.
+    const REGENERATE_TEMPERATURE: f32 = 1.3;
+
+    // Deterministic mode pins the temperature to 0, which would defeat the point
+    if claude.get_deterministic_mode() {
+        return Err("Deterministic mode is on; turn it off to regenerate a different result".to_string());
+    }
+
+    let task = match operation.as_str() {
+        "translate" => format!(
+            "다음 내용을 {}(으)로 번역해주세요. 번역문만 출력해주세요.",
+            target_language.as_deref().unwrap_or("Korean")
+        ),
+        "summarize" => {
+            "다음 내용을 핵심 포인트 중심으로 간결하게 요약해주세요. 반드시 한글로 작성해주세요.".to_string()
+        }
+        "ask" => format!(
+            "다음 문서를 바탕으로 질문에 전문적이고 상세하게 답변해주세요. 반드시 한글로 답변해주세요.\n\n## 질문:\n{}",
+            question.as_deref().unwrap_or_default()
+        ),
+        _ => return Err(format!("Unknown operation: {}", operation)),
+    };
+
+    let prompt = format!(
+        "{}\n\n이전 결과와는 다른 관점과 표현으로 작성해주세요. 이전 결과를 반복하지 마세요.\n\n\
+        ## 이전 결과:\n{}\n\n## 문서:\n{}",
+        task, previous_output, content
+    );
+
+    let options = RequestOptions {
+        temperature: (model != "claude").then_some(REGENERATE_TEMPERATURE),
+        ..Default::default()
+    };
+    send_prompt_with(&claude, &ai, &model, &prompt, &options)
+        .await
+        .map_err(String::from)
+}
+
+// RSS/Atom feeds
+#[derive(Serialize)]
+pub struct FeedEntrySummary {
+    pub title: String,
+    pub link: String,
+    pub published: Option<String>,
+    pub summary: Option<String>,
+    pub error: Option<String>,
+}
+
+#[tauri::command]
+pub async fn fetch_feed(web: State<'_, WebClient>, feed_url: String) -> Result<Vec<FeedEntry>, String> {
+    web.fetch_feed(&feed_url).await
+}
+
+#[tauri::command]
+pub async fn summarize_feed(
+    mcp: State<'_, McpClient>,
+    web: State<'_, WebClient>,
+    claude: State<'_, ClaudeClient>,
+    feed_url: String,
+    limit: Option<usize>,
+) -> Result<Vec<FeedEntrySummary>, String> {
+    use futures::stream::{self, StreamExt};
+
+    const DEFAULT_FEED_LIMIT: usize = 5;
+    const FEED_CONCURRENCY: usize = 3;
+
+    let entries = web.fetch_feed(&feed_url).await?;
+    let limit = limit.unwrap_or(DEFAULT_FEED_LIMIT);
+
+    let (mcp, web, claude) = (&*mcp, &*web, &*claude);
+    let summaries = stream::iter(entries.into_iter().take(limit))
+        .map(|entry| async move {
+            let result = match fetch_url_content(mcp, web, &entry.link).await {
+                Ok(content) => claude.summarize(&content).await.map_err(String::from),
+                Err(e) => Err(e),
+            };
+            let (summary, error) = match result {
+                Ok(summary) => (Some(summary), None),
+                Err(e) => (None, Some(e)),
+            };
+            FeedEntrySummary {
+                title: entry.title,
+                link: entry.link,
+                published: entry.published,
+                summary,
+                error,
+            }
+        })
+        .buffered(FEED_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    Ok(summaries)
 }
@@ -0,0 +1,170 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Tells apart temp files staged by the same process, e.g. two saves of one file at once
+static STAGE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub fn config_file_path(file_name: &str) -> Option<PathBuf> {
+    dirs::config_dir().map(|p| p.join(file_name))
+}
+
+/// A missing file means nothing has been saved yet and yields the defaults.
+/// Permission problems and malformed JSON are errors so the user can find out why settings don't stick.
+pub fn read_json<T: DeserializeOwned + Default>(path: &Path) -> Result<T, String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(T::default()),
+        Err(e) => return Err(format!("Cannot read config file {}: {}", path.display(), e)),
+    };
+
+    serde_json::from_str(&content).map_err(|e| {
+        format!(
+            "Config file {} is not valid ({}); fix or remove it",
+            path.display(),
+            e
+        )
+    })
+}
+
+/// Creates the config directory first; it doesn't exist on a fresh profile on some systems
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    stage_json(path, value)?.commit()
+}
+
+/// A config file written and synced beside its target, not yet moved into place.
+/// Dropping it without committing removes the written copy.
+pub struct StagedFile {
+    tmp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl StagedFile {
+    pub fn commit(self) -> Result<(), String> {
+        std::fs::rename(&self.tmp_path, &self.path)
+            .map_err(|e| format!("Failed to write config file {}: {}", self.path.display(), e))
+    }
+}
+
+impl Drop for StagedFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.tmp_path);
+    }
+}
+
+/// The first half of `write_json`, for files that must change together; see `commit_all`
+pub fn stage_json<T: Serialize>(path: &Path, value: &T) -> Result<StagedFile, String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Cannot create config directory {}: {}", dir.display(), e))?;
+    }
+
+    let content = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize config: {}", e))?;
+
+    // Write beside the target and rename over it, so a crash mid-write leaves the old file intact
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    let seq = STAGE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    tmp_name.push(format!(".{}.{}.tmp", std::process::id(), seq));
+    let staged = StagedFile {
+        tmp_path: path.with_file_name(tmp_name),
+        path: path.to_path_buf(),
+    };
+
+    write_new_synced(&staged.tmp_path, content.as_bytes())
+        .map_err(|e| format!("Failed to write config file {}: {}", path.display(), e))?;
+    Ok(staged)
+}
+
+/// Move staged files into place. If one can't be moved, the ones already moved get their
+/// previous content back (or are removed if they didn't exist) and the rest are discarded.
+pub fn commit_all(files: Vec<StagedFile>) -> Result<(), String> {
+    let mut committed: Vec<(PathBuf, Option<Vec<u8>>)> = Vec::new();
+    for file in files {
+        let path = file.path.clone();
+        let previous = std::fs::read(&path).ok();
+        if let Err(e) = file.commit() {
+            for (path, previous) in committed.into_iter().rev() {
+                let _ = match previous {
+                    Some(content) => write_synced(&path, &content),
+                    None => std::fs::remove_file(&path),
+                };
+            }
+            return Err(e);
+        }
+        committed.push((path, previous));
+    }
+    Ok(())
+}
+
+fn write_synced(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+/// `write_synced` that fails instead of writing through a file (or link) already at `path`
+fn write_new_synced(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut file = std::fs::OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+/// Problem with an existing config file, if any
+pub fn check(path: &Path) -> Option<String> {
+    read_json::<serde_json::Value>(path).err()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commit_all_restores_committed_files_when_one_fails() {
+        let dir = std::env::temp_dir().join(format!("ainotework_commit_all_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let kept = dir.join("kept.json");
+        let fresh = dir.join("fresh.json");
+        let blocked = dir.join("blocked.json");
+        std::fs::write(&kept, "\"old\"").unwrap();
+
+        let staged = vec![
+            stage_json(&kept, &"new").unwrap(),
+            stage_json(&fresh, &"new").unwrap(),
+            stage_json(&blocked, &"new").unwrap(),
+        ];
+        // A non-empty directory in the way makes the last rename fail
+        std::fs::create_dir_all(blocked.join("in_the_way")).unwrap();
+
+        assert!(commit_all(staged).is_err());
+        assert_eq!(std::fs::read_to_string(&kept).unwrap(), "\"old\"");
+        assert!(!fresh.exists());
+        let leftovers: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn staging_one_file_twice_uses_separate_temp_files() {
+        let dir = std::env::temp_dir().join(format!("ainotework_stage_twice_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("settings.json");
+
+        let first = stage_json(&path, &"first").unwrap();
+        let second = stage_json(&path, &"second").unwrap();
+        commit_all(vec![first]).unwrap();
+        commit_all(vec![second]).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "\"second\"");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
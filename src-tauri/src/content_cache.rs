@@ -0,0 +1,90 @@
+use crate::ttl_cache::TtlCache;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Fetched content older than this is fetched again
+const CONTENT_TTL: Duration = Duration::from_secs(10 * 60);
+/// The oldest entry is dropped to make room beyond this many
+const MAX_CACHED_PAGES: usize = 64;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CacheEntry {
+    pub url: String,
+    pub size_bytes: usize,
+    pub age_secs: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CacheListing {
+    /// Newest first
+    pub entries: Vec<CacheEntry>,
+    pub total_bytes: usize,
+}
+
+/// Extracted text of recently fetched pages and URLs, keyed by the URL as given
+pub struct ContentCache {
+    entries: TtlCache<String, String>,
+}
+
+impl ContentCache {
+    pub fn new() -> Self {
+        Self {
+            entries: TtlCache::new(CONTENT_TTL, MAX_CACHED_PAGES),
+        }
+    }
+
+    pub async fn get(&self, url: &str) -> Option<String> {
+        self.entries.get(&url.to_string()).await
+    }
+
+    pub async fn insert(&self, url: &str, text: &str) {
+        self.entries.insert(url.to_string(), text.to_string()).await;
+    }
+
+    /// Live entries with their size and age
+    pub async fn list(&self) -> CacheListing {
+        let mut listed = self
+            .entries
+            .map_live(|url, text, age| CacheEntry {
+                url: url.clone(),
+                size_bytes: text.len(),
+                age_secs: age.as_secs(),
+            })
+            .await;
+        listed.sort_by_key(|e| e.age_secs);
+
+        CacheListing {
+            total_bytes: listed.iter().map(|e| e.size_bytes).sum(),
+            entries: listed,
+        }
+    }
+
+    /// Whether there was an entry for `url` to drop
+    pub async fn evict(&self, url: &str) -> bool {
+        self.entries.evict(&url.to_string()).await
+    }
+
+    /// Drop the entries for pages on `host` (without `www.`), returning how many were dropped
+    pub async fn evict_host(&self, host: &str) -> usize {
+        self.entries
+            .evict_where(|url| url_host(url).is_some_and(|h| h == host))
+            .await
+    }
+
+    pub async fn clear(&self) {
+        self.entries.clear().await;
+    }
+}
+
+/// Lowercase host of `url` without a leading `www.`
+pub(crate) fn url_host(url: &str) -> Option<String> {
+    reqwest::Url::parse(url.trim())
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.trim_start_matches("www.").to_lowercase()))
+}
+
+impl Default for ContentCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,72 @@
+use crate::text_util::content_hash;
+use crate::ttl_cache::TtlCache;
+use std::time::Duration;
+
+/// Distilled text older than this is produced again
+const DISTILL_TTL: Duration = Duration::from_secs(60 * 60);
+/// The oldest entry is dropped to make room beyond this many
+const MAX_DISTILLED: usize = 32;
+
+/// Distilled versions of recently seen content, keyed by provider, model and a hash of the content
+pub struct DistillCache {
+    entries: TtlCache<(String, String, String), String>,
+}
+
+impl DistillCache {
+    pub fn new() -> Self {
+        Self {
+            entries: TtlCache::new(DISTILL_TTL, MAX_DISTILLED),
+        }
+    }
+
+    fn key(provider: &str, model: &str, content: &str) -> (String, String, String) {
+        (provider.to_string(), model.to_string(), content_hash(content))
+    }
+
+    pub async fn get(&self, provider: &str, model: &str, content: &str) -> Option<String> {
+        self.entries.get(&Self::key(provider, model, content)).await
+    }
+
+    pub async fn insert(&self, provider: &str, model: &str, content: &str, text: String) {
+        self.entries.insert(Self::key(provider, model, content), text).await;
+    }
+}
+
+impl Default for DistillCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn distilled_text_is_reused_for_the_same_content_and_model() {
+        let cache = DistillCache::new();
+        cache.insert("claude", "claude-3-5-haiku-20241022", "본문과 메뉴", "본문".to_string()).await;
+
+        assert_eq!(
+            cache.get("claude", "claude-3-5-haiku-20241022", "본문과 메뉴").await.as_deref(),
+            Some("본문")
+        );
+    }
+
+    #[tokio::test]
+    async fn other_content_provider_or_model_misses() {
+        let cache = DistillCache::new();
+        cache.insert("claude", "claude-3-5-haiku-20241022", "본문과 메뉴", "본문".to_string()).await;
+
+        assert!(cache.get("claude", "claude-3-5-haiku-20241022", "본문과 광고").await.is_none());
+        assert!(cache.get("openai", "claude-3-5-haiku-20241022", "본문과 메뉴").await.is_none());
+        assert!(cache.get("claude", "claude-sonnet-4-20250514", "본문과 메뉴").await.is_none());
+    }
+
+    #[test]
+    fn key_uses_the_stable_content_hash() {
+        let (_, _, hash) = DistillCache::key("gemini", "gemini-2.0-flash-lite", "abc");
+        assert_eq!(hash, content_hash("abc"));
+        assert_eq!(hash, "e71fa2190541574b");
+    }
+}
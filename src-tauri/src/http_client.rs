@@ -0,0 +1,13 @@
+use reqwest::Client;
+use std::time::Duration;
+
+/// HTTP client with the given connect and whole-request timeouts, shared by the API clients.
+/// Falls back to a default client if the builder fails, as reqwest only fails there when
+/// the TLS backend can't be initialised.
+pub fn build_client(connect_secs: u64, request_secs: u64) -> Client {
+    Client::builder()
+        .timeout(Duration::from_secs(request_secs))
+        .connect_timeout(Duration::from_secs(connect_secs))
+        .build()
+        .unwrap_or_else(|_| Client::new())
+}
@@ -1,15 +1,35 @@
 mod ai_clients;
+mod breaker;
 mod claude;
 mod commands;
+mod config_file;
+mod content_cache;
+mod distill;
+mod http_client;
 mod mcp;
+mod notion_api;
+mod operations;
+mod preferences;
+mod rate_limit;
+mod redact;
+mod seen_content;
+mod sessions;
+mod templates;
+mod text_util;
+mod ttl_cache;
+mod vault;
 mod web;
 
 use ai_clients::AiClients;
 use claude::ClaudeClient;
 use commands::*;
+use distill::DistillCache;
 use mcp::McpClient;
+use operations::OperationLog;
+use sessions::SessionStore;
 use web::WebClient;
 use tauri::menu::{Menu, Submenu, AboutMetadata, PredefinedMenuItem};
+use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -73,12 +93,21 @@ pub fn run() {
             let menu = Menu::with_items(app, &[&app_menu, &edit_menu, &window_menu])?;
             app.set_menu(menu)?;
 
+            // Stops the MCP server after the configured idle time; a no-op while that is 0
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                handle.state::<McpClient>().watch_idle().await;
+            });
+
             Ok(())
         })
         .manage(ClaudeClient::new())
         .manage(McpClient::new())
         .manage(WebClient::new())
         .manage(AiClients::new())
+        .manage(SessionStore::new())
+        .manage(DistillCache::new())
+        .manage(OperationLog::new())
         .invoke_handler(tauri::generate_handler![
             set_api_key,
             load_api_key,
@@ -87,15 +116,47 @@ pub fn run() {
             set_notion_token,
             load_notion_token,
             get_notion_token,
+            verify_notion_token,
             set_database_id,
+            load_notion_hosts,
+            set_notion_hosts,
             load_database_id,
+            list_save_targets,
+            add_save_target,
+            remove_save_target,
             connect_mcp,
             disconnect_mcp,
             is_mcp_connected,
+            get_mcp_idle_timeout,
+            set_mcp_idle_timeout,
             fetch_notion_page,
+            inspect_notion_page,
             fetch_web_page,
+            get_fetch_settings,
+            set_fetch_settings,
+            get_guard_fetched_content,
+            set_guard_fetched_content,
+            get_strip_injection_lines,
+            set_strip_injection_lines,
+            get_content_selectors,
+            set_content_selectors,
+            fetch_content,
+            content_quality_check,
+            list_cache_entries,
+            evict_cache_entry,
+            clear_content_cache,
+            fetch_and_merge,
+            ask_across_sources,
+            list_recent_operations,
+            rerun_operation,
             translate,
+            translate_resumable,
+            translate_and_save,
+            translate_notion_page,
+            translate_if_changed,
             summarize,
+            summarize_and_save,
+            summarize_if_changed,
             ask_question,
             ask_question_openai,
             ask_question_gemini,
@@ -103,23 +164,110 @@ pub fn run() {
             load_openai_key,
             get_openai_key,
             clear_openai_key,
+            list_openai_models,
+            list_claude_models,
+            ask_about_image,
             set_gemini_key,
             load_gemini_key,
             get_gemini_key,
             clear_gemini_key,
+            list_gemini_models,
             save_to_notion,
+            verify_saved_page,
+            export_conversation,
+            save_conversation_to_notion,
+            save_answer_to_notion,
             search_databases,
+            search_pages,
             load_recent_databases,
             add_recent_database,
+            load_preferences,
+            save_preferences,
             list_mcp_tools,
+            get_notion_backend,
+            set_notion_backend,
+            fetch_notion_blocks_raw,
             export_notes_to_file,
             translate_content,
+            translate_multi,
+            translate_with_context,
+            refine_translation,
+            translate_content_resumable,
+            plan_translation,
+            get_translation_chunk_size,
+            set_translation_chunk_size,
+            get_translation_concurrency,
+            set_translation_concurrency,
+            get_prompt_caching,
+            set_prompt_caching,
+            get_claude_beta_features,
+            set_claude_beta_features,
+            get_max_output_tokens,
+            set_max_output_tokens,
+            get_rate_limits,
+            set_rate_limit,
+            get_thinking,
+            set_thinking,
+            get_deterministic_mode,
+            set_deterministic_mode,
+            get_ai_timeouts,
+            set_ai_timeouts,
             summarize_content,
+            summarize_constrained,
             ask_claude_content,
+            get_provider_fallback,
+            set_provider_fallback,
+            ask_content_with_fallback,
+            summarize_content_with_fallback,
+            benchmark_providers,
             ask_openai_content,
             ask_gemini_content,
+            ask_openai_stream,
+            ask_gemini_stream,
+            ask_openai_with_tools,
             ask_with_history,
-            extract_text_from_file
+            ask_with_history_stream,
+            branch_conversation,
+            extract_text_from_file,
+            translate_pdf_to_notion,
+            list_templates,
+            save_template,
+            delete_template,
+            run_template,
+            start_session,
+            continue_session,
+            end_session,
+            normalize_text,
+            to_markdown,
+            extract_references,
+            extract_tables,
+            outline,
+            translate_outline,
+            align_translation,
+            split_into_sections,
+            save_large_document,
+            summarize_with_citations,
+            compare_documents,
+            extract_action_items,
+            save_action_items_to_notion,
+            summarize_database,
+            distill_content,
+            get_distill_model,
+            set_distill_model,
+            generate_flashcards,
+            save_flashcards_to_notion,
+            get_app_info,
+            get_status,
+            config_path,
+            export_settings,
+            import_settings,
+            set_master_passphrase,
+            unlock,
+            set_debug_capture,
+            debug_last_response,
+            regenerate,
+            fetch_feed,
+            summarize_feed
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,16 +1,37 @@
+use crate::config_file::{config_file_path, read_json, stage_json, StagedFile};
+use crate::notion_api::NotionApi;
+use crate::redact::redact_secrets;
+use crate::text_util::truncate_chars;
+use crate::vault::{self, NewPassphrase};
 use serde::{Deserialize, Serialize};
+use futures::future::BoxFuture;
 use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
 use std::process::Stdio;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::{Child, Command};
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Mutex};
 use tokio::time::timeout;
 
 const MCP_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 const MCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const MCP_CONNECTION_CLOSED: &str = "MCP connection closed";
+const MCP_SERVER_EXITED: &str = "MCP server closed the connection. Please reconnect.";
+/// How often the idle watcher looks at the last request time
+const MCP_IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const MCP_IDLE_TIMEOUT_RANGE: std::ops::RangeInclusive<u64> = 60..=24 * 60 * 60;
+const NOTION_RATE_LIMIT_RETRIES: u32 = 3;
+const NOTION_MAX_RETRY_WAIT: Duration = Duration::from_secs(30);
+/// Most blocks Notion accepts in one create-a-page or append request
+const NOTION_BLOCK_BATCH: usize = 100;
+/// Rows listed under an inline database heading in fetched page content
+const CHILD_DATABASE_PREVIEW_ROWS: usize = 5;
+/// Nesting depth that `fetch_blocks_raw` follows before leaving children out
+const MAX_RAW_BLOCK_DEPTH: usize = 8;
 
 #[derive(Serialize)]
 struct JsonRpcRequest {
@@ -43,18 +64,52 @@ pub struct McpClient {
     stdin: Arc<Mutex<Option<tokio::process::ChildStdin>>>,
     stdout: Arc<Mutex<Option<BufReader<tokio::process::ChildStdout>>>>,
     request_id: AtomicU64,
+    /// Set by `disconnect` so in-flight requests stop waiting on the pipes
+    closed: watch::Sender<bool>,
     notion_token: Arc<tokio::sync::RwLock<Option<String>>>,
     database_id: Arc<tokio::sync::RwLock<Option<String>>>,
+    backend: Arc<tokio::sync::RwLock<NotionBackend>>,
+    notion_api: NotionApi,
+    /// Last successful `connect`, for reconnecting after an idle disconnect
+    last_connection: Arc<Mutex<Option<ServerCommand>>>,
+    /// Unix seconds of the last MCP request's start or end
+    last_request: AtomicU64,
+    /// Seconds without requests before the server is stopped; 0 keeps it running
+    idle_timeout_secs: AtomicU64,
+    /// Set when the idle watcher stopped the server, so the next request starts it again
+    idle_disconnected: AtomicBool,
+    /// Held by `wake` and by the idle watcher from its idle check through shutdown, so a
+    /// request never meets a half-stopped server and waits for a reconnect instead
+    reconnecting: Mutex<()>,
+}
+
+/// Command and arguments that start the MCP server
+type ServerCommand = (String, Vec<String>);
+
+/// How Notion is reached: through the MCP subprocess or directly over the REST API
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotionBackend {
+    #[default]
+    Mcp,
+    Api,
 }
 
 const CONFIG_FILE_NAME: &str = "ainotework_mcp_config.json";
+const MAX_RECENT_DATABASES: usize = 10;
+/// Save target that `set_database_id` keeps in sync with the current database
+const DEFAULT_SAVE_TARGET: &str = "default";
 
 #[derive(serde::Serialize, serde::Deserialize, Default, Clone)]
-struct Config {
+pub(crate) struct Config {
     api_key: Option<String>,
     notion_token: Option<String>,
     database_id: Option<String>,
     recent_databases: Option<Vec<RecentDatabase>>,
+    save_targets: Option<Vec<SaveTarget>>,
+    notion_backend: Option<NotionBackend>,
+    notion_hosts: Option<Vec<String>>,
+    mcp_idle_timeout_secs: Option<u64>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
@@ -63,6 +118,82 @@ pub struct RecentDatabase {
     pub name: String,
 }
 
+/// Named database that notes can be saved to
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct SaveTarget {
+    pub name: String,
+    pub database_id: String,
+    /// Name of the database's title property when it isn't "title"
+    #[serde(default)]
+    pub title_property: Option<String>,
+}
+
+/// Top-level block whose text didn't make it into the fetched page content
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct DroppedBlock {
+    pub id: String,
+    pub block_type: String,
+    /// "empty" for text blocks with no text, "unsupported" for block types without rich text
+    pub reason: String,
+    pub has_children: bool,
+}
+
+/// What a page is made of, for finding out why fetched content looks incomplete
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct PageInspection {
+    pub title: Option<String>,
+    pub block_count: usize,
+    pub block_types: BTreeMap<String, usize>,
+    pub dropped: Vec<DroppedBlock>,
+    /// Blocks with nested children; only the top level is fetched
+    pub blocks_with_children: usize,
+    /// More than one page of results; only the first page is fetched
+    pub has_more: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct NotionTokenStatus {
+    pub valid: bool,
+    pub bot_name: Option<String>,
+    pub workspace_name: Option<String>,
+    pub message: Option<String>,
+}
+
+/// Metadata read from a leading frontmatter block
+#[derive(Default, Debug)]
+struct Frontmatter {
+    title: Option<String>,
+    tags: Vec<String>,
+}
+
+/// Which kind of Notion object a search should return
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchObjectType {
+    #[default]
+    Database,
+    Page,
+    Any,
+}
+
+impl SearchObjectType {
+    fn filter_value(self) -> Option<&'static str> {
+        match self {
+            SearchObjectType::Database => Some("database"),
+            SearchObjectType::Page => Some("page"),
+            SearchObjectType::Any => None,
+        }
+    }
+
+    /// Results without an object type are kept, since some MCP servers omit it
+    fn matches(self, object: &str) -> bool {
+        match self.filter_value() {
+            Some(value) => object == value || object.is_empty(),
+            None => true,
+        }
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct DatabaseInfo {
     pub id: String,
@@ -76,38 +207,127 @@ impl McpClient {
             stdin: Arc::new(Mutex::new(None)),
             stdout: Arc::new(Mutex::new(None)),
             request_id: AtomicU64::new(1),
+            closed: watch::channel(false).0,
             notion_token: Arc::new(tokio::sync::RwLock::new(None)),
             database_id: Arc::new(tokio::sync::RwLock::new(None)),
+            backend: Arc::new(tokio::sync::RwLock::new(
+                Self::load_config().notion_backend.unwrap_or_default(),
+            )),
+            notion_api: NotionApi::new(),
+            last_connection: Arc::new(Mutex::new(None)),
+            last_request: AtomicU64::new(0),
+            idle_timeout_secs: AtomicU64::new(Self::load_config().mcp_idle_timeout_secs.unwrap_or(0)),
+            idle_disconnected: AtomicBool::new(false),
+            reconnecting: Mutex::new(()),
         }
     }
 
-    fn get_config_path() -> Option<std::path::PathBuf> {
-        dirs::config_dir().map(|p| p.join(CONFIG_FILE_NAME))
+    pub(crate) fn get_config_path() -> Option<PathBuf> {
+        config_file_path(CONFIG_FILE_NAME)
+    }
+
+    fn try_load_config() -> Result<Config, String> {
+        let path = Self::get_config_path().ok_or("Could not determine config directory")?;
+        read_json(&path)
     }
 
+    /// Defaults when the file can't be read; updates go through `try_load_config` so a bad file is never overwritten
     fn load_config() -> Config {
-        if let Some(path) = Self::get_config_path() {
-            if let Ok(content) = std::fs::read_to_string(&path) {
-                if let Ok(config) = serde_json::from_str(&content) {
-                    return config;
+        Self::try_load_config().unwrap_or_default()
+    }
+
+    /// Secrets are encrypted on the way out when a master passphrase is set
+    pub(crate) fn save_config(config: &Config) -> Result<(), String> {
+        Self::stage_config(config)?.commit()
+    }
+
+    /// `save_config` without moving the file into place, for writes that go together
+    fn stage_config(config: &Config) -> Result<StagedFile, String> {
+        let path = Self::get_config_path().ok_or("Could not determine config directory")?;
+        let mut config = config.clone();
+        config.api_key = vault::seal(config.api_key)?;
+        config.notion_token = vault::seal(config.notion_token)?;
+        stage_json(&path, &config)
+    }
+
+    /// `save_config` for a passphrase change: secrets are sealed under the new key and the file
+    /// is only staged, to be committed together with the vault and the other configs
+    pub(crate) fn stage_config_resealed(
+        config: &Config,
+        passphrase: &NewPassphrase,
+    ) -> Result<StagedFile, String> {
+        let path = Self::get_config_path().ok_or("Could not determine config directory")?;
+        let mut config = config.clone();
+        config.api_key = passphrase.seal(config.api_key)?;
+        config.notion_token = passphrase.seal(config.notion_token)?;
+        stage_json(&path, &config)
+    }
+
+    /// Saved config with secrets decrypted, for re-encrypting under a new passphrase
+    pub(crate) fn load_config_revealed() -> Result<Config, String> {
+        let mut config = Self::try_load_config()?;
+        config.api_key = vault::reveal(config.api_key)?;
+        config.notion_token = vault::reveal(config.notion_token)?;
+        Ok(config)
+    }
+
+    /// Saved settings for export; the token and API key are left out unless `include_secrets`
+    pub(crate) fn export_settings(include_secrets: bool) -> Result<Config, String> {
+        let mut config = Self::try_load_config()?;
+        if !include_secrets {
+            config.api_key = None;
+            config.notion_token = None;
+        }
+        Ok(config)
+    }
+
+    /// Exported settings merged into the saved ones, staged for `commit_all`; recent databases
+    /// and hosts are combined. See `vault::merge_imported` for `replacing_vault`.
+    pub(crate) fn stage_import(incoming: Config, replacing_vault: bool) -> Result<StagedFile, String> {
+        let mut config = Self::try_load_config()?;
+        config.api_key = vault::merge_imported(incoming.api_key, config.api_key, replacing_vault)?;
+        config.notion_token = vault::merge_imported(incoming.notion_token, config.notion_token, replacing_vault)?;
+        config.database_id = incoming.database_id.or(config.database_id);
+        config.notion_backend = incoming.notion_backend.or(config.notion_backend);
+        config.mcp_idle_timeout_secs = incoming.mcp_idle_timeout_secs.or(config.mcp_idle_timeout_secs);
+
+        if let Some(imported) = incoming.recent_databases {
+            let mut recent = config.recent_databases.take().unwrap_or_default();
+            for db in imported {
+                if !recent.iter().any(|r| r.id == db.id) {
+                    recent.push(db);
                 }
             }
+            recent.truncate(MAX_RECENT_DATABASES);
+            config.recent_databases = Some(recent);
         }
-        Config::default()
-    }
 
-    fn save_config(config: &Config) -> Result<(), String> {
-        let path = Self::get_config_path().ok_or("Could not determine config directory")?;
-        let content = serde_json::to_string_pretty(config)
-            .map_err(|e| format!("Failed to serialize config: {}", e))?;
-        std::fs::write(&path, content)
-            .map_err(|e| format!("Failed to write config file: {}", e))?;
-        Ok(())
+        if let Some(imported) = incoming.save_targets {
+            let mut targets = config.save_targets.take().unwrap_or_default();
+            for target in imported {
+                let target = Self::clean_save_target(target)?;
+                targets.retain(|t| t.name != target.name);
+                targets.push(target);
+            }
+            config.save_targets = Some(targets);
+        }
+
+        if let Some(imported) = incoming.notion_hosts {
+            let mut hosts = config.notion_hosts.take().unwrap_or_default();
+            for host in imported {
+                if !hosts.contains(&host) {
+                    hosts.push(host);
+                }
+            }
+            config.notion_hosts = Some(hosts);
+        }
+
+        Self::stage_config(&config)
     }
 
     pub async fn load_notion_token(&self) -> Option<String> {
         let config = Self::load_config();
-        if let Some(token) = config.notion_token.clone() {
+        if let Some(token) = vault::reveal(config.notion_token).ok().flatten() {
             let mut notion_token = self.notion_token.write().await;
             *notion_token = Some(token.clone());
             return Some(token);
@@ -121,7 +341,7 @@ impl McpClient {
             *notion_token = Some(token.clone());
         }
 
-        let mut config = Self::load_config();
+        let mut config = Self::try_load_config()?;
         config.notion_token = Some(token);
         Self::save_config(&config)?;
         Ok(())
@@ -132,6 +352,43 @@ impl McpClient {
         notion_token.clone()
     }
 
+    /// Check a token (or the stored one) against Notion's `/users/me` endpoint.
+    /// Always uses the REST API since it only needs the token, not an MCP connection.
+    pub async fn verify_notion_token(&self, token: Option<String>) -> Result<NotionTokenStatus, String> {
+        let token = match token.filter(|t| !t.trim().is_empty()) {
+            Some(t) => t.trim().to_string(),
+            None => self.get_notion_token().await.ok_or("Notion token not set")?,
+        };
+
+        let user = self.notion_api.get_bot_user(&token).await?;
+
+        if let Some(status) = user.get("status").and_then(|s| s.as_i64()) {
+            if status != 200 {
+                let message = user
+                    .get("message")
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("Unknown error");
+                return Ok(NotionTokenStatus {
+                    valid: false,
+                    bot_name: None,
+                    workspace_name: None,
+                    message: Some(redact_secrets(&format!("Notion API error ({}): {}", status, message))),
+                });
+            }
+        }
+
+        Ok(NotionTokenStatus {
+            valid: true,
+            bot_name: user.get("name").and_then(|n| n.as_str()).map(|n| n.to_string()),
+            workspace_name: user
+                .get("bot")
+                .and_then(|b| b.get("workspace_name"))
+                .and_then(|w| w.as_str())
+                .map(|w| w.to_string()),
+            message: None,
+        })
+    }
+
     pub async fn load_database_id(&self) -> Option<String> {
         let config = Self::load_config();
         if let Some(db_id) = config.database_id.clone() {
@@ -148,15 +405,120 @@ impl McpClient {
             *database_id = Some(db_id.clone());
         }
 
-        let mut config = Self::load_config();
+        let mut config = Self::try_load_config()?;
+        let mut targets = config.save_targets.take().unwrap_or_default();
+        match targets.iter_mut().find(|t| t.name == DEFAULT_SAVE_TARGET) {
+            Some(target) => target.database_id = db_id.clone(),
+            None => targets.insert(
+                0,
+                SaveTarget {
+                    name: DEFAULT_SAVE_TARGET.to_string(),
+                    database_id: db_id.clone(),
+                    title_property: None,
+                },
+            ),
+        }
+        config.save_targets = Some(targets);
         config.database_id = Some(db_id);
         Self::save_config(&config)?;
         Ok(())
     }
 
-    pub async fn get_database_id(&self) -> Option<String> {
-        let database_id = self.database_id.read().await;
-        database_id.clone()
+    pub async fn get_backend(&self) -> NotionBackend {
+        *self.backend.read().await
+    }
+
+    pub async fn set_backend(&self, backend: NotionBackend) -> Result<(), String> {
+        *self.backend.write().await = backend;
+
+        let mut config = Self::try_load_config()?;
+        config.notion_backend = Some(backend);
+        Self::save_config(&config)
+    }
+
+    /// Idle time in seconds after which the MCP server is stopped; 0 when it always stays up
+    pub fn get_idle_timeout(&self) -> u64 {
+        self.idle_timeout_secs.load(Ordering::SeqCst)
+    }
+
+    pub fn set_idle_timeout(&self, secs: u64) -> Result<(), String> {
+        if secs != 0 && !MCP_IDLE_TIMEOUT_RANGE.contains(&secs) {
+            return Err(format!(
+                "Idle timeout must be 0 (disabled) or between {} and {} seconds",
+                MCP_IDLE_TIMEOUT_RANGE.start(),
+                MCP_IDLE_TIMEOUT_RANGE.end()
+            ));
+        }
+        self.idle_timeout_secs.store(secs, Ordering::SeqCst);
+
+        let mut config = Self::try_load_config()?;
+        config.mcp_idle_timeout_secs = Some(secs);
+        Self::save_config(&config)
+    }
+
+    fn now_secs() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+
+    /// Stop the server once no request has been made for the idle timeout; runs for the app's lifetime
+    pub async fn watch_idle(&self) {
+        let mut interval = tokio::time::interval(MCP_IDLE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let idle_timeout = self.get_idle_timeout();
+            if idle_timeout == 0 || !self.is_connected().await {
+                continue;
+            }
+            let _waking = self.reconnecting.lock().await;
+            let idle_for = Self::now_secs().saturating_sub(self.last_request.load(Ordering::SeqCst));
+            if idle_for >= idle_timeout && self.shutdown().await.is_ok() {
+                self.idle_disconnected.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Restart the server if the idle watcher stopped it, then note the request time
+    async fn wake(&self) -> Result<(), String> {
+        let _waking = self.reconnecting.lock().await;
+        if self.idle_disconnected.load(Ordering::SeqCst) {
+            let connection = self.last_connection.lock().await.clone();
+            if let Some((command, args)) = connection {
+                let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+                self.connect(&command, &args)
+                    .await
+                    .map_err(|e| format!("Failed to reconnect to the MCP server: {}", e))?;
+            }
+        }
+        self.last_request.store(Self::now_secs(), Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Custom domains (e.g. `notion.mycompany.com`) that serve Notion pages
+    pub fn load_notion_hosts() -> Vec<String> {
+        Self::load_config().notion_hosts.unwrap_or_default()
+    }
+
+    pub fn set_notion_hosts(hosts: Vec<String>) -> Result<(), String> {
+        let mut seen = HashSet::new();
+        let hosts: Vec<String> = hosts
+            .into_iter()
+            .map(|h| {
+                // Accept full URLs as well as bare hostnames
+                let h = h.trim().to_lowercase();
+                let h = h
+                    .trim_start_matches("https://")
+                    .trim_start_matches("http://");
+                h.split('/').next().unwrap_or_default().to_string()
+            })
+            .filter(|h| !h.is_empty() && seen.insert(h.clone()))
+            .collect();
+
+        let mut config = Self::try_load_config()?;
+        config.notion_hosts = Some(hosts);
+        Self::save_config(&config)
     }
 
     pub fn load_recent_databases() -> Vec<RecentDatabase> {
@@ -165,7 +527,7 @@ impl McpClient {
     }
 
     pub fn add_recent_database(db: RecentDatabase) -> Result<(), String> {
-        let mut config = Self::load_config();
+        let mut config = Self::try_load_config()?;
         let mut recent = config.recent_databases.unwrap_or_default();
 
         // Remove if already exists
@@ -175,13 +537,86 @@ impl McpClient {
         recent.insert(0, db);
 
         // Keep only last 10
-        recent.truncate(10);
+        recent.truncate(MAX_RECENT_DATABASES);
 
         config.recent_databases = Some(recent);
         Self::save_config(&config)
     }
 
+    /// Configured save targets; a database id saved before targets existed shows up as the default
+    pub fn load_save_targets() -> Vec<SaveTarget> {
+        let config = Self::load_config();
+        let mut targets = config.save_targets.unwrap_or_default();
+        if let Some(db_id) = config.database_id {
+            if !targets.iter().any(|t| t.name == DEFAULT_SAVE_TARGET) {
+                targets.insert(
+                    0,
+                    SaveTarget {
+                        name: DEFAULT_SAVE_TARGET.to_string(),
+                        database_id: db_id,
+                        title_property: None,
+                    },
+                );
+            }
+        }
+        targets
+    }
+
+    /// Add a target, replacing any existing one with the same name
+    pub fn add_save_target(target: SaveTarget) -> Result<(), String> {
+        let target = Self::clean_save_target(target)?;
+
+        let mut config = Self::try_load_config()?;
+        let mut targets = config.save_targets.take().unwrap_or_default();
+        targets.retain(|t| t.name != target.name);
+        targets.push(target);
+        config.save_targets = Some(targets);
+        Self::save_config(&config)
+    }
+
+    /// Trimmed target; the name and database ID must not be empty
+    fn clean_save_target(target: SaveTarget) -> Result<SaveTarget, String> {
+        let name = target.name.trim().to_string();
+        if name.is_empty() {
+            return Err("Save target name must not be empty".to_string());
+        }
+        if target.database_id.trim().is_empty() {
+            return Err("Save target database ID must not be empty".to_string());
+        }
+        let title_property = target
+            .title_property
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty());
+
+        Ok(SaveTarget {
+            name,
+            database_id: target.database_id.trim().to_string(),
+            title_property,
+        })
+    }
+
+    pub fn remove_save_target(name: &str) -> Result<(), String> {
+        let mut config = Self::try_load_config()?;
+        let mut targets = config.save_targets.take().unwrap_or_default();
+        targets.retain(|t| t.name != name);
+        config.save_targets = Some(targets);
+        Self::save_config(&config)
+    }
+
+    /// The save target called `target`, or a target for `target` as a database ID or URL
+    fn resolve_save_target(target: &str) -> SaveTarget {
+        Self::load_save_targets()
+            .into_iter()
+            .find(|t| t.name == target.trim())
+            .unwrap_or_else(|| SaveTarget {
+                name: String::new(),
+                database_id: target.to_string(),
+                title_property: None,
+            })
+    }
+
     pub async fn list_tools(&self) -> Result<Vec<String>, String> {
+        self.wake().await?;
         let request = JsonRpcRequest {
             jsonrpc: "2.0".to_string(),
             id: self.next_id(),
@@ -203,8 +638,30 @@ impl McpClient {
         Ok(tool_names)
     }
 
-    pub async fn search_databases(&self, query: &str) -> Result<Vec<DatabaseInfo>, String> {
-        let tools = self.list_tools().await?;
+    pub async fn search_databases(
+        &self,
+        query: &str,
+        object_type: SearchObjectType,
+    ) -> Result<Vec<DatabaseInfo>, String> {
+        self.search_objects(query, object_type).await
+    }
+
+    pub async fn search_pages(&self, query: &str) -> Result<Vec<DatabaseInfo>, String> {
+        self.search_objects(query, SearchObjectType::Page).await
+    }
+
+    async fn search_objects(
+        &self,
+        query: &str,
+        object_type: SearchObjectType,
+    ) -> Result<Vec<DatabaseInfo>, String> {
+        // Upper bound on result pages followed via next_cursor
+        const MAX_SEARCH_PAGES: usize = 10;
+
+        let tools = match self.get_backend().await {
+            NotionBackend::Mcp => self.list_tools().await?,
+            NotionBackend::Api => vec!["API-post-search".to_string()],
+        };
 
         let search_tool_names = [
             "API-post-search",
@@ -229,58 +686,124 @@ impl McpClient {
             }
         };
 
-        let result = self
-            .call_tool(
-                tool_name,
-                json!({
-                    "query": query,
-                    "filter": {
-                        "property": "object",
-                        "value": "database"
-                    }
-                }),
-            )
-            .await;
-
-        let result = match result {
-            Ok(r) => r,
-            Err(_) => {
-                let r2 = self
-                    .call_tool(
-                        tool_name,
-                        json!({
-                            "query": query,
-                            "filter": "database"
-                        }),
-                    )
-                    .await;
-                match r2 {
-                    Ok(r) => r,
-                    Err(_) => {
-                        self.call_tool(tool_name, json!({ "query": query })).await?
-                    }
+        // Search tools disagree on the filter shape, so try the Notion API form first,
+        // then a bare string, then no filter at all (relying on the post-filter below)
+        let mut candidate_args = Vec::new();
+        if let Some(value) = object_type.filter_value() {
+            candidate_args.push(json!({
+                "query": query,
+                "filter": {
+                    "property": "object",
+                    "value": value
                 }
+            }));
+            candidate_args.push(json!({
+                "query": query,
+                "filter": value
+            }));
+        }
+        candidate_args.push(json!({ "query": query }));
+
+        let mut args = Value::Null;
+        let mut result = Err("No search attempted".to_string());
+        for candidate in candidate_args {
+            result = self
+                .notion_call(tool_name, candidate.clone())
+                .await
+                .and_then(Self::checked);
+            if result.is_ok() {
+                args = candidate;
+                break;
             }
-        };
+        }
+        let mut result = result?;
 
-        let mut databases = Vec::new();
+        let mut objects = Vec::new();
 
-        if let Some(results) = result.get("results").and_then(|r| r.as_array()) {
-            for item in results {
-                let object_type = item.get("object").and_then(|o| o.as_str()).unwrap_or("");
-                if object_type == "database" || query.is_empty() || object_type.is_empty() {
+        for page in 0..MAX_SEARCH_PAGES {
+            if let Some(results) = result.get("results").and_then(|r| r.as_array()) {
+                for item in results {
+                    let item_type = item.get("object").and_then(|o| o.as_str()).unwrap_or("");
+                    if !object_type.matches(item_type) {
+                        continue;
+                    }
                     if let Some(id) = item.get("id").and_then(|i| i.as_str()) {
-                        let name = Self::extract_database_name(item);
-                        databases.push(DatabaseInfo {
+                        let name = if item_type == "page" {
+                            Self::extract_page_title(item).unwrap_or_else(|| "Untitled".to_string())
+                        } else {
+                            Self::extract_database_name(item)
+                        };
+                        objects.push(DatabaseInfo {
                             id: id.to_string(),
                             name,
                         });
                     }
                 }
             }
+
+            let has_more = result.get("has_more").and_then(|h| h.as_bool()).unwrap_or(false);
+            let next_cursor = result.get("next_cursor").and_then(|c| c.as_str());
+            let next_cursor = match next_cursor {
+                Some(cursor) if has_more && page + 1 < MAX_SEARCH_PAGES => cursor.to_string(),
+                _ => break,
+            };
+
+            let mut next_args = args.clone();
+            if let Some(obj) = next_args.as_object_mut() {
+                obj.insert("start_cursor".to_string(), json!(next_cursor));
+            }
+            result = self.notion_call(tool_name, next_args).await.and_then(Self::checked)?;
+        }
+
+        Ok(objects)
+    }
+
+    /// Rows of a database in its default order, following cursors until `max_pages` are collected
+    pub async fn list_database_pages(&self, database_id: &str, max_pages: usize) -> Result<Vec<DatabaseInfo>, String> {
+        // Notion returns at most 100 rows per query
+        const QUERY_PAGE_SIZE: usize = 100;
+
+        let database_id = Self::extract_page_id(database_id);
+        let mut pages = Vec::new();
+        let mut cursor: Option<String> = None;
+
+        while pages.len() < max_pages {
+            let mut args = json!({
+                "database_id": database_id,
+                "page_size": (max_pages - pages.len()).min(QUERY_PAGE_SIZE)
+            });
+            if let Some(cursor) = &cursor {
+                args["start_cursor"] = json!(cursor);
+            }
+
+            let result = self
+                .notion_call_with_retry("API-post-database-query", args)
+                .await?;
+            Self::check_page_access(&result, &database_id)?;
+
+            if let Some(results) = result.get("results").and_then(|r| r.as_array()) {
+                pages.extend(results.iter().filter_map(|row| {
+                    let id = row.get("id").and_then(|i| i.as_str())?;
+                    Some(DatabaseInfo {
+                        id: id.to_string(),
+                        name: Self::extract_page_title(row).unwrap_or_else(|| "Untitled".to_string()),
+                    })
+                }));
+            }
+
+            let has_more = result.get("has_more").and_then(|h| h.as_bool()).unwrap_or(false);
+            cursor = result
+                .get("next_cursor")
+                .and_then(|c| c.as_str())
+                .filter(|_| has_more)
+                .map(|c| c.to_string());
+            if cursor.is_none() {
+                break;
+            }
         }
 
-        Ok(databases)
+        pages.truncate(max_pages);
+        Ok(pages)
     }
 
     fn extract_database_name(db: &Value) -> String {
@@ -297,20 +820,30 @@ impl McpClient {
         "Untitled".to_string()
     }
 
-    pub async fn connect(&self, command: &str, args: &[&str]) -> Result<(), String> {
-        let npx_paths = [
-            command.to_string(),
-            "/usr/local/bin/npx".to_string(),
-            "/opt/homebrew/bin/npx".to_string(),
-            format!("{}/bin/npx", std::env::var("HOME").unwrap_or_default()),
-            format!("{}/.nvm/versions/node/*/bin/npx", std::env::var("HOME").unwrap_or_default()),
-        ];
+    /// Location of `npx`: PATH first, then the usual install locations a GUI app's PATH misses
+    pub fn find_npx() -> Option<String> {
+        let home = std::env::var("HOME").unwrap_or_default();
+        let mut candidates: Vec<PathBuf> = std::env::var_os("PATH")
+            .map(|path| std::env::split_paths(&path).map(|dir| dir.join("npx")).collect())
+            .unwrap_or_default();
+        candidates.push("/usr/local/bin/npx".into());
+        candidates.push("/opt/homebrew/bin/npx".into());
+        candidates.push(format!("{}/bin/npx", home).into());
+
+        // nvm keeps one directory per installed node version
+        if let Ok(versions) = std::fs::read_dir(format!("{}/.nvm/versions/node", home)) {
+            candidates.extend(versions.flatten().map(|v| v.path().join("bin/npx")));
+        }
 
+        candidates
+            .into_iter()
+            .find(|p| p.is_file())
+            .map(|p| p.to_string_lossy().to_string())
+    }
+
+    pub async fn connect(&self, command: &str, args: &[&str]) -> Result<(), String> {
         let actual_command = if command == "npx" {
-            npx_paths.iter()
-                .find(|p| std::path::Path::new(p).exists())
-                .cloned()
-                .unwrap_or_else(|| command.to_string())
+            Self::find_npx().unwrap_or_else(|| command.to_string())
         } else {
             command.to_string()
         };
@@ -337,7 +870,7 @@ impl McpClient {
 
         let mut child = cmd
             .spawn()
-            .map_err(|e| format!("Failed to start MCP server: {}", e))?;
+            .map_err(|e| redact_secrets(&format!("Failed to start MCP server: {}", e)))?;
 
         let stdin = child.stdin.take().ok_or("Failed to get stdin")?;
         let stdout = child.stdout.take().ok_or("Failed to get stdout")?;
@@ -345,17 +878,21 @@ impl McpClient {
         *self.process.lock().await = Some(child);
         *self.stdin.lock().await = Some(stdin);
         *self.stdout.lock().await = Some(BufReader::new(stdout));
+        self.closed.send_replace(false);
 
         // Initialize with timeout
         match timeout(MCP_CONNECT_TIMEOUT, self.initialize()).await {
             Ok(result) => result?,
             Err(_) => {
                 // Clean up on timeout
-                self.disconnect().await.ok();
+                self.shutdown().await.ok();
                 return Err("MCP connection timed out (15 seconds). Please check if Notion token is valid.".to_string());
             }
         }
 
+        *self.last_connection.lock().await = Some((command.to_string(), args.iter().map(|a| a.to_string()).collect()));
+        self.last_request.store(Self::now_secs(), Ordering::SeqCst);
+        self.idle_disconnected.store(false, Ordering::SeqCst);
         Ok(())
     }
 
@@ -404,6 +941,22 @@ impl McpClient {
     }
 
     async fn send_request(&self, request: &JsonRpcRequest) -> Result<Value, String> {
+        let mut closed = self.closed.subscribe();
+        if *closed.borrow_and_update() {
+            return Err("MCP client not connected".to_string());
+        }
+
+        // Give up the pipes as soon as disconnect() starts instead of racing it mid-read
+        let result = tokio::select! {
+            result = self.exchange(request) => result,
+            _ = closed.wait_for(|closed| *closed) => Err(MCP_CONNECTION_CLOSED.to_string()),
+        };
+        // A long request counts as activity until it ends, not just when it starts
+        self.last_request.store(Self::now_secs(), Ordering::SeqCst);
+        result
+    }
+
+    async fn exchange(&self, request: &JsonRpcRequest) -> Result<Value, String> {
         let mut stdin = self.stdin.lock().await;
         let stdin = stdin.as_mut().ok_or("MCP client not connected")?;
 
@@ -423,20 +976,33 @@ impl McpClient {
         let read_future = async {
             loop {
                 line.clear();
-                stdout
+                let read = stdout
                     .read_line(&mut line)
                     .await
                     .map_err(|e| format!("Read error: {}", e))?;
 
+                // EOF: the server exited, so no response is coming
+                if read == 0 {
+                    return Err(MCP_SERVER_EXITED.to_string());
+                }
+                // A line without its newline was cut off by EOF: the server died mid-response
+                if !line.ends_with('\n') {
+                    return Err(format!(
+                        "{} The response was cut off after {} bytes.",
+                        MCP_SERVER_EXITED,
+                        line.len()
+                    ));
+                }
+
                 if line.trim().is_empty() {
                     continue;
                 }
 
                 let response: JsonRpcResponse =
-                    serde_json::from_str(&line).map_err(|e| format!("Parse error: {} - Line: {}", e, line))?;
+                    serde_json::from_str(&line).map_err(|e| redact_secrets(&format!("Parse error: {} - Line: {}", e, truncate_chars(&line, 500))))?;
 
                 if let Some(error) = response.error {
-                    return Err(format!("MCP error: {}", error.message));
+                    return Err(redact_secrets(&format!("MCP error: {}", error.message)));
                 }
 
                 return response.result.ok_or_else(|| "Empty result".to_string());
@@ -449,6 +1015,7 @@ impl McpClient {
     }
 
     pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<Value, String> {
+        self.wake().await?;
         let request = JsonRpcRequest {
             jsonrpc: "2.0".to_string(),
             id: self.next_id(),
@@ -473,13 +1040,109 @@ impl McpClient {
         Ok(result)
     }
 
+    /// Run a Notion operation (named after the MCP tool) on the configured backend.
+    /// Both backends return Notion's error bodies (HTTP 4xx/5xx) as `Ok`, so every caller
+    /// must pass the result through `check_status` or `check_page_access` before using it.
+    async fn notion_call(&self, tool: &str, arguments: Value) -> Result<Value, String> {
+        if self.get_backend().await == NotionBackend::Mcp {
+            return self.call_tool(tool, arguments).await;
+        }
+
+        let token = self
+            .notion_token
+            .read()
+            .await
+            .clone()
+            .or_else(|| std::env::var("NOTION_TOKEN").ok())
+            .ok_or("Notion token not set")?;
+
+        let str_arg = |name: &str| -> Result<String, String> {
+            arguments
+                .get(name)
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string())
+                .ok_or_else(|| format!("Missing argument '{}' for {}", name, tool))
+        };
+
+        match tool {
+            "API-retrieve-a-page" => self.notion_api.retrieve_page(&token, &str_arg("page_id")?).await,
+            "API-get-block-children" => {
+                let start_cursor = arguments.get("start_cursor").and_then(|c| c.as_str());
+                self.notion_api
+                    .get_block_children(&token, &str_arg("block_id")?, start_cursor)
+                    .await
+            }
+            "API-retrieve-a-database" => {
+                self.notion_api
+                    .retrieve_database(&token, &str_arg("database_id")?)
+                    .await
+            }
+            "API-post-database-query" => {
+                let database_id = str_arg("database_id")?;
+                let mut body = arguments.clone();
+                if let Some(obj) = body.as_object_mut() {
+                    obj.remove("database_id");
+                }
+                self.notion_api.query_database(&token, &database_id, body).await
+            }
+            "API-create-a-page" => self.notion_api.create_page(&token, arguments).await,
+            "API-patch-block-children" => {
+                let children = arguments.get("children").cloned().unwrap_or_else(|| json!([]));
+                self.notion_api
+                    .append_block_children(&token, &str_arg("block_id")?, children)
+                    .await
+            }
+            "API-post-search" => self.notion_api.search(&token, arguments).await,
+            _ => Err(format!("Operation not supported by the direct Notion API backend: {}", tool)),
+        }
+    }
+
+    /// Seconds Notion asked us to wait, from whichever field the backend exposes it in
+    fn retry_after_secs(result: &Value) -> Option<u64> {
+        let from_value = |v: &Value| {
+            v.as_u64()
+                .or_else(|| v.as_str().and_then(|s| s.trim().parse::<u64>().ok()))
+        };
+
+        result
+            .get("retry_after")
+            .and_then(from_value)
+            .or_else(|| result.get("retry-after").and_then(from_value))
+            .or_else(|| {
+                result
+                    .get("headers")
+                    .and_then(|h| h.get("retry-after"))
+                    .and_then(from_value)
+            })
+    }
+
+    /// Like `notion_call`, but backs off and retries when Notion answers with HTTP 429
+    async fn notion_call_with_retry(&self, tool: &str, arguments: Value) -> Result<Value, String> {
+        let mut attempt = 0;
+        loop {
+            let result = self.notion_call(tool, arguments.clone()).await?;
+
+            let rate_limited = result.get("status").and_then(|s| s.as_i64()) == Some(429);
+            if !rate_limited || attempt >= NOTION_RATE_LIMIT_RETRIES {
+                return Ok(result);
+            }
+
+            // Honour retry-after when given, otherwise exponential backoff (1s, 2s, 4s)
+            let wait = Self::retry_after_secs(&result)
+                .map(Duration::from_secs)
+                .unwrap_or_else(|| Duration::from_secs(1 << attempt))
+                .min(NOTION_MAX_RETRY_WAIT);
+            tokio::time::sleep(wait).await;
+            attempt += 1;
+        }
+    }
+
     fn extract_page_id(input: &str) -> String {
         let input = input.trim();
 
         if let Some(id) = input
             .split(&['/', '?', '#', '-'][..])
-            .filter(|s| s.len() == 32 && s.chars().all(|c| c.is_ascii_hexdigit()))
-            .last()
+            .rfind(|s| s.len() == 32 && s.chars().all(|c| c.is_ascii_hexdigit()))
         {
             return format!(
                 "{}-{}-{}-{}-{}",
@@ -499,49 +1162,49 @@ impl McpClient {
     }
 
     pub async fn fetch_notion_page(&self, page_input: &str) -> Result<String, String> {
+        self.fetch_notion_page_with_title(page_input)
+            .await
+            .map(|(_, content)| content)
+    }
+
+    /// Page content along with the page title, when it has one
+    pub async fn fetch_notion_page_with_title(
+        &self,
+        page_input: &str,
+    ) -> Result<(Option<String>, String), String> {
         let page_id = Self::extract_page_id(page_input);
 
         let page_result = self
-            .call_tool("API-retrieve-a-page", json!({ "page_id": page_id }))
+            .notion_call_with_retry("API-retrieve-a-page", json!({ "page_id": page_id }))
             .await?;
 
-        if let Some(status) = page_result.get("status").and_then(|s| s.as_i64()) {
-            if status != 200 {
-                let message = page_result
-                    .get("message")
-                    .and_then(|m| m.as_str())
-                    .unwrap_or("Unknown error");
-                return Err(format!("Notion API error ({}): {}", status, message));
-            }
-        }
+        Self::check_page_access(&page_result, &page_id)?;
 
         let mut content_parts: Vec<String> = Vec::new();
 
-        if let Some(title) = Self::extract_page_title(&page_result) {
+        let title = Self::extract_page_title(&page_result);
+        if let Some(title) = &title {
             content_parts.push(format!("# {}", title));
             content_parts.push(String::new());
         }
 
         let blocks_result = self
-            .call_tool("API-get-block-children", json!({ "block_id": page_id }))
+            .notion_call_with_retry("API-get-block-children", json!({ "block_id": page_id }))
             .await?;
 
-        if let Some(status) = blocks_result.get("status").and_then(|s| s.as_i64()) {
-            if status != 200 {
-                let message = blocks_result
-                    .get("message")
-                    .and_then(|m| m.as_str())
-                    .unwrap_or("Unknown error");
-                return Err(format!("Notion API error ({}): {}", status, message));
-            }
-        }
+        Self::check_page_access(&blocks_result, &page_id)?;
 
         if let Some(results) = blocks_result.get("results").and_then(|r| r.as_array()) {
-            for block in results {
-                if let Some(text) = Self::extract_block_text(block) {
-                    content_parts.push(text);
+            let mut texts = self.render_blocks(results).await.into_iter().peekable();
+
+            // Pages often repeat their title as the first heading; keep only one copy
+            if let (Some(title), Some(first)) = (&title, texts.peek()) {
+                if Self::same_heading_text(title, first) {
+                    texts.next();
                 }
             }
+
+            content_parts.extend(texts);
         }
 
         if content_parts.is_empty() {
@@ -552,49 +1215,444 @@ impl McpClient {
             ));
         }
 
-        Ok(content_parts.join("\n"))
+        Ok((title, content_parts.join("\n")))
     }
 
-    fn extract_page_title(page: &Value) -> Option<String> {
-        if let Some(properties) = page.get("properties").and_then(|p| p.as_object()) {
-            for (_key, prop) in properties {
-                if prop.get("type").and_then(|t| t.as_str()) == Some("title") {
-                    if let Some(title_arr) = prop.get("title").and_then(|t| t.as_array()) {
-                        let title: String = title_arr
-                            .iter()
-                            .filter_map(|t| t.get("plain_text").and_then(|pt| pt.as_str()))
-                            .collect::<Vec<_>>()
-                            .join("");
-                        if !title.is_empty() {
-                            return Some(title);
-                        }
-                    }
+    /// The page's blocks exactly as Notion returns them, every page of results, with nested
+    /// blocks under a `children` key so ids and types survive for a faithful save-back
+    pub async fn fetch_blocks_raw(&self, page_input: &str) -> Result<Vec<Value>, String> {
+        let page_id = Self::extract_page_id(page_input);
+        self.block_children_raw(page_id, 0).await
+    }
+
+    /// Text of every block on the page, one line per block, following all result pages and
+    /// nested blocks; `fetch_notion_page` reads only the first 100 top-level blocks
+    pub async fn fetch_all_block_text(&self, page_input: &str) -> Result<String, String> {
+        fn collect(blocks: &[Value], lines: &mut Vec<String>) {
+            for block in blocks {
+                if let Some(text) = McpClient::extract_block_text(block) {
+                    lines.push(text);
+                }
+                if let Some(children) = block.get("children").and_then(|c| c.as_array()) {
+                    collect(children, lines);
                 }
             }
         }
-        None
+
+        let blocks = self.fetch_blocks_raw(page_input).await?;
+        let mut lines = Vec::new();
+        collect(&blocks, &mut lines);
+        Ok(lines.join("\n"))
     }
 
-    fn extract_block_text(block: &Value) -> Option<String> {
-        let block_type = block.get("type").and_then(|t| t.as_str())?;
-        let type_content = block.get(block_type)?;
+    /// Boxed because it recurses into child blocks
+    fn block_children_raw(&self, block_id: String, depth: usize) -> BoxFuture<'_, Result<Vec<Value>, String>> {
+        Box::pin(async move {
+            let mut blocks = Vec::new();
+            let mut cursor: Option<String> = None;
 
-        if let Some(rich_text) = type_content.get("rich_text").and_then(|rt| rt.as_array()) {
-            let text: String = rich_text
-                .iter()
-                .filter_map(|t| t.get("plain_text").and_then(|pt| pt.as_str()))
-                .collect::<Vec<_>>()
-                .join("");
+            loop {
+                let mut args = json!({ "block_id": block_id });
+                if let Some(cursor) = &cursor {
+                    args["start_cursor"] = json!(cursor);
+                }
+                let result = self
+                    .notion_call_with_retry("API-get-block-children", args)
+                    .await?;
+                Self::check_page_access(&result, &block_id)?;
 
-            if text.is_empty() {
-                return None;
+                if let Some(results) = result.get("results").and_then(|r| r.as_array()) {
+                    blocks.extend(results.iter().cloned());
+                }
+
+                let has_more = result.get("has_more").and_then(|h| h.as_bool()).unwrap_or(false);
+                cursor = result
+                    .get("next_cursor")
+                    .and_then(|c| c.as_str())
+                    .filter(|_| has_more)
+                    .map(|c| c.to_string());
+                if cursor.is_none() {
+                    break;
+                }
             }
 
-            return Some(match block_type {
-                "heading_1" => format!("# {}", text),
-                "heading_2" => format!("## {}", text),
-                "heading_3" => format!("### {}", text),
-                "bulleted_list_item" => format!("- {}", text),
+            if depth + 1 >= MAX_RAW_BLOCK_DEPTH {
+                return Ok(blocks);
+            }
+            for block in &mut blocks {
+                let has_children = block.get("has_children").and_then(|h| h.as_bool()).unwrap_or(false);
+                // Child pages and databases are separate objects, not part of this page
+                let separate = matches!(
+                    block.get("type").and_then(|t| t.as_str()),
+                    Some("child_page") | Some("child_database")
+                );
+                let id = block.get("id").and_then(|i| i.as_str()).map(|i| i.to_string());
+                if let (true, false, Some(id)) = (has_children, separate, id) {
+                    let children = self.block_children_raw(id, depth + 1).await?;
+                    block["children"] = Value::Array(children);
+                }
+            }
+
+            Ok(blocks)
+        })
+    }
+
+    /// Histogram of the page's block types plus the blocks `extract_block_text` skips
+    pub async fn inspect_notion_page(&self, page_input: &str) -> Result<PageInspection, String> {
+        let page_id = Self::extract_page_id(page_input);
+
+        let page_result = self
+            .notion_call_with_retry("API-retrieve-a-page", json!({ "page_id": page_id }))
+            .await?;
+        Self::check_page_access(&page_result, &page_id)?;
+
+        let blocks_result = self
+            .notion_call_with_retry("API-get-block-children", json!({ "block_id": page_id }))
+            .await?;
+        Self::check_page_access(&blocks_result, &page_id)?;
+
+        let blocks = blocks_result
+            .get("results")
+            .and_then(|r| r.as_array())
+            .cloned()
+            .unwrap_or_default();
+
+        let mut block_types: BTreeMap<String, usize> = BTreeMap::new();
+        let mut dropped = Vec::new();
+        let mut blocks_with_children = 0;
+
+        for block in &blocks {
+            let block_type = block
+                .get("type")
+                .and_then(|t| t.as_str())
+                .unwrap_or("unknown")
+                .to_string();
+            *block_types.entry(block_type.clone()).or_default() += 1;
+
+            let has_children = block
+                .get("has_children")
+                .and_then(|c| c.as_bool())
+                .unwrap_or(false);
+            if has_children {
+                blocks_with_children += 1;
+            }
+
+            // Expanded separately by `render_blocks`
+            let expanded = matches!(block_type.as_str(), "synced_block" | "child_database" | "table");
+            if !expanded && Self::extract_block_text(block).is_none() {
+                let has_rich_text = block
+                    .get(&block_type)
+                    .and_then(|c| c.get("rich_text"))
+                    .is_some();
+                dropped.push(DroppedBlock {
+                    id: block.get("id").and_then(|i| i.as_str()).unwrap_or_default().to_string(),
+                    reason: if has_rich_text { "empty" } else { "unsupported" }.to_string(),
+                    block_type,
+                    has_children,
+                });
+            }
+        }
+
+        Ok(PageInspection {
+            title: Self::extract_page_title(&page_result),
+            block_count: blocks.len(),
+            block_types,
+            dropped,
+            blocks_with_children,
+            has_more: blocks_result
+                .get("has_more")
+                .and_then(|h| h.as_bool())
+                .unwrap_or(false),
+        })
+    }
+
+    /// Every table on the page as (heading above it, rows of cell text), nested blocks included
+    pub async fn fetch_tables(&self, page_input: &str) -> Result<Vec<(Option<String>, Vec<Vec<String>>)>, String> {
+        fn collect(blocks: &[Value], heading: &mut Option<String>, tables: &mut Vec<(Option<String>, Vec<Vec<String>>)>) {
+            for block in blocks {
+                match block.get("type").and_then(|t| t.as_str()) {
+                    // A table's children are its rows, read once here and not walked again
+                    Some("table") => {
+                        let rows = McpClient::table_rows(block);
+                        if !rows.is_empty() {
+                            tables.push((heading.clone(), rows));
+                        }
+                        continue;
+                    }
+                    Some(kind) if kind.starts_with("heading_") => {
+                        *heading = McpClient::extract_block_text(block)
+                            .map(|text| text.trim_start_matches('#').trim().to_string());
+                    }
+                    _ => {}
+                }
+                if let Some(children) = block.get("children").and_then(|c| c.as_array()) {
+                    collect(children, heading, tables);
+                }
+            }
+        }
+
+        let blocks = self.fetch_blocks_raw(page_input).await?;
+        let mut tables = Vec::new();
+        collect(&blocks, &mut None, &mut tables);
+        Ok(tables)
+    }
+
+    /// Cell text of a `table` block's `table_row` children, which must already be under `children`
+    fn table_rows(table: &Value) -> Vec<Vec<String>> {
+        table
+            .get("children")
+            .and_then(|c| c.as_array())
+            .map(|rows| rows.iter().filter_map(Self::table_row_cells).collect())
+            .unwrap_or_default()
+    }
+
+    /// Each cell of a `table_row` block is an array of rich text
+    fn table_row_cells(row: &Value) -> Option<Vec<String>> {
+        let cells = row.get("table_row")?.get("cells")?.as_array()?;
+        Some(
+            cells
+                .iter()
+                .map(|cell| {
+                    cell.as_array()
+                        .map(|items| items.iter().filter_map(Self::rich_text_item).collect::<String>())
+                        .unwrap_or_default()
+                })
+                .collect(),
+        )
+    }
+
+    /// A table block as a markdown pipe table; its rows are fetched as the block's children
+    async fn render_table(&self, block: &Value) -> Vec<String> {
+        let Some(id) = block.get("id").and_then(|i| i.as_str()) else {
+            return Vec::new();
+        };
+        // Rows have no children of their own, so one level is enough
+        let rows = match self.block_children_raw(id.to_string(), MAX_RAW_BLOCK_DEPTH).await {
+            Ok(rows) => rows,
+            Err(_) => return vec!["> (Table could not be loaded)".to_string()],
+        };
+        let rows: Vec<Vec<String>> = rows.iter().filter_map(Self::table_row_cells).collect();
+        let Some(width) = rows.iter().map(Vec::len).max().filter(|w| *w > 0) else {
+            return Vec::new();
+        };
+
+        let line = |cells: &[String]| {
+            let mut cells: Vec<String> = cells.iter().map(|c| c.replace('|', "\\|").replace('\n', " ")).collect();
+            cells.resize(width, String::new());
+            format!("| {} |", cells.join(" | "))
+        };
+        let mut lines = vec![line(&rows[0]), format!("|{}", " --- |".repeat(width))];
+        lines.extend(rows[1..].iter().map(|row| line(row)));
+        vec![lines.join("\n")]
+    }
+
+    /// Text of a list of blocks, expanding the block types that need extra requests
+    async fn render_blocks(&self, blocks: &[Value]) -> Vec<String> {
+        let mut texts = Vec::new();
+
+        for block in blocks {
+            match block.get("type").and_then(|t| t.as_str()) {
+                Some("synced_block") => texts.extend(self.render_synced_block(block).await),
+                Some("child_database") => texts.extend(self.render_child_database(block).await),
+                Some("table") => texts.extend(self.render_table(block).await),
+                _ => texts.extend(Self::extract_block_text(block)),
+            }
+        }
+
+        texts
+    }
+
+    /// A synced block's content lives in the children of the original block.
+    /// `synced_from` is null on the original itself and points at it from every copy.
+    async fn render_synced_block(&self, block: &Value) -> Vec<String> {
+        let source_id = block
+            .get("synced_block")
+            .and_then(|s| s.get("synced_from"))
+            .and_then(|f| f.get("block_id"))
+            .or_else(|| block.get("id"))
+            .and_then(|id| id.as_str());
+        let Some(source_id) = source_id else {
+            return Vec::new();
+        };
+
+        let children = match self
+            .notion_call_with_retry("API-get-block-children", json!({ "block_id": source_id }))
+            .await
+        {
+            Ok(result) if Self::check_page_access(&result, source_id).is_ok() => result,
+            _ => {
+                return vec![
+                    "> (Synced block could not be loaded; its original may not be shared with the integration)"
+                        .to_string(),
+                ]
+            }
+        };
+
+        // Synced blocks can't contain other synced blocks, so one level is enough
+        children
+            .get("results")
+            .and_then(|r| r.as_array())
+            .map(|results| results.iter().filter_map(Self::extract_block_text).collect())
+            .unwrap_or_default()
+    }
+
+    /// Inline databases become a heading plus the first few row titles
+    async fn render_child_database(&self, block: &Value) -> Vec<String> {
+        let title = block
+            .get("child_database")
+            .and_then(|d| d.get("title"))
+            .and_then(|t| t.as_str())
+            .filter(|t| !t.is_empty())
+            .unwrap_or("Untitled database");
+        let mut texts = vec![
+            format!("## {}", title),
+            "> Inline database".to_string(),
+        ];
+
+        let Some(database_id) = block.get("id").and_then(|id| id.as_str()) else {
+            return texts;
+        };
+        let rows = self
+            .notion_call_with_retry(
+                "API-post-database-query",
+                json!({ "database_id": database_id, "page_size": CHILD_DATABASE_PREVIEW_ROWS }),
+            )
+            .await
+            .and_then(Self::checked);
+
+        if let Ok(rows) = rows {
+            if let Some(results) = rows.get("results").and_then(|r| r.as_array()) {
+                texts.extend(
+                    results
+                        .iter()
+                        .filter_map(Self::extract_page_title)
+                        .map(|row| format!("- {}", row)),
+                );
+            }
+            if rows.get("has_more").and_then(|h| h.as_bool()).unwrap_or(false) {
+                texts.push("- ...".to_string());
+            }
+        }
+
+        texts
+    }
+
+    /// Turn a Notion error result into a message, explaining the "not shared with integration" case.
+    /// Notion reports pages the integration can't see as not found, so both codes get the same hint.
+    fn check_page_access(result: &Value, page_id: &str) -> Result<(), String> {
+        let status = match result.get("status").and_then(|s| s.as_i64()) {
+            Some(status) if status != 200 => status,
+            _ => return Ok(()),
+        };
+
+        let message = result
+            .get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or("Unknown error");
+        let code = result.get("code").and_then(|c| c.as_str()).unwrap_or("");
+
+        if code == "object_not_found" || code == "restricted_resource" {
+            return Err(format!(
+                "Notion page {} is not accessible ({}). If the page exists, it has not been shared \
+                with your integration: open the page in Notion, click \"Share\" (or \"•••\" → \"Connections\"), \
+                and add your integration, then try again.",
+                page_id, code
+            ));
+        }
+
+        Err(format!("Notion API error ({}): {}", status, message))
+    }
+
+    /// Compare ignoring heading markers, case and runs of whitespace
+    fn same_heading_text(a: &str, b: &str) -> bool {
+        let normalize = |s: &str| {
+            s.trim()
+                .trim_start_matches('#')
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase()
+        };
+        normalize(a) == normalize(b)
+    }
+
+    fn extract_page_title(page: &Value) -> Option<String> {
+        if let Some(properties) = page.get("properties").and_then(|p| p.as_object()) {
+            for (_key, prop) in properties {
+                if prop.get("type").and_then(|t| t.as_str()) == Some("title") {
+                    if let Some(title_arr) = prop.get("title").and_then(|t| t.as_array()) {
+                        let title: String = title_arr
+                            .iter()
+                            .filter_map(|t| t.get("plain_text").and_then(|pt| pt.as_str()))
+                            .collect::<Vec<_>>()
+                            .join("");
+                        if !title.is_empty() {
+                            return Some(title);
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Text of one rich-text item: equations as `$...$`, mentions by name even when
+    /// `plain_text` is missing (some MCP servers drop it)
+    fn rich_text_item(item: &Value) -> Option<String> {
+        let plain_text = item
+            .get("plain_text")
+            .and_then(|pt| pt.as_str())
+            .filter(|pt| !pt.is_empty());
+
+        match item.get("type").and_then(|t| t.as_str()) {
+            Some("equation") => item
+                .pointer("/equation/expression")
+                .and_then(|e| e.as_str())
+                .or(plain_text)
+                .map(|expression| format!("${}$", expression)),
+            Some("mention") => {
+                if let Some(text) = plain_text {
+                    return Some(text.to_string());
+                }
+                let mention = item.get("mention")?;
+                let name = match mention.get("type").and_then(|t| t.as_str())? {
+                    "user" => mention.pointer("/user/name"),
+                    "date" => mention.pointer("/date/start"),
+                    "page" => mention.pointer("/page/id"),
+                    "database" => mention.pointer("/database/id"),
+                    _ => None,
+                };
+                name.and_then(|n| n.as_str()).map(|n| format!("@{}", n))
+            }
+            _ => plain_text.map(|t| t.to_string()),
+        }
+    }
+
+    fn extract_block_text(block: &Value) -> Option<String> {
+        let block_type = block.get("type").and_then(|t| t.as_str())?;
+        let type_content = block.get(block_type)?;
+
+        if block_type == "table_row" {
+            let cells = Self::table_row_cells(block)?;
+            return (!cells.iter().all(String::is_empty)).then(|| format!("| {} |", cells.join(" | ")));
+        }
+
+        if let Some(rich_text) = type_content.get("rich_text").and_then(|rt| rt.as_array()) {
+            let text: String = rich_text
+                .iter()
+                .filter_map(Self::rich_text_item)
+                .collect::<Vec<_>>()
+                .join("");
+
+            if text.is_empty() {
+                return None;
+            }
+
+            return Some(match block_type {
+                "heading_1" => format!("# {}", text),
+                "heading_2" => format!("## {}", text),
+                "heading_3" => format!("### {}", text),
+                "bulleted_list_item" => format!("- {}", text),
                 "numbered_list_item" => format!("1. {}", text),
                 "quote" => format!("> {}", text),
                 "code" => format!("```\n{}\n```", text),
@@ -616,6 +1674,7 @@ impl McpClient {
         None
     }
 
+    /// `database_id` may also name a save target, whose title property is then used
     pub async fn save_to_notion(
         &self,
         database_id: &str,
@@ -623,32 +1682,219 @@ impl McpClient {
         content: &str,
         _source_url: &str,
     ) -> Result<String, String> {
-        let db_id = Self::extract_page_id(database_id);
-        let blocks = self.markdown_to_blocks(content);
+        self.save_markdown(database_id, title, content, Vec::new()).await
+    }
+
+    /// `save_to_notion` for a Q&A answer, ending in a "출처" section that links to `source_url`
+    pub async fn save_answer_to_notion(
+        &self,
+        database_id: &str,
+        title: &str,
+        content: &str,
+        source_url: &str,
+    ) -> Result<String, String> {
+        let source_url = source_url.trim();
+        let source = if source_url.is_empty() {
+            Vec::new()
+        } else {
+            vec![
+                json!({ "object": "block", "type": "divider", "divider": {} }),
+                json!({
+                    "object": "block",
+                    "type": "heading_2",
+                    "heading_2": { "rich_text": Self::rich_text("출처") }
+                }),
+                json!({
+                    "object": "block",
+                    "type": "paragraph",
+                    "paragraph": { "rich_text": Self::link_text(source_url) }
+                }),
+            ]
+        };
+        self.save_markdown(database_id, title, content, source).await
+    }
+
+    /// Rich text for `url`, linked when it is a web address Notion will accept as a link
+    fn link_text(url: &str) -> Vec<Value> {
+        let linkable = (url.starts_with("https://") || url.starts_with("http://")) && !url.contains(char::is_whitespace);
+        if !linkable {
+            return Self::rich_text(url);
+        }
+        vec![json!({
+            "type": "text",
+            "text": { "content": url, "link": { "url": url } }
+        })]
+    }
+
+    /// New database page of `content` as blocks, followed by `trailing`
+    async fn save_markdown(
+        &self,
+        database_id: &str,
+        title: &str,
+        content: &str,
+        trailing: Vec<Value>,
+    ) -> Result<String, String> {
+        let target = Self::resolve_save_target(database_id);
+        let db_id = Self::extract_page_id(&target.database_id);
+        let (frontmatter, content) = Self::parse_frontmatter(content);
+        let title = frontmatter.title.as_deref().unwrap_or(title);
+        let mut blocks = self.markdown_to_blocks(content);
+        blocks.extend(trailing);
+
+        let mut properties = Self::title_properties(&target, title);
+
+        if !frontmatter.tags.is_empty() {
+            if let Some(tags_property) = self.find_tags_property(&db_id).await {
+                properties[tags_property] = json!({
+                    "multi_select": frontmatter
+                        .tags
+                        .iter()
+                        .map(|tag| json!({ "name": tag }))
+                        .collect::<Vec<_>>()
+                });
+            }
+        }
+
+        self.create_page(json!({ "database_id": db_id }), properties, blocks)
+            .await
+    }
+
+    /// `{ <title property>: <title> }`, using the target's title property name when it has one
+    fn title_properties(target: &SaveTarget, title: &str) -> Value {
+        let mut properties = json!({});
+        properties[target.title_property.as_deref().unwrap_or("title")] = json!({
+            "title": [{ "text": { "content": title } }]
+        });
+        properties
+    }
+
+    /// New database page of toggle blocks, each `(summary, body)` pair opening to its body.
+    /// `database_id` may also name a save target.
+    pub async fn save_toggles_to_notion(
+        &self,
+        database_id: &str,
+        title: &str,
+        toggles: &[(String, String)],
+    ) -> Result<String, String> {
+        let target = Self::resolve_save_target(database_id);
+        let db_id = Self::extract_page_id(&target.database_id);
+        let blocks = toggles
+            .iter()
+            .map(|(summary, body)| {
+                json!({
+                    "object": "block",
+                    "type": "toggle",
+                    "toggle": {
+                        "rich_text": Self::rich_text(summary),
+                        "children": [{
+                            "object": "block",
+                            "type": "paragraph",
+                            "paragraph": {
+                                "rich_text": Self::rich_text(body)
+                            }
+                        }]
+                    }
+                })
+            })
+            .collect();
+        let properties = Self::title_properties(&target, title);
 
+        self.create_page(json!({ "database_id": db_id }), properties, blocks)
+            .await
+    }
+
+    /// Save under `parent`, which may be a save target name, a database or a regular page
+    pub async fn save_under_parent(&self, parent: &str, title: &str, content: &str) -> Result<String, String> {
+        let parent_id = Self::extract_page_id(&Self::resolve_save_target(parent).database_id);
+
+        // Retrieving a page as a database fails, which is how the two are told apart
+        let is_database = self
+            .notion_call_with_retry(
+                "API-retrieve-a-database",
+                json!({ "database_id": parent_id }),
+            )
+            .await
+            .and_then(Self::checked)
+            .ok()
+            .and_then(|db| db.get("object").and_then(|o| o.as_str()).map(|o| o == "database"))
+            .unwrap_or(false);
+        if is_database {
+            // Pass the name on so a target's title property is used
+            return self.save_to_notion(parent, title, content, "").await;
+        }
+
+        // Child pages only have a title property
+        let (frontmatter, content) = Self::parse_frontmatter(content);
+        let title = frontmatter.title.as_deref().unwrap_or(title);
+        let properties = json!({
+            "title": {
+                "title": [{ "text": { "content": title } }]
+            }
+        });
+
+        self.create_page(
+            json!({ "page_id": parent_id }),
+            properties,
+            self.markdown_to_blocks(content),
+        )
+        .await
+    }
+
+    /// Create the page with at most one batch of blocks, then append the rest batch by batch;
+    /// create-a-page is stricter than append and both take at most `NOTION_BLOCK_BATCH` blocks.
+    /// Returns the page id only once every block is saved.
+    async fn create_page(&self, parent: Value, properties: Value, mut blocks: Vec<Value>) -> Result<String, String> {
+        let rest = blocks.split_off(blocks.len().min(NOTION_BLOCK_BATCH));
         let result = self
-            .call_tool(
+            .notion_call_with_retry(
                 "API-create-a-page",
                 json!({
-                    "parent": {
-                        "database_id": db_id
-                    },
-                    "properties": {
-                        "title": {
-                            "title": [
-                                {
-                                    "text": {
-                                        "content": title
-                                    }
-                                }
-                            ]
-                        }
-                    },
+                    "parent": parent,
+                    "properties": properties,
                     "children": blocks
                 }),
             )
             .await?;
+        Self::check_status(&result)?;
+
+        let page_id = result
+            .get("id")
+            .and_then(|id| id.as_str())
+            .map(|s| s.to_string())
+            .ok_or_else(|| "Failed to get created page ID".to_string())?;
+
+        let total = blocks.len() + rest.len();
+        let mut saved = blocks.len();
+        for batch in rest.chunks(NOTION_BLOCK_BATCH) {
+            let appended = self
+                .notion_call_with_retry(
+                    "API-patch-block-children",
+                    json!({
+                        "block_id": page_id,
+                        "children": batch
+                    }),
+                )
+                .await
+                .and_then(|result| Self::check_status(&result));
+            if let Err(e) = appended {
+                return Err(format!(
+                    "Page {} was created but only {} of {} blocks were saved: {}",
+                    page_id, saved, total, e
+                ));
+            }
+            saved += batch.len();
+        }
+
+        Ok(page_id)
+    }
 
+    /// `result` itself once `check_status` passes
+    fn checked(result: Value) -> Result<Value, String> {
+        Self::check_status(&result)?;
+        Ok(result)
+    }
+
+    fn check_status(result: &Value) -> Result<(), String> {
         if let Some(status) = result.get("status").and_then(|s| s.as_i64()) {
             if status != 200 {
                 let message = result
@@ -658,12 +1904,191 @@ impl McpClient {
                 return Err(format!("Notion API error ({}): {}", status, message));
             }
         }
+        Ok(())
+    }
+
+    /// Content without its frontmatter block, which is saved as properties rather than blocks
+    pub(crate) fn strip_frontmatter(content: &str) -> &str {
+        Self::parse_frontmatter(content).1
+    }
+
+    /// Split a leading YAML frontmatter block (`---` ... `---`) from the body.
+    /// Only `title` and `tags` (inline `[a, b]` or `- a` list form) are read.
+    fn parse_frontmatter(content: &str) -> (Frontmatter, &str) {
+        let mut frontmatter = Frontmatter::default();
+
+        let trimmed = content.trim_start_matches('\u{feff}').trim_start();
+        let Some(after_open) = trimmed.strip_prefix("---") else {
+            return (frontmatter, content);
+        };
+        let Some(after_open) = after_open
+            .strip_prefix("\r\n")
+            .or_else(|| after_open.strip_prefix('\n'))
+        else {
+            return (frontmatter, content);
+        };
+
+        let mut offset = 0;
+        let mut closing = None;
+        for line in after_open.split_inclusive('\n') {
+            if line.trim_end() == "---" {
+                closing = Some((offset, offset + line.len()));
+                break;
+            }
+            offset += line.len();
+        }
+        let Some((yaml_end, body_start)) = closing else {
+            return (frontmatter, content);
+        };
 
-        result
-            .get("id")
-            .and_then(|id| id.as_str())
-            .map(|s| s.to_string())
-            .ok_or_else(|| "Failed to get created page ID".to_string())
+        let unquote = |v: &str| v.trim().trim_matches('"').trim_matches('\'').trim().to_string();
+        let is_key = |k: &str| {
+            !k.is_empty() && k.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        };
+        // A `---` block that isn't all `key: value` lines, or names neither key we read,
+        // is a horizontal rule and stays part of the content
+        let not_frontmatter = (Frontmatter::default(), content);
+        let mut in_list = false;
+        let mut in_tags_list = false;
+        let mut recognised = false;
+
+        for line in after_open[..yaml_end].lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if in_list {
+                if let Some(item) = trimmed.strip_prefix("- ") {
+                    let tag = unquote(item);
+                    if in_tags_list && !tag.is_empty() {
+                        frontmatter.tags.push(tag);
+                    }
+                    continue;
+                }
+                in_tags_list = false;
+            }
+
+            let Some((key, value)) = line.split_once(':').filter(|(key, _)| is_key(key.trim())) else {
+                return not_frontmatter;
+            };
+            in_list = value.trim().is_empty();
+            match key.trim() {
+                "title" => {
+                    recognised = true;
+                    let title = unquote(value);
+                    if !title.is_empty() {
+                        frontmatter.title = Some(title);
+                    }
+                }
+                "tags" => {
+                    recognised = true;
+                    let value = value.trim();
+                    if value.is_empty() {
+                        in_tags_list = true;
+                    } else {
+                        frontmatter.tags.extend(
+                            value
+                                .trim_start_matches('[')
+                                .trim_end_matches(']')
+                                .split(',')
+                                .map(unquote)
+                                .filter(|t| !t.is_empty()),
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !recognised {
+            return not_frontmatter;
+        }
+
+        // Notion multi-select options cannot contain commas
+        for tag in frontmatter.tags.iter_mut() {
+            *tag = tag.replace(',', " ");
+        }
+
+        (frontmatter, &after_open[body_start..])
+    }
+
+    /// Name of the database's multi-select tags property, if it has one
+    async fn find_tags_property(&self, database_id: &str) -> Option<String> {
+        let database = self
+            .notion_call_with_retry(
+                "API-retrieve-a-database",
+                json!({ "database_id": database_id }),
+            )
+            .await
+            .and_then(Self::checked)
+            .ok()?;
+
+        let properties = database.get("properties")?.as_object()?;
+        let multi_selects: Vec<&String> = properties
+            .iter()
+            .filter(|(_, prop)| prop.get("type").and_then(|t| t.as_str()) == Some("multi_select"))
+            .map(|(name, _)| name)
+            .collect();
+
+        multi_selects
+            .iter()
+            .find(|name| {
+                let name = name.to_lowercase();
+                name == "tags" || name == "tag" || name == "태그"
+            })
+            .or_else(|| multi_selects.first())
+            .map(|name| name.to_string())
+    }
+
+    /// Rich-text array for `text`, split into pieces under Notion's 2000-character limit.
+    /// Splits at whitespace where possible so words are not cut in half.
+    fn rich_text(text: &str) -> Vec<Value> {
+        const NOTION_RICH_TEXT_LIMIT: usize = 2000;
+
+        let mut pieces = Vec::new();
+        let mut rest = text;
+
+        while rest.chars().count() > NOTION_RICH_TEXT_LIMIT {
+            let limit_byte = rest
+                .char_indices()
+                .nth(NOTION_RICH_TEXT_LIMIT)
+                .map(|(i, _)| i)
+                .unwrap_or(rest.len());
+            let head = &rest[..limit_byte];
+
+            // Keep the whitespace with the first piece so rejoining restores the text
+            let split_at = head
+                .rfind(char::is_whitespace)
+                .map(|i| i + head[i..].chars().next().map(|c| c.len_utf8()).unwrap_or(1))
+                .filter(|&i| i > 0 && i <= limit_byte)
+                .unwrap_or(limit_byte);
+
+            pieces.push(&rest[..split_at]);
+            rest = &rest[split_at..];
+        }
+        pieces.push(rest);
+
+        pieces
+            .into_iter()
+            .map(|piece| {
+                json!({
+                    "type": "text",
+                    "text": { "content": piece }
+                })
+            })
+            .collect()
+    }
+
+    /// `- [ ] task` / `- [x] task` checklist lines
+    fn parse_task_item(line: &str) -> Option<(bool, &str)> {
+        let rest = line.strip_prefix("- [")?;
+        let (checked, text) = if let Some(text) = rest.strip_prefix(" ] ") {
+            (false, text)
+        } else if let Some(text) = rest.strip_prefix("x] ").or_else(|| rest.strip_prefix("X] ")) {
+            (true, text)
+        } else {
+            return None;
+        };
+        Some((checked, text))
     }
 
     fn markdown_to_blocks(&self, content: &str) -> Vec<Value> {
@@ -675,26 +2100,20 @@ impl McpClient {
                 continue;
             }
 
-            let block = if line.starts_with("## ") {
+            let block = if let Some(text) = line.strip_prefix("## ") {
                 json!({
                     "object": "block",
                     "type": "heading_2",
                     "heading_2": {
-                        "rich_text": [{
-                            "type": "text",
-                            "text": { "content": &line[3..] }
-                        }]
+                        "rich_text": Self::rich_text(text)
                     }
                 })
-            } else if line.starts_with("# ") {
+            } else if let Some(text) = line.strip_prefix("# ") {
                 json!({
                     "object": "block",
                     "type": "heading_1",
                     "heading_1": {
-                        "rich_text": [{
-                            "type": "text",
-                            "text": { "content": &line[2..] }
-                        }]
+                        "rich_text": Self::rich_text(text)
                     }
                 })
             } else if line == "---" {
@@ -703,15 +2122,21 @@ impl McpClient {
                     "type": "divider",
                     "divider": {}
                 })
-            } else if line.starts_with("- ") {
+            } else if let Some((checked, text)) = Self::parse_task_item(line) {
+                json!({
+                    "object": "block",
+                    "type": "to_do",
+                    "to_do": {
+                        "rich_text": Self::rich_text(text),
+                        "checked": checked
+                    }
+                })
+            } else if let Some(text) = line.strip_prefix("- ") {
                 json!({
                     "object": "block",
                     "type": "bulleted_list_item",
                     "bulleted_list_item": {
-                        "rich_text": [{
-                            "type": "text",
-                            "text": { "content": &line[2..] }
-                        }]
+                        "rich_text": Self::rich_text(text)
                     }
                 })
             } else {
@@ -719,10 +2144,7 @@ impl McpClient {
                     "object": "block",
                     "type": "paragraph",
                     "paragraph": {
-                        "rich_text": [{
-                            "type": "text",
-                            "text": { "content": line }
-                        }]
+                        "rich_text": Self::rich_text(line)
                     }
                 })
             };
@@ -734,6 +2156,15 @@ impl McpClient {
     }
 
     pub async fn disconnect(&self) -> Result<(), String> {
+        // An explicit disconnect stays disconnected
+        self.idle_disconnected.store(false, Ordering::SeqCst);
+        self.shutdown().await
+    }
+
+    async fn shutdown(&self) -> Result<(), String> {
+        // Cancel in-flight requests first; they drop the stdin/stdout locks on the way out
+        self.closed.send_replace(true);
+
         let mut process = self.process.lock().await;
         if let Some(mut child) = process.take() {
             child.kill().await.map_err(|e| format!("Failed to kill process: {}", e))?;
@@ -753,3 +2184,207 @@ impl Default for McpClient {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Use `sh -c script` as the server, skipping the initialize handshake
+    async fn attach_stub(client: &McpClient, script: &str) {
+        let mut child = Command::new("sh")
+            .args(["-c", script])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to start stub server");
+        *client.stdin.lock().await = child.stdin.take();
+        *client.stdout.lock().await = child.stdout.take().map(BufReader::new);
+        *client.process.lock().await = Some(child);
+        client.closed.send_replace(false);
+    }
+
+    fn tools_list_request(client: &McpClient) -> JsonRpcRequest {
+        JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: client.next_id(),
+            method: "tools/list".to_string(),
+            params: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn disconnect_during_requests_ends_them_without_hanging() {
+        let client = Arc::new(McpClient::new());
+        // Reads requests and never answers
+        attach_stub(&client, "while read line; do :; done").await;
+
+        let fetches: Vec<_> = (0..3)
+            .map(|_| {
+                let client = client.clone();
+                tokio::spawn(async move {
+                    let request = tools_list_request(&client);
+                    client.send_request(&request).await
+                })
+            })
+            .collect();
+        let disconnect = {
+            let client = client.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                client.disconnect().await
+            })
+        };
+
+        let finished = timeout(Duration::from_secs(5), async {
+            let disconnected = disconnect.await.expect("disconnect panicked");
+            let mut results = Vec::new();
+            for fetch in fetches {
+                results.push(fetch.await.expect("request panicked"));
+            }
+            (disconnected, results)
+        })
+        .await
+        .expect("requests hung after disconnect");
+
+        assert_eq!(finished.0, Ok(()));
+        for result in finished.1 {
+            assert_eq!(result, Err(MCP_CONNECTION_CLOSED.to_string()));
+        }
+        assert!(!client.is_connected().await);
+
+        // Requests after the disconnect fail straight away
+        let request = tools_list_request(&client);
+        assert!(client.send_request(&request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn server_exiting_after_a_request_fails_it_immediately() {
+        let client = McpClient::new();
+        attach_stub(&client, "read line").await;
+
+        let started = std::time::Instant::now();
+        let request = tools_list_request(&client);
+        let result = client.send_request(&request).await;
+
+        assert_eq!(result, Err(MCP_SERVER_EXITED.to_string()));
+        assert!(started.elapsed() < Duration::from_secs(5), "waited {:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn server_exiting_mid_response_reports_the_cut_off() {
+        let client = McpClient::new();
+        attach_stub(&client, r#"read line; printf '{"jsonrpc":"2.0","id":1,"res'"#).await;
+
+        let started = std::time::Instant::now();
+        let request = tools_list_request(&client);
+        let error = client.send_request(&request).await.unwrap_err();
+
+        assert!(error.starts_with(MCP_SERVER_EXITED), "{}", error);
+        assert!(error.contains("cut off after 28 bytes"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(5), "waited {:?}", started.elapsed());
+    }
+
+    fn rich_text_pieces(text: &str) -> Vec<String> {
+        McpClient::rich_text(text)
+            .iter()
+            .map(|item| item["text"]["content"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    fn assert_chunked(text: &str, expected_chunks: usize) {
+        let pieces = rich_text_pieces(text);
+        assert_eq!(pieces.len(), expected_chunks);
+        for piece in &pieces {
+            assert!(piece.chars().count() <= 2000, "piece of {} chars", piece.chars().count());
+        }
+        assert_eq!(pieces.concat(), text);
+    }
+
+    #[test]
+    fn rich_text_splits_long_paragraph_at_whitespace() {
+        let text = "lorem ipsum ".repeat(417)[..5000].to_string();
+        assert_eq!(text.chars().count(), 5000);
+        assert_chunked(&text, 3);
+    }
+
+    #[test]
+    fn rich_text_splits_text_without_whitespace() {
+        assert_chunked(&"a".repeat(5000), 3);
+    }
+
+    #[test]
+    fn rich_text_never_splits_multibyte_chars() {
+        // 3-byte chars with no whitespace to split at
+        assert_chunked(&"한글".repeat(2500), 3);
+        // Korean words with spaces, and emoji (4 bytes)
+        let words: String = "안녕하세요 😀 ".repeat(700).chars().take(5000).collect();
+        assert_chunked(&words, 3);
+    }
+
+    #[test]
+    fn rich_text_keeps_short_text_in_one_piece() {
+        assert_chunked("", 1);
+        assert_chunked(&"가".repeat(2000), 1);
+    }
+
+    #[test]
+    fn frontmatter_reads_title_and_tags() {
+        let (frontmatter, body) =
+            McpClient::parse_frontmatter("---\ntitle: \"Notes\"\ndate: 2024-01-01\ntags:\n  - a\n  - b,c\n---\nBody");
+        assert_eq!(frontmatter.title.as_deref(), Some("Notes"));
+        assert_eq!(frontmatter.tags, vec!["a", "b c"]);
+        assert_eq!(body, "Body");
+    }
+
+    #[test]
+    fn horizontal_rule_at_the_start_is_not_frontmatter() {
+        let content = "---\nFirst section text.\n\n---\nSecond section.";
+        let (frontmatter, body) = McpClient::parse_frontmatter(content);
+        assert!(frontmatter.title.is_none() && frontmatter.tags.is_empty());
+        assert_eq!(body, content);
+    }
+
+    #[test]
+    fn block_without_a_known_key_is_kept() {
+        let content = "---\nauthor: Kim\n---\nBody";
+        assert_eq!(McpClient::strip_frontmatter(content), content);
+    }
+
+    #[test]
+    fn only_the_answer_source_is_linked() {
+        let client = McpClient::new();
+        let blocks = client.markdown_to_blocks("https://example.com/page\n```\nhttps://example.com/code\n```");
+        assert!(blocks.iter().all(|b| !b.to_string().contains("\"link\"")), "{:?}", blocks);
+
+        let linked = McpClient::link_text("https://example.com/page");
+        assert_eq!(linked[0]["text"]["link"]["url"], "https://example.com/page");
+        let plain = McpClient::link_text("사내 위키 3쪽");
+        assert!(plain[0]["text"].get("link").is_none());
+    }
+
+    #[test]
+    fn table_rows_read_each_cell_of_each_row() {
+        let row = |cells: &[&str]| {
+            json!({
+                "type": "table_row",
+                "table_row": {
+                    "cells": cells
+                        .iter()
+                        .map(|c| json!([{ "type": "text", "plain_text": c }]))
+                        .collect::<Vec<_>>()
+                }
+            })
+        };
+        let table = json!({
+            "type": "table",
+            "table": { "table_width": 2 },
+            "children": [row(&["이름", "값"]), row(&["a", "1"])]
+        });
+
+        assert_eq!(
+            McpClient::table_rows(&table),
+            vec![vec!["이름".to_string(), "값".to_string()], vec!["a".to_string(), "1".to_string()]]
+        );
+        assert_eq!(McpClient::extract_block_text(&row(&["a", "1"])).as_deref(), Some("| a | 1 |"));
+    }
+}
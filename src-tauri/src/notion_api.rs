@@ -0,0 +1,137 @@
+use crate::http_client::build_client;
+use crate::redact::redact_with;
+use reqwest::{Client, Method};
+use serde_json::{json, Value};
+
+const NOTION_API_URL: &str = "https://api.notion.com/v1";
+const NOTION_VERSION: &str = "2022-06-28";
+
+/// Direct Notion REST client, an alternative to the MCP subprocess.
+/// Responses are returned as the raw JSON so callers can share the MCP result handling;
+/// Notion error bodies carry the same `status`/`message` fields the MCP server relays.
+pub struct NotionApi {
+    client: Client,
+}
+
+impl NotionApi {
+    pub fn new() -> Self {
+        Self {
+            client: build_client(15, 30),
+        }
+    }
+
+    /// Error statuses come back as `Ok` with the status folded into the body, like the MCP
+    /// server relays them, so retries on 429 and the `code`-based access messages work the
+    /// same on both backends; `Err` means no Notion response was received or parsed
+    async fn request(
+        &self,
+        token: &str,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<Value, String> {
+        let mut request = self
+            .client
+            .request(method, format!("{}{}", NOTION_API_URL, path))
+            .bearer_auth(token)
+            .header("Notion-Version", NOTION_VERSION);
+
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| redact_with(&format!("Notion request failed: {}", e), token))?;
+
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok());
+        let text = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read response: {}", e))?;
+
+        let mut value: Value = serde_json::from_str(&text).map_err(|e| {
+            redact_with(&format!("Failed to parse Notion response ({}): {}", status, e), token)
+        })?;
+
+        // Make sure error responses always carry the HTTP status for the shared checks
+        if !status.is_success() {
+            if let Some(obj) = value.as_object_mut() {
+                obj.entry("status").or_insert(json!(status.as_u16()));
+                if let Some(seconds) = retry_after {
+                    obj.insert("retry_after".to_string(), json!(seconds));
+                }
+            }
+        }
+
+        Ok(value)
+    }
+
+    pub async fn retrieve_page(&self, token: &str, page_id: &str) -> Result<Value, String> {
+        self.request(token, Method::GET, &format!("/pages/{}", page_id), None)
+            .await
+    }
+
+    pub async fn get_block_children(
+        &self,
+        token: &str,
+        block_id: &str,
+        start_cursor: Option<&str>,
+    ) -> Result<Value, String> {
+        let mut path = format!("/blocks/{}/children?page_size=100", block_id);
+        if let Some(cursor) = start_cursor {
+            path.push_str(&format!("&start_cursor={}", cursor));
+        }
+        self.request(token, Method::GET, &path, None).await
+    }
+
+    pub async fn retrieve_database(&self, token: &str, database_id: &str) -> Result<Value, String> {
+        self.request(token, Method::GET, &format!("/databases/{}", database_id), None)
+            .await
+    }
+
+    pub async fn query_database(&self, token: &str, database_id: &str, body: Value) -> Result<Value, String> {
+        self.request(
+            token,
+            Method::POST,
+            &format!("/databases/{}/query", database_id),
+            Some(body),
+        )
+        .await
+    }
+
+    pub async fn create_page(&self, token: &str, body: Value) -> Result<Value, String> {
+        self.request(token, Method::POST, "/pages", Some(body)).await
+    }
+
+    pub async fn append_block_children(&self, token: &str, block_id: &str, children: Value) -> Result<Value, String> {
+        self.request(
+            token,
+            Method::PATCH,
+            &format!("/blocks/{}/children", block_id),
+            Some(json!({ "children": children })),
+        )
+        .await
+    }
+
+    pub async fn search(&self, token: &str, body: Value) -> Result<Value, String> {
+        self.request(token, Method::POST, "/search", Some(body)).await
+    }
+
+    /// The bot user behind the integration token
+    pub async fn get_bot_user(&self, token: &str) -> Result<Value, String> {
+        self.request(token, Method::GET, "/users/me", None).await
+    }
+}
+
+impl Default for NotionApi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,126 @@
+use crate::claude::SummaryFormat;
+use crate::text_util::content_hash;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+
+/// The oldest operation is forgotten beyond this many
+const MAX_OPERATIONS: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    Summarize,
+    Ask,
+    Translate,
+}
+
+/// Inputs of an operation; also the shape of the overrides for `rerun_operation`,
+/// where only the fields that are set replace the recorded ones
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct OperationParams {
+    pub url: Option<String>,
+    /// "claude", "openai" or "gemini"
+    pub provider: Option<String>,
+    /// Specific model within the provider; `None` uses the provider default
+    pub model: Option<String>,
+    pub question: Option<String>,
+    /// Claude questions only: whether extended thinking was on, and its reasoning included
+    pub thinking: Option<bool>,
+    pub include_reasoning: Option<bool>,
+    pub target_language: Option<String>,
+    pub skip_target_language: Option<bool>,
+    pub output_format: Option<SummaryFormat>,
+}
+
+impl OperationParams {
+    pub fn merged(&self, overrides: &OperationParams) -> OperationParams {
+        OperationParams {
+            url: overrides.url.clone().or_else(|| self.url.clone()),
+            provider: overrides.provider.clone().or_else(|| self.provider.clone()),
+            model: overrides.model.clone().or_else(|| self.model.clone()),
+            question: overrides.question.clone().or_else(|| self.question.clone()),
+            thinking: overrides.thinking.or(self.thinking),
+            include_reasoning: overrides.include_reasoning.or(self.include_reasoning),
+            target_language: overrides
+                .target_language
+                .clone()
+                .or_else(|| self.target_language.clone()),
+            skip_target_language: overrides.skip_target_language.or(self.skip_target_language),
+            output_format: overrides.output_format.or(self.output_format),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Operation {
+    pub id: String,
+    pub kind: OperationKind,
+    pub params: OperationParams,
+    /// Identifies pasted content (operations without a URL) without exposing it
+    pub content_hash: Option<String>,
+    /// Unix seconds
+    pub created_at: u64,
+    /// Pasted content, kept in memory only so the operation can be re-run
+    #[serde(skip)]
+    pub content: Option<String>,
+}
+
+/// Recent successful operations, newest first, for listing and re-running; not persisted
+pub struct OperationLog {
+    operations: Arc<RwLock<VecDeque<Operation>>>,
+    counter: AtomicU64,
+}
+
+impl OperationLog {
+    pub fn new() -> Self {
+        Self {
+            operations: Arc::new(RwLock::new(VecDeque::new())),
+            counter: AtomicU64::new(1),
+        }
+    }
+
+    /// Record an operation on a URL, or on pasted `content` when there is no URL
+    pub async fn record(&self, kind: OperationKind, params: OperationParams, content: Option<&str>) {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let content = content.filter(|_| params.url.is_none());
+        let operation = Operation {
+            id: format!("op-{}", self.counter.fetch_add(1, Ordering::SeqCst)),
+            kind,
+            params,
+            content_hash: content.map(content_hash),
+            created_at,
+            content: content.map(str::to_string),
+        };
+
+        let mut operations = self.operations.write().await;
+        operations.push_front(operation);
+        operations.truncate(MAX_OPERATIONS);
+    }
+
+    pub async fn recent(&self, limit: usize) -> Vec<Operation> {
+        self.operations.read().await.iter().take(limit).cloned().collect()
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Operation, String> {
+        self.operations
+            .read()
+            .await
+            .iter()
+            .find(|op| op.id == id)
+            .cloned()
+            .ok_or_else(|| format!("Operation not found: {}", id))
+    }
+}
+
+impl Default for OperationLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,50 @@
+use crate::config_file::{config_file_path, read_json, stage_json, write_json, StagedFile};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const PREFERENCES_FILE_NAME: &str = "ainotework_preferences.json";
+
+/// Non-secret UI choices restored on the next launch
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct UiPreferences {
+    pub last_model: Option<String>,
+    pub last_target_language: Option<String>,
+    pub last_summary_mode: Option<String>,
+    pub auto_title: Option<bool>,
+}
+
+pub(crate) fn get_config_path() -> Option<PathBuf> {
+    config_file_path(PREFERENCES_FILE_NAME)
+}
+
+/// Defaults when nothing has been saved yet or the file can't be read
+pub fn load() -> UiPreferences {
+    get_config_path()
+        .and_then(|path| read_json(&path).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(preferences: &UiPreferences) -> Result<(), String> {
+    let path = get_config_path().ok_or("Could not determine config directory")?;
+    write_json(&path, preferences)
+}
+
+/// Saved preferences for a settings export; an unreadable file is an error there
+pub fn export() -> Result<UiPreferences, String> {
+    let path = get_config_path().ok_or("Could not determine config directory")?;
+    read_json(&path)
+}
+
+/// Imported preferences over the saved ones, staged for `commit_all`; values missing from the import are kept
+pub fn stage_import(incoming: UiPreferences) -> Result<StagedFile, String> {
+    let path = get_config_path().ok_or("Could not determine config directory")?;
+    let current: UiPreferences = read_json(&path)?;
+    let merged = UiPreferences {
+        last_model: incoming.last_model.or(current.last_model),
+        last_target_language: incoming.last_target_language.or(current.last_target_language),
+        last_summary_mode: incoming.last_summary_mode.or(current.last_summary_mode),
+        auto_title: incoming.auto_title.or(current.auto_title),
+    };
+    stage_json(&path, &merged)
+}
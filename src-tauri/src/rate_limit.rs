@@ -0,0 +1,78 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A full bucket holds this many seconds' worth of requests, so a batch can't spend
+/// the whole minute's allowance at once
+const BURST_SECS: f64 = 10.0;
+
+struct Bucket {
+    /// 0 means unlimited
+    rpm: u32,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn capacity(&self) -> f64 {
+        (self.rpm as f64 / 60.0 * BURST_SECS).max(1.0)
+    }
+}
+
+/// Token bucket shared by every request to one provider, whichever command sends it.
+/// Requests over the limit wait for a token instead of failing.
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(rpm: u32) -> Self {
+        let mut bucket = Bucket {
+            rpm,
+            tokens: 0.0,
+            refilled: Instant::now(),
+        };
+        bucket.tokens = bucket.capacity();
+        Self {
+            bucket: Mutex::new(bucket),
+        }
+    }
+
+    fn bucket(&self) -> std::sync::MutexGuard<'_, Bucket> {
+        self.bucket.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn rpm(&self) -> u32 {
+        self.bucket().rpm
+    }
+
+    /// Starts over with a full bucket at the new rate
+    pub fn set_rpm(&self, rpm: u32) {
+        let mut bucket = self.bucket();
+        bucket.rpm = rpm;
+        bucket.tokens = bucket.capacity();
+        bucket.refilled = Instant::now();
+    }
+
+    /// Wait until a request may be sent; returns at once when there is no limit
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket();
+                if bucket.rpm == 0 {
+                    return;
+                }
+                let rate = bucket.rpm as f64 / 60.0;
+                let now = Instant::now();
+                bucket.tokens = (bucket.tokens + now.duration_since(bucket.refilled).as_secs_f64() * rate)
+                    .min(bucket.capacity());
+                bucket.refilled = now;
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / rate)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
@@ -0,0 +1,145 @@
+/// Known secret prefixes: Anthropic/OpenAI keys, Google API keys, Notion tokens
+const SECRET_PREFIXES: [&str; 4] = ["sk-", "AIza", "secret_", "ntn_"];
+
+/// Minimum length of the part after the prefix before it is treated as a secret
+const MIN_SECRET_BODY_LEN: usize = 8;
+
+fn is_secret_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+/// Mask anything that looks like an API key or integration token.
+/// Keeps the prefix so the user can still tell which credential was involved.
+pub fn redact_secrets(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+    let mut rest = input;
+
+    'outer: while !rest.is_empty() {
+        for prefix in SECRET_PREFIXES {
+            if !rest.starts_with(prefix) {
+                continue;
+            }
+
+            // Only match at a token boundary so words like "task-" are left alone
+            let at_boundary = result
+                .chars()
+                .last()
+                .map(|c| !is_secret_char(c))
+                .unwrap_or(true);
+            if !at_boundary {
+                continue;
+            }
+
+            let body_len: usize = rest[prefix.len()..]
+                .chars()
+                .take_while(|c| is_secret_char(*c))
+                .map(|c| c.len_utf8())
+                .sum();
+
+            if body_len >= MIN_SECRET_BODY_LEN {
+                result.push_str(prefix);
+                result.push_str("***");
+                rest = &rest[prefix.len() + body_len..];
+                continue 'outer;
+            }
+        }
+
+        let c = rest.chars().next().unwrap_or_default();
+        result.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    result
+}
+
+/// Mask a specific known secret (e.g. the configured key) in addition to the pattern-based masking
+pub fn redact_with(input: &str, secret: &str) -> String {
+    let masked = if secret.is_empty() {
+        input.to_string()
+    } else {
+        input.replace(secret, "***")
+    };
+    redact_secrets(&masked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYS: [&str; 5] = [
+        "sk-ant-REDACTED",
+        "sk-proj-abc123XYZ456def789",
+        "AIzaSyA1b2C3d4E5f6G7h8I9j0",
+        "secret_0123456789abcdefABCDEF",
+        "ntn_4815162342abcdefghij",
+    ];
+
+    fn assert_hidden(output: &str, key: &str) {
+        assert!(!output.contains(key), "key leaked: {}", output);
+        assert!(output.contains("***"), "nothing masked: {}", output);
+    }
+
+    #[test]
+    fn masks_bare_keys_and_keeps_prefix() {
+        for key in KEYS {
+            let output = redact_secrets(&format!("using key {} now", key));
+            assert_hidden(&output, key);
+        }
+        assert_eq!(redact_secrets("key sk-abcdefghijkl"), "key sk-***");
+    }
+
+    #[test]
+    fn masks_key_inside_url() {
+        for key in KEYS {
+            let input = format!(
+                "error sending request for url (https://example.com/v1/models?key={}&alt=sse)",
+                key
+            );
+            let output = redact_secrets(&input);
+            assert_hidden(&output, key);
+            assert!(output.ends_with("&alt=sse)"));
+        }
+    }
+
+    #[test]
+    fn masks_key_inside_json_error_body() {
+        for key in KEYS {
+            let input = format!(
+                r#"{{"error":{{"message":"Incorrect API key provided: {}.","type":"invalid_request_error"}}}}"#,
+                key
+            );
+            let output = redact_secrets(&input);
+            assert_hidden(&output, key);
+            assert!(output.contains(r#".","type""#));
+        }
+    }
+
+    #[test]
+    fn masks_key_next_to_punctuation() {
+        for key in KEYS {
+            for wrapped in [
+                format!("({})", key),
+                format!("'{}',", key),
+                format!("\"{}\";", key),
+                format!("key:{}.", key),
+                format!("[{}]", key),
+            ] {
+                assert_hidden(&redact_secrets(&wrapped), key);
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_ordinary_words_alone() {
+        let input = "task-list and desk-top are fine, so is sk-short";
+        assert_eq!(redact_secrets(input), input);
+    }
+
+    #[test]
+    fn redact_with_masks_keys_without_a_known_prefix() {
+        let key = "custom-9f8e7d6c5b4a";
+        let output = redact_with(&format!("failed: {{\"token\":\"{}\"}}", key), key);
+        assert!(!output.contains(key));
+        assert_eq!(output, "failed: {\"token\":\"***\"}");
+    }
+}
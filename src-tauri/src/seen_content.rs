@@ -0,0 +1,100 @@
+use crate::config_file::{config_file_path, read_json, stage_json, write_json, StagedFile};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SEEN_CONTENT_FILE_NAME: &str = "ainotework_seen_content.json";
+/// The least recently updated entries are dropped beyond this many
+const MAX_SEEN_ENTRIES: usize = 200;
+
+/// Hash of a page's content the last time it was processed, with the result produced then
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SeenContent {
+    pub hash: String,
+    pub result: String,
+    /// Unix seconds
+    pub updated_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct SeenContentConfig {
+    /// Keyed by operation and URL, e.g. "summarize https://..."
+    entries: HashMap<String, SeenContent>,
+}
+
+pub(crate) fn get_config_path() -> Option<PathBuf> {
+    config_file_path(SEEN_CONTENT_FILE_NAME)
+}
+
+fn try_load_config() -> Result<SeenContentConfig, String> {
+    let path = get_config_path().ok_or("Could not determine config directory")?;
+    read_json(&path)
+}
+
+fn save_config(config: &SeenContentConfig) -> Result<(), String> {
+    let path = get_config_path().ok_or("Could not determine config directory")?;
+    write_json(&path, config)
+}
+
+/// What was recorded for `key`, if anything; an unreadable file counts as nothing recorded
+pub fn last_seen(key: &str) -> Option<SeenContent> {
+    try_load_config().ok()?.entries.remove(key)
+}
+
+pub fn remember(key: &str, hash: String, result: String) -> Result<(), String> {
+    let mut config = try_load_config()?;
+    let updated_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    config.entries.insert(
+        key.to_string(),
+        SeenContent {
+            hash,
+            result,
+            updated_at,
+        },
+    );
+    prune(&mut config);
+    save_config(&config)
+}
+
+/// Recorded entries for a settings export
+pub fn export_entries() -> Result<HashMap<String, SeenContent>, String> {
+    Ok(try_load_config()?.entries)
+}
+
+/// Imported entries merged into the recorded ones, staged for `commit_all`; for a key in both, the newer is kept
+pub fn stage_import(imported: HashMap<String, SeenContent>) -> Result<StagedFile, String> {
+    let mut config = try_load_config()?;
+    for (key, seen) in imported {
+        let newer = config
+            .entries
+            .get(&key)
+            .is_none_or(|current| current.updated_at < seen.updated_at);
+        if newer {
+            config.entries.insert(key, seen);
+        }
+    }
+    prune(&mut config);
+
+    let path = get_config_path().ok_or("Could not determine config directory")?;
+    stage_json(&path, &config)
+}
+
+/// Drop the least recently updated entries beyond `MAX_SEEN_ENTRIES`
+fn prune(config: &mut SeenContentConfig) {
+    if config.entries.len() > MAX_SEEN_ENTRIES {
+        let mut by_age: Vec<(String, u64)> = config
+            .entries
+            .iter()
+            .map(|(key, seen)| (key.clone(), seen.updated_at))
+            .collect();
+        by_age.sort_by_key(|(_, updated_at)| *updated_at);
+        let excess = config.entries.len() - MAX_SEEN_ENTRIES;
+        for (key, _) in by_age.into_iter().take(excess) {
+            config.entries.remove(&key);
+        }
+    }
+}
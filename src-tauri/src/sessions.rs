@@ -0,0 +1,90 @@
+use crate::commands::ConversationMessage;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+
+/// Sessions idle for longer than this are evicted
+const SESSION_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone)]
+pub struct Session {
+    pub content: String,
+    pub model: String,
+    pub messages: Vec<ConversationMessage>,
+    last_used: Instant,
+}
+
+pub struct SessionStore {
+    sessions: Arc<RwLock<HashMap<String, Session>>>,
+    counter: AtomicU64,
+}
+
+impl SessionStore {
+    pub fn new() -> Self {
+        Self {
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+            counter: AtomicU64::new(1),
+        }
+    }
+
+    fn next_id(&self) -> String {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        format!("{:x}-{:x}", nanos, self.counter.fetch_add(1, Ordering::SeqCst))
+    }
+
+    fn evict_expired(sessions: &mut HashMap<String, Session>) {
+        sessions.retain(|_, s| s.last_used.elapsed() < SESSION_TTL);
+    }
+
+    pub async fn start(&self, content: String, model: String) -> String {
+        let id = self.next_id();
+        let mut sessions = self.sessions.write().await;
+        Self::evict_expired(&mut sessions);
+        sessions.insert(
+            id.clone(),
+            Session {
+                content,
+                model,
+                messages: Vec::new(),
+                last_used: Instant::now(),
+            },
+        );
+        id
+    }
+
+    /// Snapshot of a live session; the lock is not held while the caller talks to the provider
+    pub async fn get(&self, session_id: &str) -> Result<Session, String> {
+        let mut sessions = self.sessions.write().await;
+        Self::evict_expired(&mut sessions);
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| format!("Session not found or expired: {}", session_id))?;
+        session.last_used = Instant::now();
+        Ok(session.clone())
+    }
+
+    pub async fn append(&self, session_id: &str, messages: Vec<ConversationMessage>) -> Result<(), String> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| format!("Session not found or expired: {}", session_id))?;
+        session.messages.extend(messages);
+        session.last_used = Instant::now();
+        Ok(())
+    }
+
+    pub async fn end(&self, session_id: &str) {
+        self.sessions.write().await.remove(session_id);
+    }
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
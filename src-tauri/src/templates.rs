@@ -0,0 +1,214 @@
+use crate::config_file::{config_file_path, read_json, stage_json, write_json, StagedFile};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+const TEMPLATES_FILE_NAME: &str = "ainotework_templates.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub description: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub builtin: bool,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct TemplatesConfig {
+    templates: Vec<PromptTemplate>,
+}
+
+fn builtin_templates() -> Vec<PromptTemplate> {
+    vec![
+        PromptTemplate {
+            name: "action_items".to_string(),
+            description: "문서에서 실행 항목 추출".to_string(),
+            prompt: "다음 문서에서 실행해야 할 항목(Action Items)만 추출해주세요. \
+                각 항목은 담당자와 기한이 있다면 함께 표시하고, 목록 형태로 한글로 작성해주세요.\n\n{content}"
+                .to_string(),
+            builtin: true,
+        },
+        PromptTemplate {
+            name: "executive_rewrite".to_string(),
+            description: "경영진 보고용으로 재작성".to_string(),
+            prompt: "다음 내용을 경영진 보고용으로 재작성해주세요. 핵심 결론을 먼저 제시하고, \
+                배경과 근거는 간결하게 정리하며, 의사결정이 필요한 사항을 명확히 표시해주세요. \
+                반드시 한글로 작성해주세요.\n\n{content}"
+                .to_string(),
+            builtin: true,
+        },
+        PromptTemplate {
+            name: "explain_simply".to_string(),
+            description: "쉬운 말로 설명".to_string(),
+            prompt: "다음 문서를 배경 지식이 없는 사람도 이해할 수 있도록 쉬운 말로 설명해주세요. \
+                {question}\n\n반드시 한글로 작성해주세요.\n\n{content}"
+                .to_string(),
+            builtin: true,
+        },
+    ]
+}
+
+pub(crate) fn get_config_path() -> Option<PathBuf> {
+    config_file_path(TEMPLATES_FILE_NAME)
+}
+
+fn try_load_config() -> Result<TemplatesConfig, String> {
+    let path = get_config_path().ok_or("Could not determine config directory")?;
+    read_json(&path)
+}
+
+fn load_config() -> TemplatesConfig {
+    try_load_config().unwrap_or_default()
+}
+
+fn save_config(config: &TemplatesConfig) -> Result<(), String> {
+    let path = get_config_path().ok_or("Could not determine config directory")?;
+    write_json(&path, config)
+}
+
+/// Built-in templates followed by user templates; a user template with the same name overrides the built-in
+pub fn list_templates() -> Vec<PromptTemplate> {
+    let user_templates = load_config().templates;
+    let mut templates: Vec<PromptTemplate> = builtin_templates()
+        .into_iter()
+        .filter(|b| !user_templates.iter().any(|t| t.name == b.name))
+        .collect();
+    templates.extend(user_templates);
+    templates
+}
+
+pub fn get_template(name: &str) -> Option<PromptTemplate> {
+    list_templates().into_iter().find(|t| t.name == name)
+}
+
+pub fn save_template(name: String, description: String, prompt: String) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Template name must not be empty".to_string());
+    }
+    if prompt.trim().is_empty() {
+        return Err("Template prompt must not be empty".to_string());
+    }
+
+    let mut config = try_load_config()?;
+    config.templates.retain(|t| t.name != name);
+    config.templates.push(PromptTemplate {
+        name,
+        description,
+        prompt,
+        builtin: false,
+    });
+    save_config(&config)
+}
+
+pub fn delete_template(name: &str) -> Result<(), String> {
+    let mut config = try_load_config()?;
+    let before = config.templates.len();
+    config.templates.retain(|t| t.name != name);
+    if config.templates.len() == before {
+        return Err(format!("Template not found: {}", name));
+    }
+    save_config(&config)
+}
+
+/// Substitute `{content}`, `{question}` and any extra `{name}` placeholders in one pass over
+/// the template, so text coming from a value (the document or another variable) is never
+/// substituted in turn. Unknown placeholders are left as written.
+pub fn render(template: &PromptTemplate, content: &str, extra_vars: &HashMap<String, String>) -> String {
+    let mut prompt = String::with_capacity(template.prompt.len() + content.len());
+    let mut rest = template.prompt.as_str();
+
+    while let Some(open) = rest.find('{') {
+        prompt.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let name = after.find('}').map(|close| &after[..close]).filter(|name| !name.contains('{'));
+        let value = match name {
+            Some("content") => Some(content),
+            // Unfilled question placeholder is dropped rather than sent literally
+            Some(name) => extra_vars
+                .get(name)
+                .map(String::as_str)
+                .or_else(|| (name == "question").then_some("")),
+            None => None,
+        };
+        match (name, value) {
+            (Some(name), Some(value)) => {
+                prompt.push_str(value);
+                rest = &after[name.len() + 1..];
+            }
+            _ => {
+                prompt.push('{');
+                rest = after;
+            }
+        }
+    }
+    prompt.push_str(rest);
+    prompt
+}
+
+/// User templates only; built-ins ship with the app
+pub fn export_templates() -> Result<Vec<PromptTemplate>, String> {
+    Ok(try_load_config()?.templates)
+}
+
+/// Imported templates added to the user templates, replacing those with the same name, staged
+/// for `commit_all`; also returns how many were taken
+pub fn stage_import(imported: Vec<PromptTemplate>) -> Result<(StagedFile, usize), String> {
+    let mut config = try_load_config()?;
+    let mut count = 0;
+
+    for template in imported {
+        if template.name.trim().is_empty() || template.prompt.trim().is_empty() {
+            continue;
+        }
+        config.templates.retain(|t| t.name != template.name);
+        config.templates.push(PromptTemplate {
+            builtin: false,
+            ..template
+        });
+        count += 1;
+    }
+
+    let path = get_config_path().ok_or("Could not determine config directory")?;
+    Ok((stage_json(&path, &config)?, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(prompt: &str) -> PromptTemplate {
+        PromptTemplate {
+            name: "test".to_string(),
+            description: String::new(),
+            prompt: prompt.to_string(),
+            builtin: false,
+        }
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn values_are_not_substituted_again() {
+        let rendered = render(
+            &template("{a} / {b} / {content}"),
+            "문서에 {a}가 있음",
+            &vars(&[("a", "{b}"), ("b", "{a}")]),
+        );
+        assert_eq!(rendered, "{b} / {a} / 문서에 {a}가 있음");
+    }
+
+    #[test]
+    fn question_defaults_to_empty_and_unknown_placeholders_stay() {
+        let rendered = render(&template("Q: {question} {unknown} {content"), "doc", &HashMap::new());
+        assert_eq!(rendered, "Q:  {unknown} {content");
+    }
+
+    #[test]
+    fn content_cannot_be_overridden_by_a_variable() {
+        let rendered = render(&template("{content}"), "doc", &vars(&[("content", "other")]));
+        assert_eq!(rendered, "doc");
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+struct Entry<V> {
+    value: V,
+    stored: Instant,
+}
+
+/// Map whose entries expire after a fixed time; once full, the oldest entry is dropped
+/// to make room for a new key
+pub struct TtlCache<K, V> {
+    entries: Arc<RwLock<HashMap<K, Entry<V>>>>,
+    ttl: Duration,
+    capacity: usize,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            entries: Arc::new(RwLock::new(HashMap::new())),
+            ttl,
+            capacity,
+        }
+    }
+
+    pub async fn get(&self, key: &K) -> Option<V> {
+        self.entries
+            .read()
+            .await
+            .get(key)
+            .filter(|e| e.stored.elapsed() < self.ttl)
+            .map(|e| e.value.clone())
+    }
+
+    pub async fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.write().await;
+        entries.retain(|_, e| e.stored.elapsed() < self.ttl);
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, e)| e.stored)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            key,
+            Entry {
+                value,
+                stored: Instant::now(),
+            },
+        );
+    }
+
+    /// `describe` applied to each live entry with its age; expired ones are dropped on the way
+    pub async fn map_live<R>(&self, describe: impl Fn(&K, &V, Duration) -> R) -> Vec<R> {
+        let mut entries = self.entries.write().await;
+        entries.retain(|_, e| e.stored.elapsed() < self.ttl);
+        entries
+            .iter()
+            .map(|(key, e)| describe(key, &e.value, e.stored.elapsed()))
+            .collect()
+    }
+
+    /// Whether there was an entry for `key` to drop
+    pub async fn evict(&self, key: &K) -> bool {
+        self.entries.write().await.remove(key).is_some()
+    }
+
+    /// Drop every entry whose key matches, returning how many were dropped
+    pub async fn evict_where(&self, matches: impl Fn(&K) -> bool) -> usize {
+        let mut entries = self.entries.write().await;
+        let before = entries.len();
+        entries.retain(|key, _| !matches(key));
+        before - entries.len()
+    }
+
+    pub async fn clear(&self) {
+        self.entries.write().await.clear();
+    }
+}
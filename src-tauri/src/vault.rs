@@ -0,0 +1,227 @@
+use crate::config_file::{config_file_path, read_json, stage_json, StagedFile};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+const VAULT_FILE_NAME: &str = "ainotework_vault.json";
+const SEALED_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+const SALT_LEN: usize = 16;
+/// Encrypted with the derived key so a wrong passphrase is detected on unlock
+const CHECK_PLAINTEXT: &str = "ainotework-vault";
+
+/// Key derived from the master passphrase; only held in memory
+static VAULT_KEY: Mutex<Option<[u8; 32]>> = Mutex::new(None);
+
+/// Salt and verifier of the master passphrase; exported with settings that include keys, so
+/// keys that were encrypted stay encrypted in the file
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+pub struct VaultFile {
+    salt: Option<String>,
+    check: Option<String>,
+}
+
+fn load_vault() -> Result<VaultFile, String> {
+    let path = config_file_path(VAULT_FILE_NAME).ok_or("Could not determine config directory")?;
+    read_json(&path)
+}
+
+fn current_key() -> Option<[u8; 32]> {
+    *VAULT_KEY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], String> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Failed to derive key: {}", e))?;
+    Ok(key)
+}
+
+fn encrypt(key: &[u8; 32], plaintext: &str) -> Result<String, String> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| "Failed to encrypt secret".to_string())?;
+
+    let mut payload = nonce.to_vec();
+    payload.extend(ciphertext);
+    Ok(format!("{}{}", SEALED_PREFIX, STANDARD.encode(payload)))
+}
+
+fn decrypt(key: &[u8; 32], sealed: &str) -> Result<String, String> {
+    let encoded = sealed.strip_prefix(SEALED_PREFIX).ok_or("Value is not encrypted")?;
+    let payload = STANDARD
+        .decode(encoded)
+        .map_err(|_| "Encrypted value is corrupt".to_string())?;
+    if payload.len() <= NONCE_LEN {
+        return Err("Encrypted value is corrupt".to_string());
+    }
+
+    let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    // GCM authenticates, so a wrong key fails here instead of yielding garbage
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Wrong passphrase or corrupt encrypted value".to_string())?;
+    String::from_utf8(plaintext).map_err(|_| "Encrypted value is corrupt".to_string())
+}
+
+/// The saved vault, for a settings export
+pub fn export_vault() -> Result<VaultFile, String> {
+    load_vault()
+}
+
+/// Whether importing `imported` sets another passphrase than the current one (or sets one at all).
+/// Keys sealed under the current passphrase can't be read with the imported one.
+pub fn replaces_current(imported: &VaultFile) -> bool {
+    imported.salt.is_some() && load_vault().map(|current| current != *imported).unwrap_or(true)
+}
+
+/// An imported vault staged for `commit_all`; call `lock` once it is committed
+pub fn stage_imported(imported: &VaultFile) -> Result<StagedFile, String> {
+    let (Some(salt), Some(_)) = (&imported.salt, &imported.check) else {
+        return Err("Vault in the settings file is incomplete".to_string());
+    };
+    if STANDARD.decode(salt).map(|s| s.len()) != Ok(SALT_LEN) {
+        return Err("Vault in the settings file is corrupt".to_string());
+    }
+    let path = config_file_path(VAULT_FILE_NAME).ok_or("Could not determine config directory")?;
+    stage_json(&path, imported)
+}
+
+/// A secret for an imported config: the imported value, or the current one when the import has none.
+/// When the import replaces the vault, current secrets can't be decrypted any more and are dropped,
+/// and imported ones must be sealed under the imported passphrase.
+pub fn merge_imported(
+    incoming: Option<String>,
+    current: Option<String>,
+    replacing_vault: bool,
+) -> Result<Option<String>, String> {
+    if !replacing_vault {
+        return Ok(incoming.or(current));
+    }
+    match incoming {
+        Some(value) if !value.starts_with(SEALED_PREFIX) => {
+            Err("Settings file has a master passphrase but contains unencrypted keys".to_string())
+        }
+        incoming => Ok(incoming),
+    }
+}
+
+/// Forget the key in memory, e.g. after the passphrase was replaced by an import
+pub fn lock() {
+    *VAULT_KEY.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Whether a master passphrase has been set
+pub fn is_enabled() -> bool {
+    load_vault().map(|v| v.salt.is_some()).unwrap_or(false)
+}
+
+pub fn is_unlocked() -> bool {
+    current_key().is_some()
+}
+
+/// Check the passphrase against the stored verifier and keep the key in memory
+pub fn unlock(passphrase: &str) -> Result<(), String> {
+    let vault = load_vault()?;
+    let (Some(salt), Some(check)) = (vault.salt, vault.check) else {
+        return Err("No master passphrase is set".to_string());
+    };
+    let salt = STANDARD
+        .decode(salt)
+        .map_err(|_| "Vault file is corrupt".to_string())?;
+
+    let key = derive_key(passphrase, &salt)?;
+    match decrypt(&key, &check) {
+        Ok(text) if text == CHECK_PLAINTEXT => {}
+        _ => return Err("Wrong passphrase".to_string()),
+    }
+
+    *VAULT_KEY.lock().unwrap_or_else(|e| e.into_inner()) = Some(key);
+    Ok(())
+}
+
+/// Key for a new master passphrase, derived with a fresh salt but not yet in use
+pub struct NewPassphrase {
+    key: [u8; 32],
+    vault: VaultFile,
+}
+
+/// Check and derive a new passphrase. Nothing changes until the caller has staged every secret
+/// sealed under it, committed those files together with `stage_vault`, and called `activate`.
+pub fn prepare_passphrase(passphrase: &str) -> Result<NewPassphrase, String> {
+    if passphrase.chars().count() < 8 {
+        return Err("Passphrase must be at least 8 characters".to_string());
+    }
+    if is_enabled() && !is_unlocked() {
+        return Err("Unlock with the current passphrase first".to_string());
+    }
+
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = derive_key(passphrase, &salt)?;
+
+    Ok(NewPassphrase {
+        vault: VaultFile {
+            salt: Some(STANDARD.encode(salt)),
+            check: Some(encrypt(&key, CHECK_PLAINTEXT)?),
+        },
+        key,
+    })
+}
+
+impl NewPassphrase {
+    /// Encrypt a plaintext secret (as returned by `reveal`) under the new key
+    pub fn seal(&self, value: Option<String>) -> Result<Option<String>, String> {
+        value.map(|value| encrypt(&self.key, &value)).transpose()
+    }
+
+    /// The vault file for the new passphrase, to be committed with the re-sealed configs
+    pub fn stage_vault(&self) -> Result<StagedFile, String> {
+        let path = config_file_path(VAULT_FILE_NAME).ok_or("Could not determine config directory")?;
+        stage_json(&path, &self.vault)
+    }
+
+    /// Make the new key current, once everything sealed under it is on disk
+    pub fn activate(self) {
+        *VAULT_KEY.lock().unwrap_or_else(|e| e.into_inner()) = Some(self.key);
+    }
+}
+
+/// Encrypt a secret for writing to disk.
+/// Values that are already encrypted are kept as-is; without a passphrase the value stays plaintext.
+pub fn seal(value: Option<String>) -> Result<Option<String>, String> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    if value.starts_with(SEALED_PREFIX) {
+        return Ok(Some(value));
+    }
+
+    match current_key() {
+        Some(key) => encrypt(&key, &value).map(Some),
+        None if is_enabled() => Err("Unlock with the master passphrase before saving keys".to_string()),
+        None => Ok(Some(value)),
+    }
+}
+
+/// Plaintext of a stored secret; encrypted values need the vault to be unlocked
+pub fn reveal(value: Option<String>) -> Result<Option<String>, String> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    if !value.starts_with(SEALED_PREFIX) {
+        return Ok(Some(value));
+    }
+
+    let key = current_key().ok_or("Settings are locked; unlock with the master passphrase")?;
+    decrypt(&key, &value).map(Some)
+}
@@ -1,27 +1,356 @@
-use reqwest::Client;
-use scraper::{Html, Selector};
+use crate::config_file::{config_file_path, read_json, stage_json, StagedFile};
+use crate::content_cache::{url_host, ContentCache};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Response};
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+const WEB_CONFIG_FILE_NAME: &str = "ainotework_web_config.json";
+const DEFAULT_FETCH_RETRIES: u32 = 2;
+const MAX_FETCH_RETRIES: u32 = 5;
+const DEFAULT_FETCH_TIMEOUT_SECS: u64 = 30;
+
+#[derive(Serialize, Deserialize, Default)]
+pub(crate) struct WebConfig {
+    fetch_retries: Option<u32>,
+    fetch_timeout_secs: Option<u64>,
+    /// Tell the model that documents asked about are data, not instructions; on unless turned off
+    guard_fetched_content: Option<bool>,
+    /// Also drop document lines with a blatant injection phrase; off unless turned on,
+    /// as it can remove legitimate lines (e.g. an article quoting such a phrase)
+    strip_injection_lines: Option<bool>,
+    /// Replaces `DEFAULT_CONTENT_SELECTORS` for every site
+    content_selectors: Option<Vec<String>>,
+    /// Selectors for specific hosts ("docs.example.com"), tried before the general list
+    host_content_selectors: Option<HashMap<String, Vec<String>>>,
+}
+
+/// Where the main content usually lives, tried in order; the body is the fallback
+const DEFAULT_CONTENT_SELECTORS: [&str; 8] = [
+    "article",
+    "main",
+    "[role='main']",
+    ".content",
+    ".post-content",
+    ".article-content",
+    ".entry-content",
+    "#content",
+];
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ContentSelectors {
+    /// The general list in use: the configured one, or the built-in defaults
+    pub selectors: Vec<String>,
+    pub host_overrides: HashMap<String, Vec<String>>,
+}
+
+/// Extracted text shorter than this is probably a stub rather than the page
+const MIN_CONTENT_CHARS: usize = 200;
+/// Phrases of cookie walls, JavaScript stubs and bot checks, compared lowercased
+const STUB_PHRASES: [&str; 10] = [
+    "enable javascript",
+    "javascript is disabled",
+    "javascript is required",
+    "accept cookies",
+    "cookie settings",
+    "are you a robot",
+    "verify you are human",
+    "자바스크립트를 활성화",
+    "javascript를 활성화",
+    "쿠키 사용에 동의",
+];
+/// Share of the text in stub-phrase lines above which the page counts as a stub
+const MAX_STUB_SHARE: f64 = 0.3;
+/// Share of the page text inside links above which it is probably navigation, not content
+const MAX_LINK_TEXT_SHARE: f64 = 0.5;
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ContentQuality {
+    /// Whether the content is likely not the real page (a stub, a cookie wall or mostly links)
+    pub suspicious: bool,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CheckedContent {
+    pub content: String,
+    pub quality: ContentQuality,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct FeedEntry {
+    pub title: String,
+    pub link: String,
+    pub published: Option<String>,
+}
 
 pub struct WebClient {
     client: Client,
+    fetch_retries: AtomicU32,
+    fetch_timeout_secs: AtomicU64,
+    guard_fetched_content: AtomicBool,
+    strip_injection_lines: AtomicBool,
+    /// Shared by web pages and Notion pages fetched for the commands
+    content_cache: ContentCache,
 }
 
 impl WebClient {
     pub fn new() -> Self {
+        let config = Self::load_config();
+
         Self {
             client: Client::builder()
                 .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36")
                 .build()
                 .unwrap_or_else(|_| Client::new()),
+            fetch_retries: AtomicU32::new(
+                config
+                    .fetch_retries
+                    .unwrap_or(DEFAULT_FETCH_RETRIES)
+                    .min(MAX_FETCH_RETRIES),
+            ),
+            fetch_timeout_secs: AtomicU64::new(
+                config.fetch_timeout_secs.unwrap_or(DEFAULT_FETCH_TIMEOUT_SECS),
+            ),
+            guard_fetched_content: AtomicBool::new(config.guard_fetched_content.unwrap_or(true)),
+            strip_injection_lines: AtomicBool::new(config.strip_injection_lines.unwrap_or(false)),
+            content_cache: ContentCache::new(),
+        }
+    }
+
+    pub(crate) fn get_config_path() -> Option<PathBuf> {
+        config_file_path(WEB_CONFIG_FILE_NAME)
+    }
+
+    fn try_load_config() -> Result<WebConfig, String> {
+        let path = Self::get_config_path().ok_or("Could not determine config directory")?;
+        read_json(&path)
+    }
+
+    /// Defaults when the file can't be read; updates go through `try_load_config` so a bad file is never overwritten
+    fn load_config() -> WebConfig {
+        Self::try_load_config().unwrap_or_default()
+    }
+
+    fn save_config(config: &WebConfig) -> Result<(), String> {
+        Self::stage_config(config)?.commit()
+    }
+
+    fn stage_config(config: &WebConfig) -> Result<StagedFile, String> {
+        let path = Self::get_config_path().ok_or("Could not determine config directory")?;
+        stage_json(&path, config)
+    }
+
+    pub(crate) fn export_settings() -> Result<WebConfig, String> {
+        Self::try_load_config()
+    }
+
+    /// Exported settings merged into the saved ones, staged for `commit_all`; values missing
+    /// from the import are kept
+    pub(crate) fn stage_import(incoming: WebConfig) -> Result<StagedFile, String> {
+        let mut config = Self::try_load_config()?;
+        config.fetch_retries = incoming.fetch_retries.or(config.fetch_retries);
+        config.fetch_timeout_secs = incoming.fetch_timeout_secs.or(config.fetch_timeout_secs);
+        config.guard_fetched_content = incoming.guard_fetched_content.or(config.guard_fetched_content);
+        config.strip_injection_lines = incoming.strip_injection_lines.or(config.strip_injection_lines);
+        config.content_selectors = incoming.content_selectors.or(config.content_selectors);
+        if let Some(imported) = incoming.host_content_selectors {
+            config
+                .host_content_selectors
+                .get_or_insert_with(HashMap::new)
+                .extend(imported);
+        }
+        Self::stage_config(&config)
+    }
+
+    /// (retries, timeout in seconds) for page and feed fetches
+    pub fn get_fetch_settings(&self) -> (u32, u64) {
+        (
+            self.fetch_retries.load(Ordering::SeqCst),
+            self.fetch_timeout_secs.load(Ordering::SeqCst),
+        )
+    }
+
+    pub fn set_fetch_settings(&self, retries: u32, timeout_secs: u64) -> Result<(), String> {
+        if retries > MAX_FETCH_RETRIES {
+            return Err(format!("Retries must be at most {}", MAX_FETCH_RETRIES));
+        }
+        if !(1..=300).contains(&timeout_secs) {
+            return Err("Fetch timeout must be between 1 and 300 seconds".to_string());
+        }
+        self.fetch_retries.store(retries, Ordering::SeqCst);
+        self.fetch_timeout_secs.store(timeout_secs, Ordering::SeqCst);
+
+        let mut config = Self::try_load_config()?;
+        config.fetch_retries = Some(retries);
+        config.fetch_timeout_secs = Some(timeout_secs);
+        Self::save_config(&config)
+    }
+
+    pub fn content_cache(&self) -> &ContentCache {
+        &self.content_cache
+    }
+
+    pub fn get_content_selectors() -> ContentSelectors {
+        let config = Self::load_config();
+        ContentSelectors {
+            selectors: config
+                .content_selectors
+                .unwrap_or_else(|| DEFAULT_CONTENT_SELECTORS.iter().map(|s| s.to_string()).collect()),
+            host_overrides: config.host_content_selectors.unwrap_or_default(),
+        }
+    }
+
+    /// Set the selectors for `host`, or the general list without one. An empty list removes
+    /// the host override, or restores the built-in defaults for the general list. Cached
+    /// content extracted with the old selectors is dropped: that host's pages, or every page
+    /// for the general list.
+    pub async fn set_content_selectors(&self, host: Option<&str>, selectors: Vec<String>) -> Result<(), String> {
+        let selectors: Vec<String> = selectors
+            .iter()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        for selector in &selectors {
+            Selector::parse(selector).map_err(|_| format!("Invalid CSS selector: {}", selector))?;
+        }
+
+        let host = host.map(|h| h.trim().trim_start_matches("www.").to_lowercase());
+        let mut config = Self::try_load_config()?;
+        match &host {
+            Some(host) if host.is_empty() => return Err("Host must not be empty".to_string()),
+            Some(host) => {
+                let overrides = config.host_content_selectors.get_or_insert_with(HashMap::new);
+                if selectors.is_empty() {
+                    overrides.remove(host);
+                } else {
+                    overrides.insert(host.clone(), selectors);
+                }
+            }
+            None => config.content_selectors = (!selectors.is_empty()).then_some(selectors),
+        }
+        Self::save_config(&config)?;
+
+        match host {
+            Some(host) => {
+                self.content_cache.evict_host(&host).await;
+            }
+            None => self.content_cache.clear().await,
+        }
+        Ok(())
+    }
+
+    /// Selectors to try for `url`: its host's override first, then the general list
+    fn content_selectors_for(url: &str) -> Vec<String> {
+        let mut config = Self::load_config();
+        let host = url_host(url);
+
+        let mut selectors = host
+            .and_then(|host| config.host_content_selectors.as_mut()?.remove(&host))
+            .unwrap_or_default();
+        selectors.extend(
+            config
+                .content_selectors
+                .unwrap_or_else(|| DEFAULT_CONTENT_SELECTORS.iter().map(|s| s.to_string()).collect()),
+        );
+        selectors
+    }
+
+    pub fn get_guard_fetched_content(&self) -> bool {
+        self.guard_fetched_content.load(Ordering::SeqCst)
+    }
+
+    pub fn set_guard_fetched_content(&self, enabled: bool) -> Result<(), String> {
+        self.guard_fetched_content.store(enabled, Ordering::SeqCst);
+
+        let mut config = Self::try_load_config()?;
+        config.guard_fetched_content = Some(enabled);
+        Self::save_config(&config)
+    }
+
+    pub fn get_strip_injection_lines(&self) -> bool {
+        self.strip_injection_lines.load(Ordering::SeqCst)
+    }
+
+    pub fn set_strip_injection_lines(&self, enabled: bool) -> Result<(), String> {
+        self.strip_injection_lines.store(enabled, Ordering::SeqCst);
+
+        let mut config = Self::try_load_config()?;
+        config.strip_injection_lines = Some(enabled);
+        Self::save_config(&config)
+    }
+
+    /// GET with retry and exponential backoff on connection errors, timeouts and 5xx.
+    /// Other statuses (e.g. 404) are returned immediately for the caller to report.
+    async fn get_with_retry(&self, url: &str, headers: &HeaderMap) -> Result<Response, String> {
+        let (retries, timeout_secs) = self.get_fetch_settings();
+        let mut attempt = 0;
+
+        loop {
+            let result = self
+                .client
+                .get(url)
+                .headers(headers.clone())
+                .timeout(Duration::from_secs(timeout_secs))
+                .send()
+                .await;
+
+            let retryable = match &result {
+                Ok(response) => response.status().is_server_error(),
+                Err(e) => e.is_connect() || e.is_timeout(),
+            };
+            if !retryable || attempt >= retries {
+                return result.map_err(|e| format!("Failed to fetch {}: {}", url, e));
+            }
+
+            tokio::time::sleep(Duration::from_millis(500 * 2u64.pow(attempt))).await;
+            attempt += 1;
         }
     }
 
     pub async fn fetch_page(&self, url: &str) -> Result<String, String> {
-        let response = self
-            .client
-            .get(url)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to fetch page: {}", e))?;
+        self.fetch_page_with_headers(url, &HashMap::new()).await
+    }
+
+    /// `fetch_page` plus a quality check of what came back, including the page's link density
+    pub async fn fetch_page_checked(&self, url: &str) -> Result<CheckedContent, String> {
+        let (content, html) = self.fetch_page_and_html(url, &HeaderMap::new()).await?;
+        let quality = Self::check_quality(&content, html.as_deref());
+        Ok(CheckedContent { content, quality })
+    }
+
+    /// Per-request headers (e.g. `Authorization` for gated wikis); they are never stored,
+    /// only sent to `url` itself, and left out of error messages.
+    pub async fn fetch_page_with_headers(
+        &self,
+        url: &str,
+        headers: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        let headers = Self::build_headers(headers)?;
+        let (content, _) = self.fetch_page_and_html(url, &headers).await?;
+        Ok(content)
+    }
+
+    /// Extracted text, and the HTML it came from when it was a plain page
+    async fn fetch_page_and_html(&self, url: &str, headers: &HeaderMap) -> Result<(String, Option<String>), String> {
+        // Video pages have no readable text; use the captions instead
+        if let Some(video_id) = Self::youtube_video_id(url) {
+            return Ok((self.fetch_youtube_transcript(&video_id).await?, None));
+        }
+
+        // Repository pages are mostly UI chrome; the README is the useful part
+        if let Some(raw_base) = Self::repository_raw_base(url) {
+            if let Some(readme) = self.fetch_readme(&raw_base).await {
+                return Ok((readme, None));
+            }
+        }
+
+        let response = self.get_with_retry(url, headers).await?;
 
         if !response.status().is_success() {
             return Err(format!("HTTP error: {}", response.status()));
@@ -32,10 +361,171 @@ impl WebClient {
             .await
             .map_err(|e| format!("Failed to read response: {}", e))?;
 
-        Ok(self.extract_text(&html))
+        Ok((self.extract_text(&html, &Self::content_selectors_for(url)), Some(html)))
     }
 
-    fn extract_text(&self, html: &str) -> String {
+    /// Raw HTML of a page, for callers that need its structure rather than its text
+    pub async fn fetch_html(&self, url: &str) -> Result<String, String> {
+        let response = self.get_with_retry(url, &HeaderMap::new()).await?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP error: {}", response.status()));
+        }
+        response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read response: {}", e))
+    }
+
+    /// Every `<table>` in the page as (caption, rows of cell text); header cells are kept as the first row.
+    /// A table nested in a cell is a table of its own; its rows are not repeated in the outer one.
+    pub fn html_tables(html: &str) -> Vec<(Option<String>, Vec<Vec<String>>)> {
+        let document = Html::parse_document(html);
+        let (Ok(table_selector), Ok(caption_selector), Ok(row_selector), Ok(cell_selector)) = (
+            Selector::parse("table"),
+            Selector::parse("caption"),
+            Selector::parse("tr"),
+            Selector::parse("th, td"),
+        ) else {
+            return Vec::new();
+        };
+
+        document
+            .select(&table_selector)
+            .filter_map(|table| {
+                let caption = table
+                    .select(&caption_selector)
+                    .find(|c| Self::is_nearest(c, "table", &table))
+                    .map(|c| Self::extract_element_text(&c))
+                    .filter(|c| !c.is_empty());
+                let rows: Vec<Vec<String>> = table
+                    .select(&row_selector)
+                    .filter(|row| Self::is_nearest(row, "table", &table))
+                    .map(|row| {
+                        row.select(&cell_selector)
+                            .filter(|cell| Self::is_nearest(cell, "tr", &row))
+                            .map(|cell| Self::extract_element_text(&cell))
+                            .collect::<Vec<_>>()
+                    })
+                    .filter(|cells| !cells.is_empty())
+                    .collect();
+                (!rows.is_empty()).then_some((caption, rows))
+            })
+            .collect()
+    }
+
+    /// Whether `outer` is the closest `tag` element around `element`
+    fn is_nearest(element: &ElementRef, tag: &str, outer: &ElementRef) -> bool {
+        element
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .find(|ancestor| ancestor.value().name() == tag)
+            .is_some_and(|ancestor| ancestor.id() == outer.id())
+    }
+
+    /// Heuristics for content that is probably not the page the user wanted: very little text,
+    /// text dominated by cookie/JavaScript/bot-check phrases, or (with the HTML) mostly link text
+    pub fn check_quality(content: &str, html: Option<&str>) -> ContentQuality {
+        let mut warnings = Vec::new();
+
+        let chars = content.chars().count();
+        if chars < MIN_CONTENT_CHARS {
+            warnings.push(format!("Very little text was extracted ({} characters)", chars));
+        }
+
+        let stub_chars: usize = content
+            .lines()
+            .filter(|line| {
+                let lower = line.to_lowercase();
+                STUB_PHRASES.iter().any(|phrase| lower.contains(phrase))
+            })
+            .map(|line| line.chars().count())
+            .sum();
+        if chars > 0 && stub_chars as f64 / chars as f64 > MAX_STUB_SHARE {
+            warnings.push("The text looks like a cookie notice, JavaScript notice or bot check".to_string());
+        }
+
+        if let Some(html) = html {
+            let document = Html::parse_document(html);
+            let text_len = |selector: &str| -> usize {
+                Selector::parse(selector)
+                    .map(|s| {
+                        document
+                            .select(&s)
+                            .flat_map(|e| e.text())
+                            .map(|t| t.trim().chars().count())
+                            .sum()
+                    })
+                    .unwrap_or(0)
+            };
+            let (body, links) = (text_len("body"), text_len("body a"));
+            if body > 0 && links as f64 / body as f64 > MAX_LINK_TEXT_SHARE {
+                warnings.push("Most of the page text is links; it may be a navigation or index page".to_string());
+            }
+        }
+
+        ContentQuality {
+            suspicious: !warnings.is_empty(),
+            warnings,
+        }
+    }
+
+    fn build_headers(headers: &HashMap<String, String>) -> Result<HeaderMap, String> {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            let header_name = HeaderName::from_bytes(name.trim().as_bytes())
+                .map_err(|_| format!("Invalid header name: {}", name))?;
+            // Values are credentials, so they are not echoed back in the error
+            let mut header_value = HeaderValue::from_str(value.trim())
+                .map_err(|_| format!("Invalid value for header {}", name))?;
+            header_value.set_sensitive(true);
+            map.insert(header_name, header_value);
+        }
+        Ok(map)
+    }
+
+    /// Entries of an RSS/Atom feed, newest first
+    pub async fn fetch_feed(&self, url: &str) -> Result<Vec<FeedEntry>, String> {
+        let response = self.get_with_retry(url, &HeaderMap::new()).await?;
+
+        if !response.status().is_success() {
+            return Err(format!("HTTP error: {}", response.status()));
+        }
+
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| format!("Failed to read response: {}", e))?;
+
+        let feed = feed_rs::parser::parse(&bytes[..])
+            .map_err(|e| format!("Failed to parse feed: {}", e))?;
+
+        let mut entries: Vec<(Option<i64>, FeedEntry)> = feed
+            .entries
+            .into_iter()
+            .filter_map(|entry| {
+                let link = entry.links.first()?.href.clone();
+                let date = entry.published.or(entry.updated);
+                Some((
+                    date.map(|d| d.timestamp()),
+                    FeedEntry {
+                        title: entry
+                            .title
+                            .map(|t| t.content.trim().to_string())
+                            .unwrap_or_else(|| "Untitled".to_string()),
+                        link,
+                        published: date.map(|d| d.to_rfc3339()),
+                    },
+                ))
+            })
+            .collect();
+
+        // Feeds are usually newest-first already, but not all of them
+        entries.sort_by_key(|(date, _)| std::cmp::Reverse(*date));
+
+        Ok(entries.into_iter().map(|(_, entry)| entry).collect())
+    }
+
+    fn extract_text(&self, html: &str, content_selectors: &[String]) -> String {
         let document = Html::parse_document(html);
         let mut text_parts: Vec<String> = Vec::new();
 
@@ -46,17 +536,6 @@ impl WebClient {
             }
         }
 
-        let content_selectors = [
-            "article",
-            "main",
-            "[role='main']",
-            ".content",
-            ".post-content",
-            ".article-content",
-            ".entry-content",
-            "#content",
-        ];
-
         let mut found_content = false;
         for selector_str in content_selectors {
             if let Ok(selector) = Selector::parse(selector_str) {
@@ -84,6 +563,229 @@ impl WebClient {
         text_parts.join("\n").trim().to_string()
     }
 
+    /// Raw-content URL prefix for the default branch of a github.com / gitlab.com repository root
+    fn repository_raw_base(url: &str) -> Option<String> {
+        let parsed = reqwest::Url::parse(url.trim()).ok()?;
+        let host = parsed.host_str()?.trim_start_matches("www.");
+        let segments: Vec<&str> = parsed
+            .path_segments()?
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        match host {
+            "github.com" => {
+                // Only the repository root, not issues, files or other branches
+                const RESERVED: [&str; 10] = [
+                    "orgs", "settings", "topics", "marketplace", "explore", "features",
+                    "login", "search", "sponsors", "collections",
+                ];
+                let (owner, repo) = (segments.first()?, segments.get(1)?);
+                if RESERVED.contains(owner) || segments.len() != 2 {
+                    return None;
+                }
+                let repo = repo.trim_end_matches(".git");
+                Some(format!("https://raw.githubusercontent.com/{}/{}/HEAD", owner, repo))
+            }
+            "gitlab.com" => {
+                // Group paths can be nested; GitLab puts sub-pages after a "-" segment
+                let project: Vec<&str> = segments.iter().take_while(|s| **s != "-").copied().collect();
+                if project.len() < 2 || project.len() != segments.len() {
+                    return None;
+                }
+                let path = project.join("/");
+                Some(format!(
+                    "https://gitlab.com/{}/-/raw/HEAD",
+                    path.trim_end_matches(".git")
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// First README found at the repository root, or `None` to fall back to scraping
+    async fn fetch_readme(&self, raw_base: &str) -> Option<String> {
+        const README_NAMES: [&str; 6] = [
+            "README.md",
+            "README.rst",
+            "README.markdown",
+            "README.txt",
+            "README",
+            "readme.md",
+        ];
+
+        for name in README_NAMES {
+            let response = match self.client.get(format!("{}/{}", raw_base, name)).send().await {
+                Ok(response) if response.status().is_success() => response,
+                _ => continue,
+            };
+            if let Ok(text) = response.text().await {
+                if !text.trim().is_empty() {
+                    return Some(text);
+                }
+            }
+        }
+        None
+    }
+
+    /// Video id from youtube.com/watch, youtu.be, /shorts/ and /embed/ URLs
+    fn youtube_video_id(url: &str) -> Option<String> {
+        let parsed = reqwest::Url::parse(url.trim()).ok()?;
+        let host = parsed.host_str()?.trim_start_matches("www.").trim_start_matches("m.");
+
+        let id = match host {
+            "youtu.be" => parsed.path_segments()?.next().map(|s| s.to_string()),
+            "youtube.com" | "music.youtube.com" => {
+                let mut segments = parsed.path_segments()?;
+                match segments.next() {
+                    Some("watch") => parsed
+                        .query_pairs()
+                        .find(|(k, _)| k == "v")
+                        .map(|(_, v)| v.to_string()),
+                    Some("shorts") | Some("embed") | Some("live") => segments.next().map(|s| s.to_string()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }?;
+
+        let valid = !id.is_empty()
+            && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        valid.then_some(id)
+    }
+
+    /// The JSON array starting at `start` (which must point at `[`), honouring strings and escapes
+    pub(crate) fn extract_json_array(text: &str, start: usize) -> Option<&str> {
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+
+        for (offset, c) in text[start..].char_indices() {
+            if in_string {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => in_string = true,
+                '[' => depth += 1,
+                ']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(&text[start..start + offset + 1]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    async fn fetch_youtube_transcript(&self, video_id: &str) -> Result<String, String> {
+        let watch_url = format!("https://www.youtube.com/watch?v={}&hl=ko", video_id);
+        let response = self
+            .client
+            .get(&watch_url)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to fetch video page: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("HTTP error: {}", response.status()));
+        }
+
+        let html = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read response: {}", e))?;
+
+        let tracks: Vec<Value> = html
+            .find("\"captionTracks\":")
+            .and_then(|pos| html[pos..].find('[').map(|offset| pos + offset))
+            .and_then(|start| Self::extract_json_array(&html, start))
+            .and_then(|array| serde_json::from_str(array).ok())
+            .unwrap_or_default();
+
+        if tracks.is_empty() {
+            return Err("이 동영상에는 자막이 없어 내용을 가져올 수 없습니다.".to_string());
+        }
+
+        // Prefer manual captions over auto-generated ones, and Korean/English over others
+        let track_rank = |track: &Value| {
+            let auto_generated = track.get("kind").and_then(|k| k.as_str()) == Some("asr");
+            let language = track.get("languageCode").and_then(|l| l.as_str()).unwrap_or("");
+            let language_rank = match language {
+                "ko" => 0,
+                "en" => 1,
+                _ => 2,
+            };
+            (auto_generated, language_rank)
+        };
+        let track = tracks
+            .iter()
+            .min_by_key(|t| track_rank(t))
+            .ok_or("No caption track found")?;
+
+        let base_url = track
+            .get("baseUrl")
+            .and_then(|u| u.as_str())
+            .ok_or("Caption track has no URL")?;
+
+        let captions = self
+            .client
+            .get(base_url)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to fetch captions: {}", e))?
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read captions: {}", e))?;
+
+        // Timed-text XML: <transcript><text start=".." dur="..">line</text>...</transcript>
+        let fragment = Html::parse_fragment(&captions);
+        let lines: Vec<String> = match Selector::parse("text") {
+            Ok(selector) => fragment
+                .select(&selector)
+                .map(|el| {
+                    // Caption text is entity-encoded twice, so one layer survives HTML parsing
+                    el.text()
+                        .collect::<String>()
+                        .replace("&#39;", "'")
+                        .replace("&quot;", "\"")
+                        .replace("&amp;", "&")
+                        .replace('\n', " ")
+                        .trim()
+                        .to_string()
+                })
+                .filter(|line| !line.is_empty())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+
+        if lines.is_empty() {
+            return Err("자막 내용이 비어 있습니다.".to_string());
+        }
+
+        let mut text_parts: Vec<String> = Vec::new();
+        let document = Html::parse_document(&html);
+        if let Ok(title_selector) = Selector::parse("title") {
+            if let Some(title) = document.select(&title_selector).next() {
+                let title = title.text().collect::<String>();
+                let title = title.trim().trim_end_matches("- YouTube").trim();
+                if !title.is_empty() {
+                    text_parts.push(format!("Title: {}", title));
+                    text_parts.push(String::new());
+                }
+            }
+        }
+        text_parts.push(lines.join("\n"));
+
+        Ok(text_parts.join("\n"))
+    }
+
     fn extract_element_text(element: &scraper::ElementRef) -> String {
         let mut texts: Vec<String> = Vec::new();
 
@@ -103,3 +805,23 @@ impl Default for WebClient {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_tables_keep_their_rows_to_themselves() {
+        let html = "<table><caption>Outer</caption>\
+            <tr><th>Name</th><th>Detail</th></tr>\
+            <tr><td>a</td><td><table><caption>Inner</caption><tr><td>x</td><td>y</td></tr></table></td></tr>\
+            </table>";
+        let tables = WebClient::html_tables(html);
+        assert_eq!(tables.len(), 2);
+        let (caption, rows) = &tables[0];
+        assert_eq!(caption.as_deref(), Some("Outer"));
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].len(), 2);
+        assert_eq!(tables[1], (Some("Inner".to_string()), vec![vec!["x".to_string(), "y".to_string()]]));
+    }
+}